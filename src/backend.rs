@@ -1,10 +1,78 @@
 use anyhow::{Context, Result};
+use keyring::credential::CredentialPersistence;
 use secrecy::{ExposeSecret, SecretString};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
 
 pub trait SecretBackend {
     fn store(&mut self, key: &str, value: &SecretString) -> Result<()>;
     fn retrieve(&self, key: &str) -> Result<Option<SecretString>>;
     fn delete(&mut self, key: &str) -> Result<bool>; // returns true if existed
+    fn name(&self) -> &'static str;
+    /// Backend of the same kind scoped to another namespace
+    fn namespaced(&self, namespace: &str) -> Box<dyn SecretBackend>;
+    /// Where `key` lives (or would live) in the backend; never includes the value
+    fn locate(&self, key: &str) -> Result<EntryLocation>;
+    /// Attempts each primitive operation on `key`'s entry separately, for `debug entry`
+    fn diagnose(&self, key: &str) -> Result<EntryDiagnosis>;
+}
+
+/// Identifying details of a backend entry, for auditing with `where`
+pub struct EntryLocation {
+    /// Keyring service name (`local-secrets` or `local-secrets:<namespace>`)
+    pub service: String,
+    /// Keyring account (user) field; the variable name
+    pub account: String,
+    /// Credential store the keyring crate was built with and how long it keeps entries
+    pub store: &'static str,
+    /// Whether the entry currently exists
+    pub exists: bool,
+    /// Platform attributes reported by the store, sorted by name
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Result of `debug entry`: the identifiers used and what each primitive did
+pub struct EntryDiagnosis {
+    /// Keyring service name
+    pub service: String,
+    /// Keyring account (user) field
+    pub account: String,
+    /// Platform target; the keyring crate derives it from service and account
+    pub target: &'static str,
+    /// Credential store the keyring crate was built with
+    pub store: &'static str,
+    /// Each primitive in the order attempted
+    pub steps: Vec<DiagnosticStep>,
+}
+
+/// One primitive operation attempted by `debug entry`
+pub struct DiagnosticStep {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    /// What happened, or the raw platform error; never the secret itself
+    pub outcome: std::result::Result<String, String>,
+}
+
+/// Where secrets are read from and written to (`--backend`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackendKind {
+    /// The OS keyring
+    Keyring,
+    /// Files in `$CREDENTIALS_DIRECTORY` provided by a systemd unit (read-only)
+    SystemdCreds,
+}
+
+impl BackendKind {
+    pub fn open(self) -> Box<dyn SecretBackend> {
+        match self {
+            BackendKind::Keyring => Box::new(KeyringBackend::new()),
+            BackendKind::SystemdCreds => Box::new(SystemdCredsBackend::new()),
+        }
+    }
 }
 
 pub struct KeyringBackend {
@@ -17,6 +85,13 @@ impl KeyringBackend {
             service: "local-secrets".to_string(),
         }
     }
+
+    /// Keyring backend whose entries live under `local-secrets:<namespace>`
+    pub fn with_namespace(namespace: &str) -> Self {
+        Self {
+            service: format!("local-secrets:{}", namespace),
+        }
+    }
 }
 
 impl SecretBackend for KeyringBackend {
@@ -66,4 +141,299 @@ impl SecretBackend for KeyringBackend {
             Err(err) => Err(err).context("Failed to delete secret from keyring")?,
         }
     }
+
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn namespaced(&self, namespace: &str) -> Box<dyn SecretBackend> {
+        Box::new(KeyringBackend::with_namespace(namespace))
+    }
+
+    fn locate(&self, key: &str) -> Result<EntryLocation> {
+        // Defensive: Validate input before proceeding
+        if key.trim().is_empty() {
+            return Err(anyhow::anyhow!("Key cannot be empty"));
+        }
+
+        let entry =
+            keyring::Entry::new(&self.service, key).context("Failed to create keyring entry")?;
+        let (exists, attributes) = match entry.get_attributes() {
+            Ok(attributes) => (true, attributes),
+            Err(keyring::Error::NoEntry) => (false, Default::default()),
+            Err(err) => Err(err).context("Failed to read keyring entry")?,
+        };
+        let mut attributes: Vec<(String, String)> = attributes.into_iter().collect();
+        attributes.sort();
+
+        Ok(EntryLocation {
+            service: self.service.clone(),
+            account: key.to_string(),
+            store: store_description(),
+            exists,
+            attributes,
+        })
+    }
+
+    fn diagnose(&self, key: &str) -> Result<EntryDiagnosis> {
+        // Defensive: Validate input before proceeding
+        if key.trim().is_empty() {
+            return Err(anyhow::anyhow!("Key cannot be empty"));
+        }
+
+        let mut steps = Vec::new();
+        let started = Instant::now();
+        let entry = keyring::Entry::new(&self.service, key);
+        steps.push(DiagnosticStep {
+            name: "create-entry",
+            elapsed: started.elapsed(),
+            outcome: entry.as_ref().map(|_| "ok".to_string()).map_err(raw_error),
+        });
+
+        // Each primitive runs once, with no retries, so the platform error surfaces as is
+        if let Ok(entry) = entry {
+            let started = Instant::now();
+            let outcome = match entry.get_attributes() {
+                Ok(attributes) => Ok(format!("yes ({} attributes)", attributes.len())),
+                Err(keyring::Error::NoEntry) => Ok("no".to_string()),
+                Err(err) => Err(raw_error(&err)),
+            };
+            steps.push(DiagnosticStep {
+                name: "exists",
+                elapsed: started.elapsed(),
+                outcome,
+            });
+
+            let started = Instant::now();
+            let outcome = match entry.get_secret() {
+                Ok(mut secret) => {
+                    let length = secret.len();
+                    secret.zeroize(); // Only the length is reported
+                    Ok(format!("{} bytes", length))
+                }
+                Err(err) => Err(raw_error(&err)),
+            };
+            steps.push(DiagnosticStep {
+                name: "retrieve-length",
+                elapsed: started.elapsed(),
+                outcome,
+            });
+
+            // Updating with no attributes touches the entry's metadata without changing it
+            let started = Instant::now();
+            let outcome = entry
+                .update_attributes(&HashMap::new())
+                .map(|()| "ok".to_string())
+                .map_err(|err| raw_error(&err));
+            steps.push(DiagnosticStep {
+                name: "touch-metadata",
+                elapsed: started.elapsed(),
+                outcome,
+            });
+        }
+
+        Ok(EntryDiagnosis {
+            service: self.service.clone(),
+            account: key.to_string(),
+            target: "platform default for service and account",
+            store: store_description(),
+            steps,
+        })
+    }
+}
+
+/// Directory systemd fills from a unit's `LoadCredential=`/`SetCredential=` settings
+pub const CREDENTIALS_DIRECTORY_VAR: &str = "CREDENTIALS_DIRECTORY";
+
+/// Largest credential file read, matching the limit on secret values
+const MAX_CREDENTIAL_BYTES: u64 = crate::security::MAX_SECRET_BYTES as u64;
+
+const SYSTEMD_CREDS_STORE: &str = "systemd credentials (read-only files)";
+
+/// Read-only backend resolving `KEY` to `$CREDENTIALS_DIRECTORY/KEY` (`--backend systemd-creds`)
+pub struct SystemdCredsBackend {
+    /// Namespaces have no systemd counterpart; kept only to reject them with a clear error
+    namespace: Option<String>,
+}
+
+impl SystemdCredsBackend {
+    pub fn new() -> Self {
+        Self { namespace: None }
+    }
+
+    /// Path of `key`'s credential file, after checking the directory and the name
+    fn credential_path(&self, key: &str) -> Result<PathBuf> {
+        if let Some(namespace) = &self.namespace {
+            return Err(anyhow::anyhow!(
+                "The systemd-creds backend has no namespaces (requested '{}')",
+                namespace
+            ));
+        }
+        // A credential name is a single file name inside the directory
+        if key.trim().is_empty() || key.contains(['/', '\\']) || key == "." || key == ".." {
+            return Err(anyhow::anyhow!("Invalid credential name"));
+        }
+        let directory = match std::env::var_os(CREDENTIALS_DIRECTORY_VAR) {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} is not set; the systemd-creds backend only works inside a unit with LoadCredential= or SetCredential=",
+                    CREDENTIALS_DIRECTORY_VAR
+                ))
+            }
+        };
+        if !directory.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Credentials directory {} does not exist",
+                directory.display()
+            ));
+        }
+        Ok(directory.join(key))
+    }
+
+    fn read_only() -> anyhow::Error {
+        anyhow::anyhow!(
+            "The systemd-creds backend is read-only; provide credentials with LoadCredential= in the unit instead"
+        )
+    }
+}
+
+impl SecretBackend for SystemdCredsBackend {
+    fn store(&mut self, _key: &str, _value: &SecretString) -> Result<()> {
+        Err(Self::read_only())
+    }
+
+    fn retrieve(&self, key: &str) -> Result<Option<SecretString>> {
+        let path = self.credential_path(key)?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read credential {}", path.display()))
+            }
+        };
+
+        let mut bytes = Vec::new();
+        let read = file
+            .take(MAX_CREDENTIAL_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read credential {}", path.display()));
+        if let Err(err) = read {
+            bytes.zeroize();
+            return Err(err);
+        }
+        if bytes.len() as u64 > MAX_CREDENTIAL_BYTES {
+            bytes.zeroize();
+            return Err(anyhow::anyhow!(
+                "Credential {} is too large (max {} bytes)",
+                path.display(),
+                MAX_CREDENTIAL_BYTES
+            ));
+        }
+        let mut text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) => {
+                err.into_bytes().zeroize();
+                return Err(anyhow::anyhow!(
+                    "Credential {} is not valid UTF-8",
+                    path.display()
+                ));
+            }
+        };
+
+        // Files written with `echo` or an editor end in a line break that is not part of the secret
+        let value = text
+            .strip_suffix("\r\n")
+            .or_else(|| text.strip_suffix('\n'))
+            .unwrap_or(&text);
+        let secret = SecretString::new(value.into());
+        text.zeroize();
+        Ok(Some(secret))
+    }
+
+    fn delete(&mut self, _key: &str) -> Result<bool> {
+        Err(Self::read_only())
+    }
+
+    fn name(&self) -> &'static str {
+        "systemd-creds"
+    }
+
+    fn namespaced(&self, namespace: &str) -> Box<dyn SecretBackend> {
+        Box::new(SystemdCredsBackend {
+            namespace: Some(namespace.to_string()),
+        })
+    }
+
+    fn locate(&self, key: &str) -> Result<EntryLocation> {
+        let path = self.credential_path(key)?;
+        let exists = path.is_file();
+        Ok(EntryLocation {
+            service: path
+                .parent()
+                .map(|directory| directory.display().to_string())
+                .unwrap_or_default(),
+            account: key.to_string(),
+            store: SYSTEMD_CREDS_STORE,
+            exists,
+            attributes: vec![("path".to_string(), path.display().to_string())],
+        })
+    }
+
+    fn diagnose(&self, key: &str) -> Result<EntryDiagnosis> {
+        let mut steps = Vec::new();
+        let started = Instant::now();
+        let path = self.credential_path(key);
+        steps.push(DiagnosticStep {
+            name: "credentials-directory",
+            elapsed: started.elapsed(),
+            outcome: path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .map_err(|err| format!("{:#}", err)),
+        });
+
+        if path.is_ok() {
+            let started = Instant::now();
+            let outcome = match self.retrieve(key) {
+                Ok(Some(secret)) => Ok(format!("{} bytes", secret.expose_secret().len())),
+                Ok(None) => Ok("no such credential".to_string()),
+                Err(err) => Err(format!("{:#}", err)),
+            };
+            steps.push(DiagnosticStep {
+                name: "retrieve-length",
+                elapsed: started.elapsed(),
+                outcome,
+            });
+        }
+
+        Ok(EntryDiagnosis {
+            service: std::env::var(CREDENTIALS_DIRECTORY_VAR).unwrap_or_default(),
+            account: key.to_string(),
+            target: "file named after the account",
+            store: SYSTEMD_CREDS_STORE,
+            steps,
+        })
+    }
+}
+
+/// Credential store the keyring crate was built with and how long it keeps entries
+fn store_description() -> &'static str {
+    match keyring::default::default_credential_builder().persistence() {
+        CredentialPersistence::EntryOnly => "mock store; nothing is persisted",
+        CredentialPersistence::ProcessOnly => "in-process store; entries vanish on exit",
+        CredentialPersistence::UntilReboot => "kernel keyring; entries vanish on reboot",
+        CredentialPersistence::UntilDelete => "platform credential store (persistent)",
+        _ => "unknown credential store",
+    }
+}
+
+/// Debug form of a keyring error with control characters made visible
+fn raw_error(err: &keyring::Error) -> String {
+    match err {
+        // Carries the undecodable secret bytes themselves
+        keyring::Error::BadEncoding(bytes) => format!("BadEncoding(<{} bytes>)", bytes.len()),
+        err => crate::security::display_name(&format!("{:?}", err)).into_owned(),
+    }
 }
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::Path;
+
+use crate::security::validate_command_args_in;
+
+/// Largest accepted command list; generated lists stay far below this
+const MAX_COMMANDS_BYTES: u64 = 1024 * 1024;
+
+/// One command to run with the injected secrets
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueuedCommand {
+    /// 1-based line in the `--commands` list; `None` for the command after `--`
+    pub line: Option<usize>,
+    pub args: Vec<String>,
+}
+
+impl QueuedCommand {
+    /// The command given after `--`
+    pub fn single(args: Vec<String>) -> Self {
+        Self { line: None, args }
+    }
+
+    /// How the command is referred to in messages
+    pub fn label(&self) -> String {
+        match self.line {
+            Some(line) => format!("Command on line {}", line),
+            None => "Command".to_string(),
+        }
+    }
+}
+
+/// Reads a `--commands` list from a file, or from stdin when the path is `-`;
+/// the commands will run in `cwd`, or in our own directory when it is `None`
+pub fn load(source: &Path, cwd: Option<&Path>) -> Result<Vec<QueuedCommand>> {
+    let mut text = String::new();
+    let read = if source == Path::new("-") {
+        std::io::stdin()
+            .take(MAX_COMMANDS_BYTES + 1)
+            .read_to_string(&mut text)
+            .context("Failed to read commands from stdin")
+    } else {
+        std::fs::File::open(source)
+            .with_context(|| format!("Failed to open commands file {}", source.display()))?
+            .take(MAX_COMMANDS_BYTES + 1)
+            .read_to_string(&mut text)
+            .with_context(|| format!("Failed to read commands file {}", source.display()))
+    };
+    read?;
+    if text.len() as u64 > MAX_COMMANDS_BYTES {
+        return Err(anyhow::anyhow!(
+            "Command list is too large (max {} bytes)",
+            MAX_COMMANDS_BYTES
+        ));
+    }
+    parse(&text, cwd)
+}
+
+/// Splits each line into argv with shell quoting rules; nothing is run through a shell.
+///
+/// Blank lines and `#` comments are skipped, and every line is checked before
+/// anything is reported.
+pub fn parse(text: &str, cwd: Option<&Path>) -> Result<Vec<QueuedCommand>> {
+    let mut commands = Vec::new();
+    let mut problems = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let parsed = shell_words::split(trimmed)
+            .context("Invalid quoting")
+            .and_then(|args| validate_command_args_in(&args, cwd).map(|()| args));
+        match parsed {
+            Ok(args) => commands.push(QueuedCommand {
+                line: Some(index + 1),
+                args,
+            }),
+            Err(err) => problems.push(format!("Line {}: {:#}", index + 1, err)),
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} invalid line{}:\n  {}",
+            problems.len(),
+            if problems.len() == 1 { "" } else { "s" },
+            problems.join("\n  ")
+        ));
+    }
+    if commands.is_empty() {
+        return Err(anyhow::anyhow!("Command list contains no commands"));
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skips_comments_and_keeps_line_numbers() {
+        let commands = parse(
+            "# deploy\n\ncurl -H 'Authorization: Bearer $TOKEN' api\n  make  test \n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            commands,
+            [
+                QueuedCommand {
+                    line: Some(3),
+                    args: vec![
+                        "curl".to_string(),
+                        "-H".to_string(),
+                        "Authorization: Bearer $TOKEN".to_string(),
+                        "api".to_string()
+                    ],
+                },
+                QueuedCommand {
+                    line: Some(4),
+                    args: vec!["make".to_string(), "test".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_reports_every_bad_line() {
+        let err = parse("ok\necho 'unterminated\n\"\"\n", None).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("2 invalid lines"), "{}", message);
+        assert!(message.contains("Line 2: Invalid quoting"));
+        assert!(message.contains("Line 3: Empty command specified"));
+
+        assert!(parse("# nothing\n\n", None).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use secrecy::SecretString;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::cleanup::TempSecretFile;
+use crate::security::{validate_secret_value, MAX_SECRET_BYTES};
+
+/// Prefix marking a stored value as base64-encoded binary data
+pub const ENVELOPE_PREFIX: &str = "local-secrets:base64:";
+
+/// Largest raw file accepted so the encoded envelope stays within the 1MB secret limit
+const MAX_BINARY_BYTES: u64 = ((MAX_SECRET_BYTES - ENVELOPE_PREFIX.len()) / 4 * 3) as u64;
+
+/// Reads a binary file and wraps its bytes in a base64 envelope
+pub fn read_file_as_envelope(path: &Path) -> Result<SecretString> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open secret file {}", path.display()))?;
+
+    // Bounded read: never buffer more than one byte past the limit
+    let mut bytes = Zeroizing::new(Vec::new());
+    file.take(MAX_BINARY_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read secret file {}", path.display()))?;
+
+    if bytes.is_empty() {
+        return Err(anyhow::anyhow!("Secret file {} is empty", path.display()));
+    }
+    if bytes.len() as u64 > MAX_BINARY_BYTES {
+        return Err(anyhow::anyhow!(
+            "Binary secret too large (max {} bytes before encoding)",
+            MAX_BINARY_BYTES
+        ));
+    }
+
+    encode_envelope(&bytes)
+}
+
+/// Encodes raw bytes into the stored envelope form
+pub fn encode_envelope(bytes: &[u8]) -> Result<SecretString> {
+    let mut encoded = String::with_capacity(ENVELOPE_PREFIX.len() + bytes.len() * 4 / 3 + 4);
+    encoded.push_str(ENVELOPE_PREFIX);
+    STANDARD.encode_string(bytes, &mut encoded);
+
+    // Security: Size limits apply to the encoded form
+    validate_secret_value(&encoded)?;
+
+    let secret = SecretString::new(encoded.clone().into());
+    encoded.zeroize(); // Zero out the encoded copy from memory
+    Ok(secret)
+}
+
+/// Returns the base64 payload when `value` is a binary envelope
+pub fn envelope_payload(value: &str) -> Option<&str> {
+    value.strip_prefix(ENVELOPE_PREFIX)
+}
+
+/// Decodes an envelope payload back into raw bytes
+pub fn decode_payload(payload: &str) -> Result<Zeroizing<Vec<u8>>> {
+    STANDARD
+        .decode(payload)
+        .map(Zeroizing::new)
+        .map_err(|_| anyhow::anyhow!("Stored binary secret is not valid base64"))
+}
+
+/// Decodes `payload` into a new private temp file for the child
+pub fn write_decoded_file(variable: &str, payload: &str) -> Result<TempSecretFile> {
+    let bytes = decode_payload(payload)?;
+    TempSecretFile::create_in_temp_dir(variable, &bytes)
+        .with_context(|| format!("Failed to write decoded temp file for {}", variable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    /// Deterministic pseudo-random bytes (xorshift) so round trips cover every byte value
+    fn random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_envelope_round_trip_random_bytes() {
+        for (len, seed) in [(1, 1), (32, 7), (255, 42), (4096, 1234567)] {
+            let bytes = random_bytes(len, seed);
+            let secret = encode_envelope(&bytes).unwrap();
+            let payload = envelope_payload(secret.expose_secret()).unwrap();
+            assert_eq!(&*decode_payload(payload).unwrap(), &bytes);
+        }
+    }
+
+    #[test]
+    fn test_envelope_handles_nul_bytes() {
+        let secret = encode_envelope(&[0, 0, 1, 0]).unwrap();
+        assert!(!secret.expose_secret().contains('\0'));
+    }
+
+    #[test]
+    fn test_plain_values_are_not_envelopes() {
+        assert!(envelope_payload("plain-token").is_none());
+        assert!(envelope_payload("base64:AAAA").is_none());
+        assert!(decode_payload("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_encode_envelope_enforces_size_limit() {
+        let bytes = vec![0u8; MAX_BINARY_BYTES as usize];
+        assert!(encode_envelope(&bytes).is_ok());
+        let bytes = vec![0u8; MAX_BINARY_BYTES as usize + 3];
+        assert!(encode_envelope(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_decoded_file_holds_raw_bytes_and_is_removed_on_drop() {
+        let bytes = random_bytes(64, 99);
+        let secret = encode_envelope(&bytes).unwrap();
+        let payload = envelope_payload(secret.expose_secret()).unwrap();
+
+        let file = write_decoded_file("UNIT_TEST_KEY", payload).unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        drop(file);
+        assert!(!path.exists());
+    }
+}
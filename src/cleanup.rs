@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Transient secret files that still exist on disk, with their written length
+static REGISTRY: Mutex<Vec<(PathBuf, usize)>> = Mutex::new(Vec::new());
+
+/// Private temp directories that still exist on disk
+static DIR_REGISTRY: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Directories holding more than this are removed without overwriting first
+const MAX_SHRED_DIR_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Exit code after cleaning up on Ctrl-C or a termination signal (128 + SIGINT)
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Shreds every registered file and directory on panic, Ctrl-C, SIGTERM and SIGHUP,
+/// and restores a terminal left in raw mode by an open prompt.
+///
+/// Normal exits are covered by the guards' `Drop`; this catches the
+/// paths that skip destructors.
+pub fn install() -> Result<()> {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        shred_all();
+        crate::prompt::restore_terminal();
+        default_hook(info);
+    }));
+
+    ctrlc::set_handler(|| {
+        crate::tree::stop_all();
+        shred_all();
+        crate::prompt::restore_terminal();
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    })
+    .context("Failed to install signal handler")
+}
+
+/// Owner-only (0600) file holding secret material for the lifetime of a run.
+///
+/// The file is overwritten with zeros and removed when dropped, or by the
+/// process-level handlers if local-secrets is interrupted first.
+pub struct TempSecretFile {
+    path: PathBuf,
+}
+
+impl TempSecretFile {
+    /// Creates a new file at `path` containing `contents`; never overwrites
+    pub fn create(path: &Path, contents: &[u8]) -> Result<Self> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        // Register before writing so an interrupt mid-write still removes the file
+        registry().push((path.to_path_buf(), contents.len()));
+        let guard = Self {
+            path: path.to_path_buf(),
+        };
+
+        file.write_all(contents)
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(guard)
+    }
+
+    /// Creates a uniquely named file in the system temp directory
+    pub fn create_in_temp_dir(label: &str, contents: &[u8]) -> Result<Self> {
+        Self::create(&unique_temp_path(label), contents)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempSecretFile {
+    fn drop(&mut self) {
+        let entry = {
+            let mut registry = registry();
+            registry
+                .iter()
+                .position(|(path, _)| *path == self.path)
+                .map(|index| registry.swap_remove(index))
+        };
+        if let Some((path, len)) = entry {
+            shred(&path, len);
+        }
+    }
+}
+
+/// Owner-only (0700) directory used as the child's `TMPDIR` (`--isolate-tmp`).
+///
+/// Everything the child leaves behind is overwritten with zeros and the tree
+/// removed when dropped, or by the process-level handlers on interrupt.
+pub struct TempSecretDir {
+    path: PathBuf,
+}
+
+impl TempSecretDir {
+    /// Creates a uniquely named directory in the system temp directory
+    pub fn create_in_temp_dir(label: &str) -> Result<Self> {
+        let path = unique_temp_path(label);
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+
+        // `create` fails if the path exists, so a pre-planted directory is never reused
+        builder
+            .create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        dir_registry().push(path.clone());
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempSecretDir {
+    fn drop(&mut self) {
+        let entry = {
+            let mut registry = dir_registry();
+            registry
+                .iter()
+                .position(|path| *path == self.path)
+                .map(|index| registry.swap_remove(index))
+        };
+        if let Some(path) = entry {
+            shred_dir(&path);
+        }
+    }
+}
+
+/// Path in the system temp directory that is unique to this process and moment
+fn unique_temp_path(label: &str) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    std::env::temp_dir().join(format!(
+        "local-secrets-{}-{}-{:08x}",
+        std::process::id(),
+        label,
+        nanos
+    ))
+}
+
+fn registry() -> MutexGuard<'static, Vec<(PathBuf, usize)>> {
+    // A poisoned lock still holds a valid list; cleanup must not give up on it
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn dir_registry() -> MutexGuard<'static, Vec<PathBuf>> {
+    DIR_REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn shred_all() {
+    let entries = std::mem::take(&mut *registry());
+    for (path, len) in entries {
+        shred(&path, len);
+    }
+    let dirs = std::mem::take(&mut *dir_registry());
+    for path in dirs {
+        shred_dir(&path);
+    }
+}
+
+/// Best effort: overwrite every regular file in the tree, then remove it all.
+///
+/// Symlinks are never followed and hard-linked files are not overwritten, so
+/// the child cannot trick cleanup into zeroing files outside the directory.
+fn shred_dir(dir: &Path) {
+    let mut files = Vec::new();
+    let mut total: u64 = 0;
+    // Explicit stack: a hostile child could nest directories deep enough to overflow recursion
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() && !is_hard_linked(&metadata) {
+                total = total.saturating_add(metadata.len());
+                files.push((entry.path(), metadata.len()));
+            }
+        }
+    }
+
+    if total > MAX_SHRED_DIR_BYTES {
+        eprintln!(
+            "Warning: {} grew to {} MiB; removing it without overwriting",
+            dir.display(),
+            total / (1024 * 1024)
+        );
+    } else {
+        for (path, len) in files {
+            shred(&path, usize::try_from(len).unwrap_or_default());
+        }
+    }
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[cfg(unix)]
+fn is_hard_linked(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn is_hard_linked(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Best effort: overwrite the content before unlinking
+fn shred(path: &Path, len: usize) {
+    if let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(path) {
+        let _ = file.write_all(&vec![0u8; len]);
+        let _ = file.sync_all();
+    }
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_registered(path: &Path) -> bool {
+        registry().iter().any(|(registered, _)| registered == path)
+    }
+
+    #[test]
+    fn test_temp_secret_file_is_private_and_removed_on_drop() {
+        let file = TempSecretFile::create_in_temp_dir("UNIT_TEST_CLEANUP", b"secret").unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+        assert!(is_registered(&path));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        drop(file);
+        assert!(!path.exists());
+        assert!(!is_registered(&path));
+    }
+
+    #[test]
+    fn test_temp_secret_dir_is_private_and_removed_recursively() {
+        let dir = TempSecretDir::create_in_temp_dir("UNIT_TEST_TMPDIR").unwrap();
+        let path = dir.path().to_path_buf();
+        std::fs::create_dir_all(path.join("nested/deeper")).unwrap();
+        std::fs::write(path.join("nested/deeper/scratch"), b"secret").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        drop(dir);
+        assert!(!path.exists());
+        assert!(!dir_registry().contains(&path));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_temp_secret_dir_does_not_overwrite_through_links() {
+        let outside = TempSecretFile::create_in_temp_dir("UNIT_TEST_OUTSIDE", b"keep").unwrap();
+        let dir = TempSecretDir::create_in_temp_dir("UNIT_TEST_LINKS").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("symlink")).unwrap();
+        std::fs::hard_link(outside.path(), dir.path().join("hardlink")).unwrap();
+
+        drop(dir);
+        assert_eq!(std::fs::read(outside.path()).unwrap(), b"keep");
+    }
+
+    #[test]
+    fn test_temp_secret_file_never_overwrites_existing_files() {
+        let first = TempSecretFile::create_in_temp_dir("UNIT_TEST_EXISTING", b"a").unwrap();
+        assert!(TempSecretFile::create(first.path(), b"b").is_err());
+        assert_eq!(std::fs::read(first.path()).unwrap(), b"a");
+    }
+}
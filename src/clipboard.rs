@@ -0,0 +1,57 @@
+use anyhow::Result;
+use secrecy::SecretString;
+
+/// Reads a secret from the system clipboard.
+///
+/// Surrounding whitespace is trimmed because copied tokens frequently carry a
+/// trailing newline or stray spaces from the source page.
+#[cfg(feature = "clipboard")]
+pub fn read_secret() -> Result<SecretString> {
+    use zeroize::Zeroize;
+
+    let mut clipboard = open()?;
+    let mut text = match clipboard.get_text() {
+        Ok(text) => text,
+        Err(arboard::Error::ContentNotAvailable) => {
+            return Err(anyhow::anyhow!(
+                "Clipboard is empty or does not contain text"
+            ));
+        }
+        Err(err) => return Err(anyhow::anyhow!("Failed to read clipboard: {}", err)),
+    };
+
+    let secret = SecretString::new(text.trim().into());
+    text.zeroize(); // Zero out the raw clipboard copy from memory
+    Ok(secret)
+}
+
+/// Clears the system clipboard after its content has been consumed.
+#[cfg(feature = "clipboard")]
+pub fn clear() -> Result<()> {
+    open()?
+        .clear()
+        .map_err(|err| anyhow::anyhow!("Failed to clear clipboard: {}", err))
+}
+
+#[cfg(feature = "clipboard")]
+fn open() -> Result<arboard::Clipboard> {
+    arboard::Clipboard::new()
+        .map_err(|err| anyhow::anyhow!("No clipboard available on this system: {}", err))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn read_secret() -> Result<SecretString> {
+    Err(not_compiled_in())
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn clear() -> Result<()> {
+    Err(not_compiled_in())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn not_compiled_in() -> anyhow::Error {
+    anyhow::anyhow!(
+        "No clipboard available: clipboard support was not compiled in (rebuild with --features clipboard)"
+    )
+}
@@ -1,15 +1,81 @@
 use anyhow::{Context, Result};
 use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
 use std::env;
-use std::process::Command;
-use zeroize::Zeroize;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::backend::SecretBackend;
-use crate::security::{validate_env_var_name, validate_secret_value};
+use crate::batch::QueuedCommand;
+use crate::binary;
+use crate::cleanup::{TempSecretDir, TempSecretFile};
+use crate::clipboard;
+use crate::derive::{self, DeriveEncoding};
+use crate::envdiff::{self, Evidence};
+use crate::envfile::{self, EnvFileFormat, ENV_FILE_VAR};
+use crate::errors::{self, ErrorCode};
+use crate::executable;
+use crate::helm;
+use crate::history;
+use crate::ide::{self, IdeFormat};
+use crate::import;
+use crate::json;
+use crate::mask;
+use crate::otel;
+use crate::process;
+use crate::prompt::{self, PromptStyle};
+use crate::security::{
+    blank_value_reason, display_name, injected_name, source_spec, split_env_spec,
+    strip_terminal_escapes, validate_command_args, validate_env_var_name, validate_namespace,
+    validate_secret_value, warn_if_critical, MAX_SECRET_BYTES,
+};
+use crate::stats::{self, RunStats};
+use crate::tree;
+use crate::watch::{self, WatchEvent};
+
+/// Where `store` obtains the secret value from
+pub enum SecretSource {
+    /// Interactive prompt (the default); `raw_input` keeps terminal escape sequences,
+    /// `style` picks the typing feedback and `timeout` abandons the prompt (`--prompt-timeout`)
+    Prompt {
+        style: PromptStyle,
+        raw_input: bool,
+        timeout: Option<Duration>,
+    },
+    /// System clipboard, optionally cleared once the secret has been stored
+    Clipboard { clear_after: bool },
+    /// Raw bytes of a file, stored as a base64 envelope
+    BinaryFile(PathBuf),
+    /// UTF-8 contents of a file (or FIFO), optionally without one trailing newline
+    TextFile { path: PathBuf, trim_newline: bool },
+    /// All of standard input, without one trailing newline unless `keep_newline`
+    Stdin { keep_newline: bool },
+    /// Standard output of a producer command, without one trailing newline
+    Command(Vec<String>),
+}
+
+/// How `store` treats a variable that already has an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreMode {
+    /// Create or replace (`store`)
+    Upsert,
+    /// Leave an existing entry alone (`store --if-missing`)
+    IfMissing,
+    /// Only replace an existing entry (`update`)
+    UpdateOnly,
+}
 
 #[cfg(not(feature = "test-secret-param"))]
-pub fn store(backend: &mut dyn SecretBackend, variable: &str) -> Result<()> {
-    store_with_options(backend, variable, None)
+pub fn store(
+    backend: &mut dyn SecretBackend,
+    variable: &str,
+    source: SecretSource,
+    mode: StoreMode,
+) -> Result<()> {
+    store_with_options(backend, variable, None, source, mode)
 }
 
 #[cfg(feature = "test-secret-param")]
@@ -17,52 +83,120 @@ pub fn store_with_test_value(
     backend: &mut dyn SecretBackend,
     variable: &str,
     test_secret: Option<&str>,
+    source: SecretSource,
+    mode: StoreMode,
 ) -> Result<()> {
-    store_with_options(backend, variable, test_secret)
+    store_with_options(backend, variable, test_secret, source, mode)
+}
+
+/// Environment variable that supplies secrets non-interactively in automated tests
+const TEST_SECRET_ENV: &str = "LOCAL_SECRETS_TEST_SECRET";
+
+/// Exit code when `--timeout` stopped the child, as with coreutils `timeout`
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// The secret provided for automated tests, if any.
+///
+/// `--test-secret` (test builds only) takes precedence over `LOCAL_SECRETS_TEST_SECRET`.
+/// An empty value from either source is rejected here, before any backend is touched,
+/// so `store` and run mode fail with the same message and exit code.
+pub fn test_secret(test_secret_param: Option<&str>) -> Result<Option<SecretString>> {
+    let mut value = match test_secret_param {
+        Some(value) => value.to_string(),
+        None => match env::var(TEST_SECRET_ENV) {
+            Ok(value) => value,
+            Err(_) => return Ok(None),
+        },
+    };
+    if value.is_empty() {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            format!(
+                "Refusing to use an empty secret from {}",
+                if test_secret_param.is_some() {
+                    "--test-secret"
+                } else {
+                    TEST_SECRET_ENV
+                }
+            ),
+        ));
+    }
+
+    // Security: Validate secret value
+    let validated =
+        validate_secret_value(&value).map_err(errors::tagged(ErrorCode::InvalidInput, None));
+    let secret = SecretString::new(value.clone().into());
+    value.zeroize(); // Zero out the copy from memory
+    validated.map(|()| Some(secret))
 }
 
 fn store_with_options(
     backend: &mut dyn SecretBackend,
     variable: &str,
     test_secret_override: Option<&str>,
+    source: SecretSource,
+    mode: StoreMode,
 ) -> Result<()> {
     // Security: Validate variable name for injection attacks
-    validate_env_var_name(variable)?;
+    validate_env_var_name(variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+
+    // Decide before asking for a value, so nobody types a secret that is then discarded
+    if !mode_allows(backend, variable, mode)? {
+        return Ok(());
+    }
+    warn_if_critical(variable);
+    let provided = test_secret(test_secret_override)?;
 
     // Get the secret value using priority order:
     // 1. test_secret_override parameter (test builds only)
-    // 2. LOCAL_SECRETS_TEST_SECRET environment variable
-    // 3. User input prompt
-    let secret = if let Some(test_value) = test_secret_override {
+    // 2. File (--from-file, with --binary as a base64 envelope) or --stdin
+    // 3. System clipboard (--from-clipboard)
+    // 4. LOCAL_SECRETS_TEST_SECRET environment variable
+    // 5. User input prompt
+    let mut clear_clipboard = false;
+    let secret = if let (Some(secret), Some(_)) = (&provided, test_secret_override) {
         // Test mode via parameter - use provided secret (no prompt needed)
+        secret.clone()
+    } else if let SecretSource::BinaryFile(path) = &source {
+        binary::read_file_as_envelope(path)?
+    } else if let SecretSource::TextFile { path, trim_newline } = &source {
+        read_text_file(path, *trim_newline)?
+    } else if let SecretSource::Command(args) = &source {
+        read_command_output(args)?
+    } else if let SecretSource::Stdin { keep_newline } = source {
+        // Never falls back to prompting: an empty pipe is an error
+        let bytes = read_bounded(std::io::stdin().lock(), "standard input")?;
+        text_secret(&bytes, "standard input", !keep_newline)?
+    } else if let SecretSource::Clipboard { clear_after } = source {
+        let secret = clipboard::read_secret()?;
+        if secret.expose_secret().is_empty() {
+            return Err(anyhow::anyhow!(
+                "Clipboard is empty or contains only whitespace"
+            ));
+        }
 
         // Security: Validate secret value
-        validate_secret_value(test_value)?;
+        validate_secret_value(secret.expose_secret())?;
 
-        let mut test_value_copy = test_value.to_string();
-        let secret = SecretString::new(test_value_copy.clone().into());
-        test_value_copy.zeroize(); // Zero out the copy from memory
+        clear_clipboard = clear_after;
         secret
-    } else if let Ok(mut test_secret) = env::var("LOCAL_SECRETS_TEST_SECRET") {
+    } else if let Some(secret) = provided {
         // Test mode via environment - use provided secret (no prompt needed)
-
-        // Security: Validate secret value
-        validate_secret_value(&test_secret)?;
-
-        let secret = SecretString::new(test_secret.clone().into());
-        test_secret.zeroize(); // Zero out the test secret from memory
         secret
     } else {
         // Production mode - prompt user
-        eprint!("Enter secret for {}: ", variable);
-        let mut password = rpassword::read_password().context("Failed to read password")?;
-
-        // Security: Validate secret value
-        validate_secret_value(&password)?;
-
-        let secret = SecretString::new(password.clone().into());
-        password.zeroize(); // Zero out the password from memory
-        secret
+        eprint!("Enter secret for {}: ", display_name(variable));
+        let (style, raw_input, timeout) = match source {
+            SecretSource::Prompt {
+                style,
+                raw_input,
+                timeout,
+            } => (style, raw_input, timeout),
+            _ => (PromptStyle::Hidden, false, None),
+        };
+        read_prompted_secret(style, raw_input, timeout)?
     };
 
     // Store the secret
@@ -71,105 +205,2612 @@ fn store_with_options(
         .context("Failed to store secret")?;
 
     println!("Stored secret for {}.", variable);
+
+    if clear_clipboard {
+        clipboard::clear()?;
+        eprintln!("Cleared clipboard.");
+    }
+
     Ok(())
 }
 
-pub fn delete(backend: &mut dyn SecretBackend, variable: &str) -> Result<()> {
-    // Security: Validate variable name for injection attacks
-    validate_env_var_name(variable)?;
+/// Whether `mode` lets `variable` be written; says so when an existing entry is kept
+fn mode_allows(backend: &dyn SecretBackend, variable: &str, mode: StoreMode) -> Result<bool> {
+    if mode == StoreMode::Upsert {
+        return Ok(true);
+    }
+    let exists = backend.retrieve(variable)?.is_some();
+    if mode == StoreMode::IfMissing && exists {
+        println!("{} is already stored; left unchanged.", variable);
+        return Ok(false);
+    }
+    if mode == StoreMode::UpdateOnly && !exists {
+        return Err(errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!(
+                "Secret {} does not exist; use `store` to create it",
+                variable
+            ),
+        ));
+    }
+    Ok(true)
+}
+
+/// `store VAR1 VAR2 ...`: prompts for each variable in turn and summarizes at the end.
+///
+/// All names are validated before the first prompt. An empty entry offers to skip the
+/// variable; declining aborts, keeping what was stored so far.
+pub fn store_many(
+    backend: &mut dyn SecretBackend,
+    variables: &[String],
+    provided: Option<SecretString>,
+    source: SecretSource,
+    mode: StoreMode,
+) -> Result<()> {
+    for (index, variable) in variables.iter().enumerate() {
+        validate_env_var_name(variable)
+            .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+        if variables[..index].contains(variable) {
+            return Err(errors::coded(
+                ErrorCode::InvalidInput,
+                Some(variable),
+                format!("{} is listed more than once", variable),
+            ));
+        }
+    }
+    let (style, raw_input, timeout) = match source {
+        SecretSource::Prompt {
+            style,
+            raw_input,
+            timeout,
+        } => (style, raw_input, timeout),
+        _ => {
+            return Err(errors::coded(
+                ErrorCode::InvalidInput,
+                None,
+                "Several variables can only be stored from prompts".to_string(),
+            ))
+        }
+    };
 
-    let existed = backend
-        .delete(variable)
-        .context("Failed to delete secret")?;
+    let mut stored = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, variable) in variables.iter().enumerate() {
+        if !mode_allows(backend, variable, mode)? {
+            skipped.push(variable.as_str());
+            continue;
+        }
+        warn_if_critical(variable);
 
-    if existed {
-        println!("Deleted {}.", variable);
-    } else {
-        eprintln!("Secret {} not found.", variable);
-        return Err(anyhow::anyhow!("Secret not found"));
+        let secret = match &provided {
+            Some(secret) => secret.clone(),
+            None => {
+                eprint!(
+                    "({}/{}) Enter secret for {}: ",
+                    index + 1,
+                    variables.len(),
+                    display_name(variable)
+                );
+                let secret = read_prompted_secret(style, raw_input, timeout)?;
+                if secret.expose_secret().is_empty() {
+                    if !confirm_skip(variable)? {
+                        summarize_stored(&stored, &skipped);
+                        return Err(anyhow::anyhow!(
+                            "Aborted at {}; nothing entered",
+                            display_name(variable)
+                        ));
+                    }
+                    skipped.push(variable.as_str());
+                    continue;
+                }
+                secret
+            }
+        };
+        backend
+            .store(variable, &secret)
+            .with_context(|| format!("Failed to store secret for {}", variable))?;
+        stored.push(variable.as_str());
     }
 
+    summarize_stored(&stored, &skipped);
     Ok(())
 }
 
-pub fn run_with_env(
+/// Asks whether an empty entry skips the variable; without a terminal it never does
+fn confirm_skip(variable: &str) -> Result<bool> {
+    if !can_ask(std::io::stdin().is_terminal(), env::var_os("CI").is_some()) {
+        return Ok(false);
+    }
+    eprint!(
+        "Nothing entered for {}. Skip it and continue? [y/N] ",
+        display_name(variable)
+    );
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    Ok(save_answer(&answer, false))
+}
+
+fn summarize_stored(stored: &[&str], skipped: &[&str]) {
+    if stored.is_empty() {
+        println!("Stored no secrets.");
+    } else {
+        println!("Stored secrets for {}.", stored.join(", "));
+    }
+    if !skipped.is_empty() {
+        println!("Skipped {}.", skipped.join(", "));
+    }
+}
+
+/// Reads a text secret from `path`, which may be a pipe; at most the 1MB secret limit is buffered
+fn read_text_file(path: &Path, trim_newline: bool) -> Result<SecretString> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open secret file {}", path.display()))?;
+    let what = format!("secret file {}", path.display());
+    text_secret(&read_bounded(file, &what)?, &what, trim_newline)
+}
+
+/// Runs a `--from-command` producer and takes its stdout as the secret.
+///
+/// The producer's stderr goes straight to the terminal, so its own errors and prompts
+/// are visible; a non-zero exit stores nothing.
+fn read_command_output(args: &[String]) -> Result<SecretString> {
+    // Security: Validate the producer argv like run mode does
+    validate_command_args(args).context("Invalid --from-command command")?;
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("--from-command needs a command after --"))?;
+
+    let mut child = Command::new(program)
+        .args(rest)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|err| {
+            errors::coded(
+                ErrorCode::SpawnFailed,
+                None,
+                format!("Failed to run --from-command {}: {}", program, err),
+            )
+        })?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("--from-command stdout was not captured"))?;
+    // Dropping the pipe after a bounded read stops a producer that writes too much
+    let bytes = read_bounded(stdout, "the --from-command output");
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for --from-command {}", program))?;
+    let bytes = bytes?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "--from-command {} {}; nothing stored",
+            program,
+            match status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => "was terminated by a signal".to_string(),
+            }
+        ));
+    }
+    text_secret(&bytes, "the --from-command output", true)
+}
+
+/// Reads `reader` to the end, buffering at most one byte past the secret size limit
+fn read_bounded(reader: impl Read, what: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut bytes = Zeroizing::new(Vec::new());
+    reader
+        .take(MAX_SECRET_BYTES as u64 + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read {}", what))?;
+    Ok(bytes)
+}
+
+/// Turns bytes read from `what` into a validated secret; empty input is an error
+fn text_secret(bytes: &[u8], what: &str, trim_newline: bool) -> Result<SecretString> {
+    let text = std::str::from_utf8(bytes).map_err(|_| {
+        anyhow::anyhow!(
+            "The contents of {} are not valid UTF-8; store binary data with --binary --from-file",
+            what
+        )
+    })?;
+
+    let value = if trim_newline {
+        text.strip_suffix("\r\n")
+            .or_else(|| text.strip_suffix('\n'))
+            .unwrap_or(text)
+    } else {
+        text
+    };
+    if value.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to store: {} is empty", what));
+    }
+
+    // Security: Validate secret value (size limit, null bytes)
+    validate_secret_value(value)?;
+    Ok(SecretString::new(value.into()))
+}
+
+/// Asks whether a value entered at a run-mode prompt should be kept in the keyring.
+///
+/// Nothing is asked without a terminal on stdin or when `CI` is set; the value
+/// is then used for this run only.
+fn confirm_save(variable: &str) -> Result<bool> {
+    if !can_ask(std::io::stdin().is_terminal(), env::var_os("CI").is_some()) {
+        eprintln!(
+            "Not saving {} (non-interactive); run 'local-secrets store {}' to keep it",
+            display_name(variable),
+            display_name(variable)
+        );
+        return Ok(false);
+    }
+
+    eprint!(
+        "Save {} to the keyring for future runs? [Y/n] ",
+        display_name(variable)
+    );
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    let save = save_answer(&answer, true);
+    if !save {
+        eprintln!("Not saving {}.", display_name(variable));
+    }
+    Ok(save)
+}
+
+/// Whether a question can be put to the user: a terminal on stdin and not a CI job
+fn can_ask(stdin_is_terminal: bool, in_ci: bool) -> bool {
+    stdin_is_terminal && !in_ci
+}
+
+/// Interprets a `[Y/n]` answer; an empty line takes the default, anything unclear declines
+fn save_answer(answer: &str, default: bool) -> bool {
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    }
+}
+
+/// Reads a secret from the prompt.
+///
+/// Unless `raw_input` is set, terminal escape sequences that leaked into the
+/// input (typically bracketed-paste markers) are removed with a warning.
+fn read_prompted_secret(
+    style: PromptStyle,
+    raw_input: bool,
+    timeout: Option<Duration>,
+) -> Result<SecretString> {
+    let mut password = prompt::read_secret(style, timeout)?;
+    if !raw_input {
+        if let Some(stripped) = strip_terminal_escapes(&password) {
+            eprintln!(
+                "Warning: removed terminal escape sequences (such as bracketed paste markers) from the secret; use --raw-input to keep them"
+            );
+            password = stripped;
+        }
+    }
+
+    // Security: Validate secret value
+    validate_secret_value(&password)?;
+
+    Ok(SecretString::new(password.as_str().into()))
+}
+
+/// Deletes each variable in order, reporting every outcome before failing.
+///
+/// All names are validated first, so a typo cannot leave a list half-deleted. Missing
+/// secrets fail the command unless `ignore_missing` is set; backend errors always do.
+pub fn delete(
     backend: &mut dyn SecretBackend,
-    env_vars: &[String],
-    no_save_missing: bool,
-    command_args: &[String],
+    variables: &[String],
+    ignore_missing: bool,
 ) -> Result<()> {
-    // Security validation is now performed in main.rs before calling this function
-    // This is part of defense-in-depth strategy
+    // Security: Validate variable names for injection attacks
+    for variable in variables {
+        validate_env_var_name(variable)
+            .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    }
 
-    if !env_vars.is_empty() {
-        eprintln!("Injecting env vars: {:?}", env_vars);
+    let mut missing = Vec::new();
+    let mut failed = Vec::new();
+    for variable in variables {
+        match backend.delete(variable) {
+            Ok(true) => println!("Deleted {}.", variable),
+            Ok(false) => {
+                eprintln!("Secret {} not found.", variable);
+                missing.push(variable.as_str());
+            }
+            Err(err) => {
+                eprintln!("Failed to delete {}: {:#}", variable, err);
+                failed.push(variable.as_str());
+            }
+        }
+    }
+
+    let single = match variables {
+        [variable] => Some(variable.as_str()),
+        _ => None,
+    };
+    if !failed.is_empty() {
+        return Err(errors::coded(
+            ErrorCode::BackendError,
+            single,
+            format!("Failed to delete {}", failed.join(", ")),
+        ));
+    }
+    if !missing.is_empty() && !ignore_missing {
+        return Err(errors::coded(
+            ErrorCode::SecretNotFound,
+            single,
+            match single {
+                Some(_) => "Secret not found".to_string(),
+                None => format!("Not found: {}", missing.join(", ")),
+            },
+        ));
     }
 
-    let mut cmd = Command::new(&command_args[0]);
-    cmd.args(&command_args[1..]);
+    Ok(())
+}
 
-    // Inject environment variables
-    for var in env_vars {
-        let secret = match backend.retrieve(var)? {
-            Some(secret) => secret,
-            None => {
-                // Secret not found, handle based on flags
-                if let Ok(mut test_secret) = env::var("LOCAL_SECRETS_TEST_SECRET") {
-                    // Test mode - use provided test secret
-                    eprintln!("Enter secret for missing {}: ", var);
+/// Prints one secret to stdout, for `TOKEN=$(local-secrets get NAME)`
+pub fn get(backend: &dyn SecretBackend, spec: &str, newline: bool, force: bool) -> Result<()> {
+    if !may_print(std::io::stdout().is_terminal(), force) {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            "Refusing to print a secret to a terminal; capture it with $(...), pipe it, or pass --force"
+                .to_string(),
+        ));
+    }
+    let mut stdout = std::io::stdout().lock();
+    write_secret(backend, spec, newline, &mut stdout)
+}
 
-                    // Security: Validate secret value
-                    validate_secret_value(&test_secret)?;
+/// `backend`, or a backend of the same kind scoped to another namespace
+enum ScopedBackend<'b> {
+    Default(&'b dyn SecretBackend),
+    Namespaced(Box<dyn SecretBackend>),
+}
 
-                    let secret = SecretString::new(test_secret.clone().into());
-                    test_secret.zeroize(); // Zero out the test secret from memory
+impl<'b> std::ops::Deref for ScopedBackend<'b> {
+    type Target = dyn SecretBackend + 'b;
 
-                    if !no_save_missing {
-                        backend.store(var, &secret)?;
-                        eprintln!("Stored secret for {}.", var);
-                    }
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ScopedBackend::Default(backend) => *backend,
+            ScopedBackend::Namespaced(backend) => backend.as_ref(),
+        }
+    }
+}
 
-                    secret
-                } else if env::var("LOCAL_SECRETS_TEST_MODE").is_ok() {
-                    // Test mode but no test secret provided - this should fail
-                    return Err(anyhow::anyhow!("Secret {} not found", var));
-                } else {
-                    // Production mode - prompt user
-                    eprint!("Enter secret for missing {}: ", var);
-                    let mut password =
-                        rpassword::read_password().context("Failed to read password")?;
+/// Validates `spec` (`NAME` or `NAME@namespace`) and returns the backend that holds
+/// it together with the variable name
+fn scope_spec<'b, 's>(
+    backend: &'b dyn SecretBackend,
+    spec: &'s str,
+) -> Result<(ScopedBackend<'b>, &'s str)> {
+    let (variable, namespace) = split_env_spec(spec);
+    validate_env_var_name(variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    let scoped = match namespace {
+        Some(namespace) => {
+            validate_namespace(namespace)
+                .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+            ScopedBackend::Namespaced(backend.namespaced(namespace))
+        }
+        None => ScopedBackend::Default(backend),
+    };
+    Ok((scoped, variable))
+}
 
-                    // Security: Validate secret value
-                    validate_secret_value(&password)?;
+/// Describes a stored secret (length, fingerprint, backend) without printing it
+pub fn info(backend: &dyn SecretBackend, spec: &str) -> Result<()> {
+    let (scoped, variable) = scope_spec(backend, spec)?;
+    let secret = scoped.retrieve(variable)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!("Secret {} not found", variable),
+        )
+    })?;
 
-                    let secret = SecretString::new(password.clone().into());
-                    password.zeroize(); // Zero out the password from memory
+    println!("variable:    {}", spec);
+    println!("backend:     {}", backend.name());
+    println!(
+        "length:      {} characters",
+        secret.expose_secret().chars().count()
+    );
+    println!("fingerprint: {} (SHA-256 prefix)", fingerprint(&secret));
+    Ok(())
+}
 
-                    if !no_save_missing {
-                        backend.store(var, &secret)?;
-                        eprintln!("Stored secret for {}.", var);
-                    }
+/// First 8 hex digits of the value's SHA-256, enough to tell two values apart at a glance
+fn fingerprint(secret: &SecretString) -> String {
+    Sha256::digest(secret.expose_secret().as_bytes())[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
 
-                    secret
+/// Secrets go to a terminal only when asked for explicitly, against shoulder-surfing
+fn may_print(stdout_is_terminal: bool, force: bool) -> bool {
+    !stdout_is_terminal || force
+}
+
+/// Writes the value of `spec` (`NAME` or `NAME@namespace`) to `out`, nothing else
+fn write_secret(
+    backend: &dyn SecretBackend,
+    spec: &str,
+    newline: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let (scoped, variable) = scope_spec(backend, spec)?;
+    let secret = scoped.retrieve(variable)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!("Secret {} not found", variable),
+        )
+    })?;
+
+    let written = out
+        .write_all(secret.expose_secret().as_bytes())
+        .and_then(|()| {
+            if newline {
+                out.write_all(b"\n")
+            } else {
+                Ok(())
+            }
+        })
+        .and_then(|()| out.flush());
+    match written {
+        // The reader went away (e.g. `| head -c 4`); nothing left to report
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("Failed to write secret to stdout"),
+    }
+}
+
+/// Runs `probe` with the secret injected and reports whether the service accepted it
+pub fn verify(backend: &dyn SecretBackend, variable: &str, probe: &str) -> Result<ExitCode> {
+    // Security: Validate variable name and probe argv like run mode does
+    validate_env_var_name(variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    let probe_args = process::parse_probe(probe)?;
+    validate_command_args(&probe_args).context("Invalid --probe command")?;
+    check_probe(&probe_args, variable, "--probe")?;
+
+    let secret = backend.retrieve(variable)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!("Secret {} not found", variable),
+        )
+    })?;
+
+    let status = run_probe(variable, &secret, &probe_args, "--probe")?;
+    if status.success() {
+        println!("{}: valid", variable);
+        Ok(ExitCode::SUCCESS)
+    } else {
+        match status.code() {
+            Some(code) => println!("{}: invalid (probe exited with code {})", variable, code),
+            None => println!("{}: invalid (probe was terminated by a signal)", variable),
+        }
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Fails unless every placeholder in the `flag` command names `variable`; checked
+/// before the secret is read
+fn check_probe(probe_args: &[String], variable: &str, flag: &str) -> Result<()> {
+    for arg in probe_args {
+        process::expand_placeholders(arg, flag, |name| probe_value(name, variable, "", flag))?;
+    }
+    Ok(())
+}
+
+/// The value to substitute for `{{name}}` in a probe that checks `variable`
+fn probe_value<'s>(name: &str, variable: &str, secret: &'s str, flag: &str) -> Result<&'s str> {
+    if name == variable {
+        return Ok(secret);
+    }
+    Err(anyhow::anyhow!(
+        "{{{{{}}}}} in {} does not name {}",
+        name,
+        flag,
+        variable
+    ))
+}
+
+/// Runs a probe with `secret` injected as `variable` (and substituted for `{{variable}}`).
+///
+/// The probe's output is discarded so a verbose tool cannot echo the secret.
+fn run_probe(
+    variable: &str,
+    secret: &SecretString,
+    probe_args: &[String],
+    flag: &str,
+) -> Result<std::process::ExitStatus> {
+    let mut substituted = false;
+    let mut args = Vec::with_capacity(probe_args.len());
+    for arg in probe_args {
+        args.push(process::expand_placeholders(arg, flag, |name| {
+            substituted = true;
+            probe_value(name, variable, secret.expose_secret(), flag)
+        })?);
+    }
+    if substituted {
+        eprintln!(
+            "Warning: {{{{{}}}}} puts the secret in the {} command's arguments, which are visible in process listings",
+            variable, flag
+        );
+    }
+
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("{} command cannot be empty", flag))?;
+
+    let status = Command::new(program.as_str())
+        .args(rest.iter().map(|arg| arg.as_str()))
+        .env(variable, secret.expose_secret())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run {} command", flag))?;
+    drop(args); // Zeroize substituted arguments as soon as the probe has run
+    Ok(status)
+}
+
+/// Prompt settings of `source`; the defaults when the secret comes from elsewhere
+fn prompt_settings(source: &SecretSource) -> (PromptStyle, bool, Option<Duration>) {
+    match *source {
+        SecretSource::Prompt {
+            style,
+            raw_input,
+            timeout,
+        } => (style, raw_input, timeout),
+        _ => (PromptStyle::Hidden, false, None),
+    }
+}
+
+/// Re-prompts for an existing secret, typed twice, after showing what is stored now.
+///
+/// With `keep_on_empty`, an empty entry leaves the secret unchanged.
+pub fn edit(
+    backend: &mut dyn SecretBackend,
+    variable: &str,
+    provided: Option<SecretString>,
+    source: SecretSource,
+    keep_on_empty: bool,
+) -> Result<()> {
+    validate_env_var_name(variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    let current = backend.retrieve(variable)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!(
+                "Secret {} does not exist; use `store` to create it",
+                variable
+            ),
+        )
+    })?;
+    eprintln!(
+        "{} is {} characters long (fingerprint {}).",
+        display_name(variable),
+        current.expose_secret().chars().count(),
+        fingerprint(&current)
+    );
+    drop(current);
+
+    let secret = match provided {
+        Some(secret) => secret,
+        None => {
+            let (style, raw_input, timeout) = prompt_settings(&source);
+            eprint!("Enter new secret for {}: ", display_name(variable));
+            let secret = read_prompted_secret(style, raw_input, timeout)?;
+            if !(keep_on_empty && secret.expose_secret().is_empty()) {
+                eprint!("Confirm new secret for {}: ", display_name(variable));
+                let confirmation = read_prompted_secret(style, raw_input, timeout)?;
+                if confirmation.expose_secret() != secret.expose_secret() {
+                    return Err(errors::coded(
+                        ErrorCode::InvalidInput,
+                        Some(variable),
+                        format!("Entries for {} did not match; nothing changed", variable),
+                    ));
                 }
             }
-        };
+            secret
+        }
+    };
+    if keep_on_empty && secret.expose_secret().is_empty() {
+        println!("Nothing entered; {} left unchanged.", variable);
+        return Ok(());
+    }
+
+    backend
+        .store(variable, &secret)
+        .context("Failed to store secret")?;
+    println!("Updated {}.", variable);
+    Ok(())
+}
+
+/// Name under which `rotate` keeps the value it replaced
+fn backup_name(variable: &str) -> String {
+    format!("{}__previous", variable)
+}
+
+/// Replaces a stored secret, keeping the old value as `VAR__previous` unless `backup` is off.
+///
+/// With `exec`, the command runs with the new value injected before anything is
+/// stored; if it fails, the backend is left untouched, so a bad rotation never sticks.
+pub fn rotate(
+    backend: &mut dyn SecretBackend,
+    variable: &str,
+    provided: Option<SecretString>,
+    source: SecretSource,
+    backup: bool,
+    exec: Option<&str>,
+) -> Result<()> {
+    // Security: Validate variable name and the verification argv like run mode does
+    validate_env_var_name(variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    let backup_variable = backup_name(variable);
+    validate_env_var_name(&backup_variable)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+    let exec_args = match exec {
+        Some(exec) => {
+            let args = process::parse_command_line(exec, "--exec")?;
+            validate_command_args(&args).context("Invalid --exec command")?;
+            check_probe(&args, variable, "--exec")?;
+            Some(args)
+        }
+        None => None,
+    };
+
+    let old = backend.retrieve(variable)?.ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SecretNotFound,
+            Some(variable),
+            format!(
+                "Secret {} not found; use 'local-secrets store {}' to create it",
+                variable, variable
+            ),
+        )
+    })?;
+
+    let new = match provided {
+        Some(secret) => secret,
+        None => {
+            eprint!("Enter new secret for {}: ", display_name(variable));
+            let (style, raw_input, timeout) = prompt_settings(&source);
+            read_prompted_secret(style, raw_input, timeout)?
+        }
+    };
+    if new.expose_secret() == old.expose_secret() {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            Some(variable),
+            format!(
+                "The new value for {} is the same as the current one",
+                variable
+            ),
+        ));
+    }
 
-        cmd.env(var, secret.expose_secret());
+    if let Some(args) = exec_args {
+        let outcome = run_probe(variable, &new, &args, "--exec");
+        if !matches!(&outcome, Ok(status) if status.success()) {
+            let reason = match outcome {
+                Ok(status) => match status.code() {
+                    Some(code) => format!("exited with code {}", code),
+                    None => "was terminated by a signal".to_string(),
+                },
+                Err(err) => format!("could not be run: {:#}", err),
+            };
+            return Err(errors::coded(
+                ErrorCode::Error,
+                Some(variable),
+                format!(
+                    "--exec command {}; kept the previous value of {}",
+                    reason, variable
+                ),
+            ));
+        }
     }
 
-    // Execute the command
-    let mut child = cmd.spawn().context("Failed to spawn child process")?;
+    let earlier_backup = backend.retrieve(&backup_variable)?;
+    if backup {
+        backend
+            .store(&backup_variable, &old)
+            .context("Failed to store the previous value")?;
+    }
+    backend
+        .store(variable, &new)
+        .context("Failed to store secret")?;
 
-    let exit_status = child.wait().context("Failed to wait for child process")?;
+    if backup {
+        println!(
+            "Rotated {}; previous value kept as {}.",
+            variable, backup_variable
+        );
+    } else {
+        if earlier_backup.is_some() {
+            // A stale backup would otherwise look like the value just replaced
+            backend.delete(&backup_variable)?;
+        }
+        println!("Rotated {}.", variable);
+    }
+    Ok(())
+}
 
-    // Defensive: Handle exit codes gracefully, never panic
-    if !exit_status.success() {
-        let code = exit_status.code().unwrap_or(1);
-        // Defensive: Ensure exit code is in valid range
-        let safe_code = if !(0..=255).contains(&code) { 1 } else { code };
-        std::process::exit(safe_code);
+/// Stores every entry of a `.env` or sops-encrypted file; nothing is stored if any entry is invalid
+pub fn import(
+    backend: &mut dyn SecretBackend,
+    path: &Path,
+    sops: bool,
+    dry_run: bool,
+    overwrite: bool,
+) -> Result<()> {
+    let (plaintext, format) = if sops {
+        (import::decrypt_sops(path)?, import::Format::Sops)
+    } else {
+        (import::read_dotenv(path)?, import::Format::Dotenv)
+    };
+    let entries = import::parse_entries(&plaintext, format)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+    drop(plaintext); // Zeroized on drop; the entries hold their own copies
+
+    // Existing keys are only replaced with --overwrite
+    let mut skipped = Vec::new();
+    let mut stored = 0;
+    for (name, secret) in &entries {
+        if !overwrite && backend.retrieve(name)?.is_some() {
+            println!(
+                "Skipped {} (already stored; pass --overwrite to replace it).",
+                name
+            );
+            skipped.push(name.as_str());
+            continue;
+        }
+        if dry_run {
+            println!("Would store {}.", name);
+        } else {
+            backend.store(name, secret)?;
+            println!("Stored secret for {}.", name);
+        }
+        stored += 1;
+    }
+
+    let skipped_note = if skipped.is_empty() {
+        String::new()
+    } else {
+        format!(", skipped {} already stored", skipped.len())
+    };
+    if dry_run {
+        println!(
+            "Dry run: would import {} secret(s) from {}{}; nothing was stored.",
+            stored,
+            path.display(),
+            skipped_note
+        );
+    } else {
+        println!(
+            "Imported {} secret(s) from {}{}.",
+            stored,
+            path.display(),
+            skipped_note
+        );
     }
 
     Ok(())
 }
+
+/// Runs `helm` with mapped secrets as values; piped as values.yaml unless `set_string`
+pub fn helm_args(
+    backend: &dyn SecretBackend,
+    maps: &[String],
+    set_string: bool,
+    helm_args: &[String],
+) -> Result<ExitCode> {
+    let mappings = maps
+        .iter()
+        .map(|map| helm::parse_mapping(map))
+        .collect::<Result<Vec<_>>>()
+        .and_then(|mappings| helm::check_conflicts(&mappings).map(|()| mappings))
+        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+    if helm_args.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No helm arguments given; e.g. -- upgrade --install RELEASE CHART"
+        ));
+    }
+    let argv: Vec<String> = std::iter::once("helm".to_string())
+        .chain(helm_args.iter().cloned())
+        .collect();
+    validate_command_args(&argv).context("Invalid helm arguments")?;
+
+    // Every secret is resolved before helm starts so a typo never half-applies a release
+    let mut secrets = Vec::with_capacity(mappings.len());
+    for mapping in &mappings {
+        let (var, namespace) = split_env_spec(mapping.source);
+        let retrieved = match namespace {
+            Some(namespace) => backend.namespaced(namespace).retrieve(var)?,
+            None => backend.retrieve(var)?,
+        };
+        secrets.push(retrieved.ok_or_else(|| {
+            errors::coded(
+                ErrorCode::SecretNotFound,
+                Some(var),
+                format!("Secret {} not found", var),
+            )
+        })?);
+    }
+    let values: Vec<_> = mappings
+        .iter()
+        .zip(&secrets)
+        .map(|(mapping, secret)| (mapping, secret.expose_secret()))
+        .collect();
+
+    let mut cmd = Command::new("helm");
+    cmd.args(helm_args);
+    let spawn_error = |err: std::io::Error| {
+        let err = match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!("helm not found on PATH"),
+            _ => anyhow::anyhow!("Failed to run helm: {}", err),
+        };
+        errors::tagged(ErrorCode::SpawnFailed, None)(err)
+    };
+
+    let status = if set_string {
+        eprintln!(
+            "Warning: --set-string puts the secrets in helm's arguments, which are visible in process listings"
+        );
+        let args: Vec<_> = values
+            .iter()
+            .map(|(mapping, value)| helm::set_string_arg(mapping, value))
+            .collect();
+        for arg in &args {
+            cmd.arg("--set-string").arg(arg.as_str());
+        }
+        cmd.status().map_err(spawn_error)?
+    } else {
+        let yaml = helm::render_values(&values);
+        cmd.args(["--values", "-"]).stdin(Stdio::piped());
+        let mut child = cmd.spawn().map_err(spawn_error)?;
+        let written = match child.stdin.take() {
+            // Dropping the handle closes the pipe so helm sees end of input
+            Some(mut stdin) => stdin.write_all(yaml.as_bytes()),
+            None => Ok(()),
+        };
+        let status = child.wait().context("Failed to wait for helm")?;
+        // helm exiting before reading its values is reported through its own status
+        if let Err(err) = written {
+            if err.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(anyhow::anyhow!("Failed to pass values to helm: {}", err));
+            }
+        }
+        status
+    };
+
+    Ok(match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+        None => ExitCode::FAILURE,
+    })
+}
+
+/// Scans shell history for inline secrets and verbatim copies of `variables`' values.
+///
+/// Read-only: findings are reported with the sensitive part elided, never removed.
+pub fn history_check(
+    backend: &dyn SecretBackend,
+    variables: &[String],
+    files: &[PathBuf],
+) -> Result<ExitCode> {
+    let mut secrets = Vec::with_capacity(variables.len());
+    for variable in variables {
+        validate_env_var_name(variable)
+            .map_err(errors::tagged(ErrorCode::InvalidInput, Some(variable)))?;
+        let secret = backend.retrieve(variable)?.ok_or_else(|| {
+            errors::coded(
+                ErrorCode::SecretNotFound,
+                Some(variable),
+                format!("Secret {} not found", variable),
+            )
+        })?;
+        secrets.push((variable.as_str(), secret));
+    }
+    let values: Vec<(&str, &str)> = secrets
+        .iter()
+        .map(|(name, secret)| (*name, secret.expose_secret()))
+        .collect();
+
+    let files = if files.is_empty() {
+        history::history_files().map_err(errors::tagged(ErrorCode::InvalidInput, None))?
+    } else {
+        files.to_vec()
+    };
+    if files.is_empty() {
+        println!("No shell history files found.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut total = 0;
+    for path in &files {
+        let text = history::read_history(path)?;
+        let findings = history::scan(&text, &values);
+        if findings.is_empty() {
+            continue;
+        }
+        for finding in &findings {
+            println!(
+                "{}:{}: {}  ({})",
+                path.display(),
+                finding.line,
+                finding.excerpt,
+                finding.reason
+            );
+        }
+        println!("  To clean up, {}", history::cleanup_hint(path));
+        total += findings.len();
+    }
+
+    if total == 0 {
+        println!("No secrets found in {} history file(s).", files.len());
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!(
+            "{} history line(s) may contain secrets; rotate any that were real.",
+            total
+        );
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Prints the secrets as one IDE env block on stdout (`ide-resolve`).
+///
+/// The values leave local-secrets in plain text, so nothing is read from the
+/// backend until the caller has passed `--unsafe-plain-output`.
+pub fn ide_resolve(
+    backend: &dyn SecretBackend,
+    specs: &[String],
+    optional: &[String],
+    format: IdeFormat,
+    acknowledged: bool,
+) -> Result<ExitCode> {
+    if !acknowledged {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            "ide-resolve prints secret values in plain text; pass --unsafe-plain-output to acknowledge"
+                .to_string(),
+        ));
+    }
+    if specs.is_empty() {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            "No variables to resolve; name them or pass --map-file".to_string(),
+        ));
+    }
+    if let Some(spec) = specs.iter().find(|spec| derive::parse_spec(spec).is_some()) {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            format!(
+                "Derived keys are not supported by ide-resolve: {}",
+                display_name(spec)
+            ),
+        ));
+    }
+    crate::security::validate_cli_security(specs, &[], None)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+
+    let secrets = lookup_all(backend, specs, &mut RunStats::default())?;
+    let mut entries = Vec::with_capacity(specs.len());
+    for (spec, secret) in specs.iter().zip(&secrets) {
+        let target = injected_name(spec);
+        match secret {
+            Some(secret) => entries.push((target, secret.expose_secret())),
+            None if optional.iter().any(|name| name == target) => {
+                eprintln!("Skipping optional {}: secret not found", target);
+            }
+            None => {
+                let (var, _) = split_env_spec(source_spec(spec));
+                return Err(errors::coded(
+                    ErrorCode::SecretNotFound,
+                    Some(var),
+                    format!("Secret {} not found", var),
+                ));
+            }
+        }
+    }
+
+    let rendered =
+        ide::render(format, &entries).map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+    print!("{}", rendered.as_str());
+    eprintln!(
+        "Warning: printed {} secret value{} in plain text; do not commit this output",
+        entries.len(),
+        if entries.len() == 1 { "" } else { "s" }
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints where `spec` (`NAME` or `NAME@namespace`) lives in the backend, without its value
+pub fn where_is(backend: &dyn SecretBackend, spec: &str, as_json: bool) -> Result<ExitCode> {
+    let (scoped, variable) = scope_spec(backend, spec)?;
+    let location = scoped.locate(variable)?;
+
+    if as_json {
+        let mut out = String::from("{\"backend\":");
+        json::push_string(&mut out, backend.name());
+        out.push_str(",\"service\":");
+        json::push_string(&mut out, &location.service);
+        out.push_str(",\"account\":");
+        json::push_string(&mut out, &location.account);
+        out.push_str(",\"store\":");
+        json::push_string(&mut out, location.store);
+        out.push_str(&format!(
+            ",\"exists\":{},\"attributes\":{{",
+            location.exists
+        ));
+        for (index, (name, value)) in location.attributes.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            json::push_string(&mut out, name);
+            out.push(':');
+            json::push_string(&mut out, value);
+        }
+        out.push_str("}}");
+        println!("{}", out);
+    } else {
+        println!("backend:  {}", backend.name());
+        println!("service:  {}", location.service);
+        println!("account:  {}", location.account);
+        println!("store:    {}", location.store);
+        println!("exists:   {}", if location.exists { "yes" } else { "no" });
+        for (name, value) in &location.attributes {
+            println!("attribute: {}={}", display_name(name), display_name(value));
+        }
+    }
+
+    Ok(if location.exists {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Attempts each primitive keyring operation on one entry and prints a report
+/// suitable for bug reports. Platform errors are shown raw, hence `--debug`.
+pub fn debug_entry(
+    backend: &dyn SecretBackend,
+    spec: &str,
+    acknowledged: bool,
+) -> Result<ExitCode> {
+    if !acknowledged {
+        return Err(errors::coded(
+            ErrorCode::InvalidInput,
+            None,
+            "debug entry prints raw platform errors; pass --debug to acknowledge".to_string(),
+        ));
+    }
+    let (scoped, variable) = scope_spec(backend, spec)?;
+    let diagnosis = scoped.diagnose(variable)?;
+
+    println!(
+        "local-secrets {} ({}-{})",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH
+    );
+    println!("backend:  {}", backend.name());
+    println!("service:  {}", diagnosis.service);
+    println!("account:  {}", diagnosis.account);
+    println!("target:   {}", diagnosis.target);
+    println!("store:    {}", diagnosis.store);
+    let mut failed = false;
+    for step in &diagnosis.steps {
+        let elapsed = stats::format_duration(step.elapsed);
+        match &step.outcome {
+            Ok(result) => println!("{:<16} ok     {:>9}  {}", step.name, elapsed, result),
+            Err(error) => {
+                failed = true;
+                println!("{:<16} error  {:>9}  {}", step.name, elapsed, error);
+            }
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Runs the command with and without canary values for `candidates` and reports which
+/// variables visibly affect it.
+///
+/// Heuristic only: a variable counts when its canary shows up in the output, or when
+/// setting it alone changes the output or exit code of an otherwise repeatable command.
+pub fn env_diff(candidates: &[String], command_args: &[String]) -> Result<ExitCode> {
+    for name in candidates {
+        validate_env_var_name(name).map_err(errors::tagged(ErrorCode::InvalidInput, Some(name)))?;
+    }
+    validate_command_args(command_args).context("Invalid command arguments")?;
+
+    let canaries: Vec<(&str, String)> = candidates
+        .iter()
+        .map(|name| (name.as_str(), envdiff::canary(name)))
+        .collect();
+    let all: Vec<(&str, &str)> = canaries
+        .iter()
+        .map(|(name, canary)| (*name, canary.as_str()))
+        .collect();
+
+    // Two baseline runs tell output that depends on the variables from output that never repeats
+    let baseline = envdiff::observe(command_args, candidates, &[])?;
+    let repeatable = baseline == envdiff::observe(command_args, candidates, &[])?;
+    if !repeatable {
+        eprintln!(
+            "Warning: the command's output differs between identical runs; only echoed canaries are reported"
+        );
+    }
+    let injected = envdiff::observe(command_args, candidates, &all)?;
+
+    let mut found = Vec::new();
+    for (name, canary) in &all {
+        let evidence = if envdiff::contains(&injected.output, canary) {
+            Some(Evidence::Echoed)
+        } else if repeatable
+            && injected != baseline
+            && envdiff::observe(command_args, candidates, &[(name, canary)])? != baseline
+        {
+            Some(Evidence::ChangesBehaviour)
+        } else {
+            None
+        };
+        if let Some(evidence) = evidence {
+            found.push((*name, evidence));
+        }
+    }
+
+    if found.is_empty() {
+        println!("No candidate variable visibly affected the command.");
+    } else {
+        let width = found
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or_default();
+        for (name, evidence) in &found {
+            println!("{:width$}  {}", name, evidence.describe(), width = width);
+        }
+    }
+    let names: Vec<&str> = found.iter().map(|(name, _)| *name).collect();
+    println!();
+    println!("Suggested manifest entry:");
+    println!("{}", envdiff::manifest_block(&names));
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Run-mode options that shape how the child process is spawned
+#[derive(Default)]
+pub struct RunOptions {
+    /// Unix user the child should run as (`--user`)
+    pub user: Option<String>,
+    /// Unix group the child should run as (`--group`)
+    pub group: Option<String>,
+    /// File-creation mask applied to the child (`--umask`, Unix)
+    pub umask: Option<u32>,
+    /// Warn when the inherited umask lets injected secrets land in group/world-writable files
+    pub warn_umask: bool,
+    /// Resource limits applied to the child (`--limit-mem`, `--limit-cpu`, `--limit-nofile`)
+    pub limits: process::Limits,
+    /// Refuse to start a child whose executable has another SHA-256 (`--verify-child-sha256`)
+    pub verify_child_sha256: Option<String>,
+    /// Fail instead of warning when the child is setuid/setgid (`--deny-privileged-child`)
+    pub deny_privileged_child: bool,
+    /// Merge the login shell's environment beneath the injected secrets (`--login-env`)
+    pub login_env: bool,
+    /// Run `.ps1` targets through PowerShell on Windows (`--windows-script-auto`)
+    pub windows_script_auto: bool,
+    /// Inject binary secrets as a path to a private decoded file (`--decode-to-file`)
+    pub decode_to_file: bool,
+    /// Also write the injected variables to this file for the child (`--env-file-out`)
+    pub env_file_out: Option<PathBuf>,
+    /// Format of the `--env-file-out` file
+    pub env_file_format: EnvFileFormat,
+    /// Print a timing and resource footer to stderr after the child exits (`--stats`)
+    pub stats: bool,
+    /// Print one greppable result line to stderr after the run (`--summary`)
+    pub summary: bool,
+    /// Restart the child when an injected secret changes, polling at this interval (`--watch`)
+    pub watch_interval: Option<Duration>,
+    /// Give the child a private temp directory that is shredded after it exits (`--isolate-tmp`)
+    pub isolate_tmp: bool,
+    /// Encoding of keys derived with `--env NAME=derive:MASTER:INFO` (`--derive-encoding`)
+    pub derive_encoding: DeriveEncoding,
+    /// Injected names that are skipped when their secret is missing (`optional` in `--map-file`)
+    pub optional: Vec<String>,
+    /// Injected names whose prompted value is never saved (`NAME!`, map file `no_save`)
+    pub no_save: Vec<String>,
+    /// Refuse to run when a resolved secret looks blank instead of warning (`--fail-on-empty`)
+    pub fail_on_empty: bool,
+    /// Keep terminal escape sequences in prompted secrets (`--raw-input`)
+    pub raw_input: bool,
+    /// Typing feedback at the prompt (`--prompt-style`)
+    pub prompt_style: PromptStyle,
+    /// Abandon an unanswered prompt after this long (`--prompt-timeout`)
+    pub prompt_timeout: Option<Duration>,
+    /// Run the remaining `--commands` after one fails (`--keep-going`)
+    pub keep_going: bool,
+    /// Replace injected values in the child's stdout and stderr (`run --mask-output`)
+    pub mask_output: bool,
+    /// Stop the child's remaining process tree when it or local-secrets exits
+    pub kill_tree: bool,
+    /// Stop the child once it has run this long (`run --timeout`)
+    pub timeout: Option<Duration>,
+    /// How long a timed-out child gets after SIGTERM before it is killed
+    pub kill_after: Duration,
+    /// Replace `{{VAR}}` in the command's arguments with the secret (`run --substitute-args`)
+    pub substitute_args: bool,
+    /// Template rendered with the secrets and written to the child's stdin
+    /// (`run --stdin-template`), already checked for unknown placeholders
+    pub stdin_template: Option<String>,
+    /// Working directory for the child, already checked (`run --cwd`)
+    pub cwd: Option<PathBuf>,
+    /// Secret used for missing variables in automated tests, from [`test_secret`]
+    pub test_secret: Option<SecretString>,
+}
+
+pub fn run_with_env(
+    backend: &mut dyn SecretBackend,
+    env_vars: &[String],
+    no_save_missing: bool,
+    commands: &[QueuedCommand],
+    options: &RunOptions,
+) -> Result<()> {
+    // Security validation is now performed in main.rs before calling this function
+    // This is part of defense-in-depth strategy
+    let Some((first, rest)) = commands.split_first() else {
+        return Err(anyhow::anyhow!("No command specified"));
+    };
+
+    // Resolve the target identity before touching any secrets so a typo fails fast
+    #[cfg(unix)]
+    let privileges =
+        process::resolve_privileges(options.user.as_deref(), options.group.as_deref())?;
+    #[cfg(not(unix))]
+    process::reject_privileges(options.user.as_deref(), options.group.as_deref())?;
+
+    // Snapshot the login environment once; it is only used for this invocation
+    let login_env = if options.login_env {
+        process::login_environment()?
+    } else {
+        Vec::new()
+    };
+
+    // One private scratch directory for the whole run, kept across --watch restarts
+    let isolated_tmp = if options.isolate_tmp {
+        let dir = TempSecretDir::create_in_temp_dir("tmp")?;
+        #[cfg(unix)]
+        if let Some(privileges) = &privileges {
+            std::os::unix::fs::chown(dir.path(), Some(privileges.uid), Some(privileges.gid))
+                .with_context(|| format!("Failed to hand {} to the child", dir.path().display()))?;
+        }
+        Some(dir)
+    } else {
+        None
+    };
+
+    if !env_vars.is_empty() {
+        eprintln!("Injecting env vars: {:?}", env_vars);
+    }
+
+    // Builds a fresh child command; called again for every --watch restart and queued command
+    let build_command = |command_args: &[String]| -> Result<Command> {
+        let mut cmd = process::build_command(command_args, options.windows_script_auto);
+        if let Some(dir) = &options.cwd {
+            cmd.current_dir(dir);
+            // Shells and build tools trust $PWD, which would still name our own directory
+            #[cfg(unix)]
+            cmd.env("PWD", dir);
+        }
+
+        // Login variables sit beneath the injections: secrets are applied later and
+        // always win, and explicitly requested names are never taken from the shell
+        for (key, value) in &login_env {
+            if !env_vars
+                .iter()
+                .any(|spec| key.as_os_str() == injected_name(spec))
+            {
+                cmd.env(key, value);
+            }
+        }
+
+        if let Some(dir) = &isolated_tmp {
+            for name in ["TMPDIR", "TEMP", "TMP"] {
+                cmd.env(name, dir.path());
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(privileges) = privileges.as_ref().filter(|p| !p.is_current()) {
+            eprintln!(
+                "Dropping privileges for child: uid={} gid={} groups={:?}",
+                privileges.uid, privileges.gid, privileges.groups
+            );
+            process::apply_privileges(&mut cmd, privileges);
+        }
+
+        process::configure_umask(
+            &mut cmd,
+            options.umask,
+            options.warn_umask && !env_vars.is_empty(),
+        )?;
+        process::configure_limits(&mut cmd, &options.limits)
+            .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        if let Some(expected) = &options.verify_child_sha256 {
+            executable::verify_sha256(&cmd, expected)?;
+        }
+        if !env_vars.is_empty() {
+            executable::check_privileged(&cmd, options.deny_privileged_child)?;
+        }
+
+        Ok(cmd)
+    };
+
+    // With --substitute-args the secrets become part of the arguments, so they are
+    // resolved before the command is built; otherwise it is checked before any prompt
+    let prepare = |backend: &mut dyn SecretBackend,
+                   stats: &mut RunStats|
+     -> Result<(Command, InjectedSecrets)> {
+        if !options.substitute_args {
+            let mut cmd = build_command(&first.args)?;
+            let injected =
+                inject_secrets(backend, env_vars, no_save_missing, options, &mut cmd, stats)?;
+            return Ok((cmd, injected));
+        }
+        let mut resolved = Command::new(&first.args[0]);
+        let injected = inject_secrets(
+            backend,
+            env_vars,
+            no_save_missing,
+            options,
+            &mut resolved,
+            stats,
+        )?;
+        let mut cmd = build_command(&substitute_args(&first.args, &resolved)?)?;
+        copy_env(&resolved, &mut cmd);
+        Ok((cmd, injected))
+    };
+
+    let mut stats = RunStats::default();
+    let (mut cmd, mut injected) = prepare(&mut *backend, &mut stats)?;
+
+    // Earlier children (the --login-env shell) must not count towards the figures
+    let usage_before = stats::children_usage();
+    let started = Instant::now();
+
+    // Execute the command
+    let mut masks = options
+        .mask_output
+        .then(|| masking_patterns(&cmd, env_vars));
+    let spawning = otel::span("child.spawn");
+    let mut spawned = spawn_child(&mut cmd, masks.as_ref(), options)?;
+    spawning.end();
+
+    let mut waiting = otel::span("child.wait");
+    let mut first_timed_out = false;
+    let exit_status = match options.watch_interval {
+        Some(interval) => loop {
+            let snapshot = &injected.snapshot;
+            match watch::wait_for_exit_or_change(
+                &mut spawned,
+                backend,
+                env_vars,
+                snapshot,
+                interval,
+            )? {
+                WatchEvent::Exited(status) => break status,
+                WatchEvent::Changed(spec) => {
+                    eprintln!("Secret {} changed; restarting child", spec);
+                    process::terminate(&mut spawned, watch::STOP_GRACE_PERIOD)?;
+                    spawned.finish();
+                    drop(injected);
+
+                    (cmd, injected) = prepare(&mut *backend, &mut stats)?;
+                    masks = options
+                        .mask_output
+                        .then(|| masking_patterns(&cmd, env_vars));
+                    spawned = spawn_child(&mut cmd, masks.as_ref(), options)?;
+                }
+            }
+        },
+        None => {
+            let (status, timed_out) = wait_child(&mut spawned, options)?;
+            first_timed_out = timed_out;
+            status
+        }
+    };
+    spawned.finish();
+    waiting.attr_int("exit_code", exit_status.code().map_or(-1, i64::from));
+    waiting.end();
+    if !first_timed_out {
+        note_limit_exceeded(&exit_status, options, &mut stats);
+    }
+
+    // Queued commands reuse the secrets resolved for the first one instead of asking
+    // the keyring again; a failure stops the queue unless --keep-going is set
+    let mut failure = exit_code(&exit_status, first_timed_out, first);
+    for queued in rest {
+        if failure.is_some() && !options.keep_going {
+            break;
+        }
+        let mut next = if options.substitute_args {
+            build_command(&substitute_args(&queued.args, &cmd)?)?
+        } else {
+            build_command(&queued.args)?
+        };
+        copy_env(&cmd, &mut next);
+        let spawning = otel::span("child.spawn");
+        let mut spawned = spawn_child(&mut next, masks.as_ref(), options)?;
+        spawning.end();
+        let mut waiting = otel::span("child.wait");
+        let (status, timed_out) = wait_child(&mut spawned, options)?;
+        spawned.finish();
+        waiting.attr_int("exit_code", status.code().map_or(-1, i64::from));
+        waiting.end();
+        if !timed_out {
+            note_limit_exceeded(&status, options, &mut stats);
+        }
+        failure = failure.or(exit_code(&status, timed_out, queued));
+    }
+
+    if options.stats {
+        let usage = stats::usage_since(usage_before, stats::children_usage());
+        stats.record_child(started.elapsed(), usage);
+        eprint!("{}", stats.footer());
+    }
+
+    if options.summary {
+        eprintln!(
+            "{}",
+            stats::summary_line(
+                injected.injected,
+                injected.skipped,
+                backend.name(),
+                failure.unwrap_or(0),
+                started.elapsed(),
+            )
+        );
+    }
+
+    // Remove transient secret files before exiting (process::exit skips destructors)
+    drop(injected);
+    drop(isolated_tmp);
+
+    // Defensive: Handle exit codes gracefully, never panic
+    if let Some(code) = failure {
+        otel::flush();
+        std::process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// Gives `to` every variable set or removed on `from`
+fn copy_env(from: &Command, to: &mut Command) {
+    for (key, value) in from.get_envs() {
+        match value {
+            Some(value) => to.env(key, value),
+            None => to.env_remove(key),
+        };
+    }
+}
+
+/// Refuses `{{VAR}}` placeholders that name no injected variable, before anything
+/// is resolved, and warns that the others will be visible in process listings
+pub fn check_placeholders(commands: &[QueuedCommand], env_vars: &[String]) -> Result<()> {
+    let mut named: Vec<String> = Vec::new();
+    for command in commands {
+        for arg in &command.args {
+            process::expand_placeholders(arg, "the command", |name| {
+                check_listed(name, env_vars, "the command")?;
+                if !named.iter().any(|seen| seen == name) {
+                    named.push(name.to_string());
+                }
+                Ok("")
+            })?;
+        }
+    }
+    for name in &named {
+        errors::warn(
+            "secret_in_arguments",
+            Some(name),
+            format!(
+                "--substitute-args puts {} in the command line, where other local users can read it in process listings",
+                name
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless the placeholder `name` found in `place` is an injected variable
+fn check_listed(name: &str, env_vars: &[String], place: &str) -> Result<()> {
+    if env_vars.iter().any(|spec| injected_name(spec) == name) {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "{{{{{}}}}} in {} does not name an --env variable",
+        name,
+        place
+    ))
+}
+
+/// The value `resolved` injects as `name`, to substitute for `{{name}}`
+fn injected_value<'c>(resolved: &'c Command, name: &str) -> Result<&'c str> {
+    resolved
+        .get_envs()
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| value)
+        .and_then(|value| value.to_str())
+        .ok_or_else(|| anyhow::anyhow!("No value to substitute for {{{{{}}}}}", name))
+}
+
+/// `args` with each `{{VAR}}` replaced by the value `resolved` injects as VAR
+fn substitute_args(args: &[String], resolved: &Command) -> Result<Zeroizing<Vec<String>>> {
+    let mut substituted = Zeroizing::new(Vec::with_capacity(args.len()));
+    for arg in args {
+        let expanded = process::expand_placeholders(arg, "the command", |name| {
+            injected_value(resolved, name)
+        })?;
+        substituted.push(expanded.to_string());
+    }
+    Ok(substituted)
+}
+
+/// Reads a `--stdin-template` and checks that every placeholder in it names one of
+/// `env_vars`, before anything is resolved
+pub fn load_stdin_template(path: &Path, env_vars: &[String]) -> Result<String> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open --stdin-template {}", path.display()))?;
+    let what = format!("--stdin-template {}", path.display());
+    let bytes = read_bounded(file, &what)?;
+    if bytes.len() > MAX_SECRET_BYTES {
+        return Err(anyhow::anyhow!("{} is larger than 1MB", what));
+    }
+    let template = std::str::from_utf8(&bytes)
+        .map_err(|_| anyhow::anyhow!("{} is not valid UTF-8", what))?
+        .to_string();
+    process::expand_placeholders(&template, &what, |name| {
+        check_listed(name, env_vars, &what)?;
+        Ok("")
+    })?;
+    Ok(template)
+}
+
+/// A running child and what has to be cleaned up once it exits
+struct Spawned {
+    child: std::process::Child,
+    relays: Option<mask::Relays>,
+    tree: Option<tree::ProcessTree>,
+    feeder: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Spawned {
+    /// Stops what is left of the child's process tree, then waits for its masked output.
+    ///
+    /// In that order, a grandchild still holding the pipes cannot keep us waiting.
+    fn finish(self) {
+        tree::defer_to_interrupt();
+        drop(self.tree);
+        if let Some(feeder) = self.feeder {
+            let _ = feeder.join();
+        }
+        if let Some(relays) = self.relays {
+            relays.finish();
+        }
+    }
+
+    /// Stops the process tree once the child has exited but before it is reaped,
+    /// while its process group cannot have been handed to anyone else
+    #[cfg(unix)]
+    fn stop_tree_if_exited(&mut self, block: bool) -> std::io::Result<bool> {
+        if self.tree.is_none() {
+            return Ok(true);
+        }
+        let exited = tree::leader_exited(&self.child, block)?;
+        if exited {
+            self.tree = None;
+        }
+        Ok(exited)
+    }
+
+    #[cfg(not(unix))]
+    fn stop_tree_if_exited(&mut self, _block: bool) -> std::io::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl process::Waitable for Spawned {
+    fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.child.kill()
+    }
+
+    fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+        if !self.stop_tree_if_exited(false)? {
+            return Ok(None);
+        }
+        self.child.try_wait()
+    }
+
+    fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        self.stop_tree_if_exited(true)?;
+        self.child.wait()
+    }
+}
+
+/// Spawns a child, relaying its output through `masks` when `--mask-output` is on,
+/// tracking its process tree for `--kill-tree` and feeding it `--stdin-template`
+fn spawn_child(
+    cmd: &mut Command,
+    masks: Option<&mask::Patterns>,
+    options: &RunOptions,
+) -> Result<Spawned> {
+    if masks.is_some() {
+        mask::Patterns::capture(cmd);
+    }
+    let input = match &options.stdin_template {
+        Some(template) => {
+            let rendered = process::expand_placeholders(template, "--stdin-template", |name| {
+                injected_value(cmd, name)
+            })?;
+            cmd.stdin(Stdio::piped());
+            Some(rendered)
+        }
+        None => None,
+    };
+    let own_tree = options.kill_tree && tree::prepare(cmd);
+    let mut child = cmd
+        .spawn()
+        .context("Failed to spawn child process")
+        .map_err(errors::tagged(ErrorCode::SpawnFailed, None))?;
+    let relays = masks.map(|masks| masks.relay(&mut child));
+    let tree = if own_tree {
+        tree::ProcessTree::adopt(&child)
+    } else {
+        None
+    };
+    // Written from a thread so a child that talks before it reads cannot deadlock us;
+    // closing the pipe gives it EOF, and the rendered text is zeroized once written
+    let feeder = input.zip(child.stdin.take()).map(|(input, mut stdin)| {
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        })
+    });
+    Ok(Spawned {
+        child,
+        relays,
+        tree,
+        feeder,
+    })
+}
+
+/// The values injected into `cmd` for `env_vars`, as patterns to mask
+fn masking_patterns(cmd: &Command, env_vars: &[String]) -> mask::Patterns {
+    mask::Patterns::new(
+        cmd.get_envs()
+            .filter(|(key, _)| env_vars.iter().any(|spec| *key == injected_name(spec)))
+            .filter_map(|(_, value)| value.and_then(|value| value.to_str()))
+            .map(str::as_bytes),
+    )
+}
+
+/// Waits for the child, stopping it once `--timeout` has elapsed; true when it had to be
+fn wait_child(
+    child: &mut impl process::Waitable,
+    options: &RunOptions,
+) -> Result<(std::process::ExitStatus, bool)> {
+    let Some(timeout) = options.timeout else {
+        let status = child.wait().context("Failed to wait for child process")?;
+        return Ok((status, false));
+    };
+    if let Some(status) = process::wait_until(child, Instant::now() + timeout)? {
+        return Ok((status, false));
+    }
+    eprintln!("Timed out after {}s; stopping the child", timeout.as_secs());
+    Ok((process::terminate(child, options.kill_after)?, true))
+}
+
+/// Tells the user when the child's death looks like one of its `--limit-*` limits
+fn note_limit_exceeded(
+    status: &std::process::ExitStatus,
+    options: &RunOptions,
+    stats: &mut RunStats,
+) {
+    if let Some(note) = process::limit_exceeded(status, &options.limits) {
+        eprintln!("{}", note);
+        stats.record_limit(note);
+    }
+}
+
+/// Exit code to report for a failed child, or `None` when it succeeded
+fn exit_code(
+    status: &std::process::ExitStatus,
+    timed_out: bool,
+    command: &QueuedCommand,
+) -> Option<i32> {
+    if timed_out {
+        if command.line.is_some() {
+            eprintln!("{} timed out", command.label());
+        }
+        return Some(TIMEOUT_EXIT_CODE);
+    }
+    if status.success() {
+        return None;
+    }
+    // Killed by a signal: report 128 + N like a shell, so scripts can tell a
+    // SIGKILL (137) or SIGSEGV (139) apart from an ordinary failure
+    if let Some((code, note)) = process::signal_exit(status) {
+        eprintln!("{}", note);
+        if command.line.is_some() {
+            eprintln!("{} failed with exit code {}", command.label(), code);
+        }
+        return Some(code);
+    }
+    let code = status.code().unwrap_or(1);
+    // Defensive: Ensure exit code is in valid range
+    let safe_code = if !(0..=255).contains(&code) { 1 } else { code };
+    if command.line.is_some() {
+        eprintln!("{} failed with exit code {}", command.label(), safe_code);
+    }
+    Some(safe_code)
+}
+
+/// Transient files backing one child's environment; removed when dropped
+struct InjectedSecrets {
+    // Held only so the files live exactly as long as the child needs them
+    _env_file: Option<TempSecretFile>,
+    _decoded_files: Vec<TempSecretFile>,
+    snapshot: Vec<Option<u64>>,
+    /// Variables placed in the child's environment, for `--summary`
+    injected: usize,
+    /// Optional variables left out because their secret was not found
+    skipped: usize,
+}
+
+/// What the backend said about one `--env` variable
+enum Lookup {
+    Found(SecretString),
+    Missing,
+    Failed(anyhow::Error),
+}
+
+impl Lookup {
+    fn describe(&self) -> String {
+        match self {
+            Lookup::Found(_) => "ok".to_string(),
+            Lookup::Missing => "missing".to_string(),
+            Lookup::Failed(err) => format!(
+                "{} ({:#})",
+                errors::classify(err).0.as_str().replace('_', " "),
+                err
+            ),
+        }
+    }
+}
+
+/// Looks up every variable once and fails with all outcomes listed if any lookup errored.
+///
+/// A backend error on one variable no longer hides which of the others were fine.
+/// Missing secrets are returned as `None` for the caller to prompt for or skip.
+fn lookup_all(
+    backend: &dyn SecretBackend,
+    env_vars: &[String],
+    stats: &mut RunStats,
+) -> Result<Vec<Option<SecretString>>> {
+    let mut lookups = Vec::with_capacity(env_vars.len());
+    for spec in env_vars {
+        let (var, namespace) = split_env_spec(source_spec(spec));
+        let mut resolving = otel::span("resolve");
+        resolving.attr_str("variable", injected_name(spec));
+        resolving.attr_str("backend", backend.name());
+        let started = Instant::now();
+        let result = match namespace {
+            Some(namespace) => backend.namespaced(namespace).retrieve(var),
+            None => backend.retrieve(var),
+        };
+        let lookup = match result {
+            Ok(Some(secret)) => Lookup::Found(secret),
+            Ok(None) => Lookup::Missing,
+            Err(err) => Lookup::Failed(err),
+        };
+        if !matches!(lookup, Lookup::Failed(_)) {
+            stats.record_lookup(
+                backend.name(),
+                started.elapsed(),
+                matches!(lookup, Lookup::Found(_)),
+            );
+        }
+        resolving.attr_str(
+            "outcome",
+            match lookup {
+                Lookup::Found(_) => "found",
+                Lookup::Missing => "missing",
+                Lookup::Failed(_) => "error",
+            },
+        );
+        resolving.end();
+        lookups.push(lookup);
+    }
+
+    let failed: Vec<&str> = env_vars
+        .iter()
+        .zip(&lookups)
+        .filter(|(_, lookup)| matches!(lookup, Lookup::Failed(_)))
+        .map(|(spec, _)| injected_name(spec))
+        .collect();
+    if !failed.is_empty() {
+        let report: Vec<String> = env_vars
+            .iter()
+            .zip(&lookups)
+            .map(|(spec, lookup)| format!("{}: {}", injected_name(spec), lookup.describe()))
+            .collect();
+        return Err(errors::coded(
+            ErrorCode::BackendError,
+            (failed.len() == 1).then(|| failed[0]),
+            format!(
+                "Failed to look up {} of {} secrets:\n  {}",
+                failed.len(),
+                lookups.len(),
+                report.join("\n  ")
+            ),
+        ));
+    }
+
+    Ok(lookups
+        .into_iter()
+        .map(|lookup| match lookup {
+            Lookup::Found(secret) => Some(secret),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Resolves every `--env` secret (prompting for missing ones) into `cmd`
+fn inject_secrets(
+    backend: &mut dyn SecretBackend,
+    env_vars: &[String],
+    no_save_missing: bool,
+    options: &RunOptions,
+    cmd: &mut Command,
+    stats: &mut RunStats,
+) -> Result<InjectedSecrets> {
+    // Decoded binary secrets live until the child exits
+    let mut decoded_files = Vec::new();
+    // Injected values, kept only when they must also be written to an env file
+    let mut env_file_entries: Vec<(String, String)> = Vec::new();
+    // What the backend holds for each variable, so --watch can notice changes
+    let mut snapshot = Vec::new();
+    // Prompted values deliberately kept out of the backend, for the summary
+    let mut unsaved = Vec::new();
+    let mut skipped = 0;
+
+    // Every variable is looked up before anything is prompted for or injected
+    let retrieved_all = lookup_all(backend, env_vars, stats)?;
+
+    // Inject environment variables
+    for (spec, retrieved) in env_vars.iter().zip(retrieved_all) {
+        // `NAME=derive:MASTER:INFO` injects a key derived from MASTER instead of MASTER itself
+        let derivation = derive::parse_spec(spec);
+        // `TARGET=SOURCE` injects SOURCE as TARGET; `NAME@namespace` reads another namespace
+        let target = injected_name(spec);
+        let (var, namespace) = split_env_spec(source_spec(spec));
+
+        // Optional names are left out of the child's environment instead of prompted for
+        if retrieved.is_none() && options.optional.iter().any(|name| name == target) {
+            eprintln!("Skipping optional {}: secret {} not found", target, var);
+            skipped += 1;
+            if options.watch_interval.is_some() {
+                snapshot.push(None);
+            }
+            continue;
+        }
+
+        // A prompted secret is saved unless --no-save-missing or its own `!` marker says not to
+        let marked_no_save = options.no_save.iter().any(|name| name == target);
+        let save_missing = !no_save_missing && !marked_no_save;
+        if retrieved.is_none() && namespace.is_none() && marked_no_save {
+            unsaved.push(target.to_string());
+        }
+        // A missing secret is in the backend afterwards only if it is saved
+        let mut held = retrieved.is_some() || save_missing;
+
+        let secret = match (retrieved, namespace) {
+            (Some(secret), Some(namespace)) => {
+                eprintln!("Using {} from namespace '{}'", var, namespace);
+                secret
+            }
+            (Some(secret), None) => secret,
+            (None, Some(namespace)) => {
+                // Cross-namespace references are read-only: never prompt for or store them
+                return Err(errors::coded(
+                    ErrorCode::SecretNotFound,
+                    Some(var),
+                    format!("Secret {} not found in namespace '{}'", var, namespace),
+                ));
+            }
+            (None, None) => {
+                // Secret not found, handle based on flags
+                if let Some(secret) = &options.test_secret {
+                    // Test mode - use provided test secret
+                    eprintln!("Enter secret for missing {}: ", display_name(var));
+                    stats.record_prompt();
+                    let secret = secret.clone();
+
+                    if save_missing {
+                        backend.store(var, &secret)?;
+                        eprintln!("Stored secret for {}.", var);
+                    }
+
+                    secret
+                } else if env::var("LOCAL_SECRETS_TEST_MODE").is_ok() {
+                    // Test mode but no test secret provided - this should fail
+                    return Err(errors::coded(
+                        ErrorCode::SecretNotFound,
+                        Some(var),
+                        format!("Secret {} not found", var),
+                    ));
+                } else {
+                    // Production mode - prompt user
+                    eprint!("Enter secret for missing {}: ", display_name(var));
+                    stats.record_prompt();
+                    let secret = read_prompted_secret(
+                        options.prompt_style,
+                        options.raw_input,
+                        options.prompt_timeout,
+                    )?;
+
+                    if save_missing && confirm_save(var)? {
+                        backend.store(var, &secret)?;
+                        eprintln!("Stored secret for {}.", var);
+                    } else {
+                        held = false;
+                    }
+
+                    secret
+                }
+            }
+        };
+
+        if let Some(reason) = blank_value_reason(secret.expose_secret()) {
+            if options.fail_on_empty {
+                return Err(errors::coded(
+                    ErrorCode::InvalidInput,
+                    Some(var),
+                    format!("Secret {} looks blank ({})", var, reason),
+                ));
+            }
+            eprintln!(
+                "Warning: secret {} looks blank ({}); pass --fail-on-empty to stop instead",
+                var, reason
+            );
+        }
+
+        if options.watch_interval.is_some() {
+            snapshot.push(held.then(|| watch::fingerprint(&secret)));
+        }
+
+        let value = match (
+            &derivation,
+            binary::envelope_payload(secret.expose_secret()),
+        ) {
+            // The master never reaches the child; only the derived key does
+            (Some(derivation), payload) => {
+                let derived = match payload {
+                    Some(payload) => derive::derive_key(
+                        &binary::decode_payload(payload)?,
+                        derivation.info,
+                        options.derive_encoding,
+                    )?,
+                    None => derive::derive_key(
+                        secret.expose_secret().as_bytes(),
+                        derivation.info,
+                        options.derive_encoding,
+                    )?,
+                };
+                derived.to_string()
+            }
+            (None, Some(payload)) if options.decode_to_file => {
+                let file = binary::write_decoded_file(var, payload)?;
+                let path = file.path().to_string_lossy().into_owned();
+                decoded_files.push(file);
+                path
+            }
+            (None, Some(payload)) => payload.to_string(),
+            (None, None) => secret.expose_secret().to_string(),
+        };
+        cmd.env(target, &value);
+
+        if options.env_file_out.is_some() {
+            env_file_entries.push((target.to_string(), value));
+        } else {
+            let mut value = value;
+            value.zeroize(); // Zero out the injected copy from memory
+        }
+    }
+
+    // Written immediately before spawn and removed as soon as the child exits
+    let env_file = match &options.env_file_out {
+        Some(path) => {
+            let result = envfile::write(path, options.env_file_format, &env_file_entries);
+            for (_, value) in env_file_entries.iter_mut() {
+                value.zeroize(); // Zero out the rendered copies from memory
+            }
+            let file = result?;
+            cmd.env(ENV_FILE_VAR, file.path());
+            Some(file)
+        }
+        None => None,
+    };
+
+    if !unsaved.is_empty() {
+        eprintln!("Not saved (marked no-save): {}", unsaved.join(", "));
+    }
+
+    Ok(InjectedSecrets {
+        _env_file: env_file,
+        _decoded_files: decoded_files,
+        snapshot,
+        injected: env_vars.len() - skipped,
+        skipped,
+    })
+}
+
+/// `__selftest echo-env VAR`: prints the variable without a trailing newline
+pub fn selftest_echo_env(variable: &str) -> ExitCode {
+    match env::var_os(variable) {
+        Some(value) => {
+            let mut stdout = std::io::stdout();
+            if stdout.write_all(value.as_encoded_bytes()).is_err() || stdout.flush().is_err() {
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("missing env: {}", variable);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// `__selftest sleep SECS`: a long-running child for timeout and signal tests
+pub fn selftest_sleep(seconds: u64) -> ExitCode {
+    std::thread::sleep(std::time::Duration::from_secs(seconds));
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{EntryDiagnosis, EntryLocation};
+
+    /// In-memory backend whose lookups fail for the names in `failing`
+    #[derive(Clone, Default)]
+    struct MockBackend {
+        values: Vec<(String, String)>,
+        failing: Vec<String>,
+    }
+
+    impl SecretBackend for MockBackend {
+        fn store(&mut self, key: &str, value: &SecretString) -> Result<()> {
+            self.values.retain(|(name, _)| name != key);
+            self.values
+                .push((key.to_string(), value.expose_secret().to_string()));
+            Ok(())
+        }
+
+        fn retrieve(&self, key: &str) -> Result<Option<SecretString>> {
+            if self.failing.iter().any(|name| name == key) {
+                return Err(anyhow::anyhow!("Platform failure: locked collection"));
+            }
+            Ok(self
+                .values
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| SecretString::new(value.clone().into())))
+        }
+
+        fn delete(&mut self, key: &str) -> Result<bool> {
+            if self.failing.iter().any(|name| name == key) {
+                return Err(anyhow::anyhow!("Platform failure: locked collection"));
+            }
+            let before = self.values.len();
+            self.values.retain(|(name, _)| name != key);
+            Ok(self.values.len() != before)
+        }
+
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn namespaced(&self, _namespace: &str) -> Box<dyn SecretBackend> {
+            Box::new(self.clone())
+        }
+
+        fn locate(&self, _key: &str) -> Result<EntryLocation> {
+            Err(anyhow::anyhow!("not supported by the mock backend"))
+        }
+
+        fn diagnose(&self, _key: &str) -> Result<EntryDiagnosis> {
+            Err(anyhow::anyhow!("not supported by the mock backend"))
+        }
+    }
+
+    fn specs(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_save_answer() {
+        assert!(save_answer("y\n", false));
+        assert!(save_answer(" YES \r\n", false));
+        assert!(!save_answer("n\n", true));
+        assert!(!save_answer("no\n", true));
+        // Default accept
+        assert!(save_answer("\n", true));
+        assert!(!save_answer("\n", false));
+        assert!(!save_answer("maybe\n", true));
+    }
+
+    #[test]
+    fn test_nothing_is_asked_in_ci_or_without_a_terminal() {
+        assert!(can_ask(true, false));
+        assert!(!can_ask(true, true));
+        assert!(!can_ask(false, false));
+    }
+
+    #[test]
+    fn test_import_skips_stored_keys_unless_overwriting() {
+        let dir = std::env::temp_dir().join(format!("local-secrets-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".env");
+        std::fs::write(&path, "OLD=new-value\nexport FRESH=\"fresh value\"\n").unwrap();
+        let stored = |backend: &MockBackend, name: &str| {
+            backend
+                .retrieve(name)
+                .unwrap()
+                .map(|value| value.expose_secret().to_string())
+        };
+
+        let mut backend = MockBackend {
+            values: vec![("OLD".to_string(), "old-value".to_string())],
+            failing: Vec::new(),
+        };
+        import(&mut backend, &path, false, true, false).unwrap();
+        assert_eq!(backend.values.len(), 1);
+
+        import(&mut backend, &path, false, false, false).unwrap();
+        assert_eq!(stored(&backend, "OLD").as_deref(), Some("old-value"));
+        assert_eq!(stored(&backend, "FRESH").as_deref(), Some("fresh value"));
+
+        import(&mut backend, &path, false, false, true).unwrap();
+        assert_eq!(stored(&backend, "OLD").as_deref(), Some("new-value"));
+        assert_eq!(backend.values.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_writes_only_the_value() {
+        let backend = MockBackend {
+            values: vec![("TOKEN".to_string(), "s3cr3t".to_string())],
+            failing: Vec::new(),
+        };
+        let mut out = Vec::new();
+        write_secret(&backend, "TOKEN", false, &mut out).unwrap();
+        assert_eq!(out, b"s3cr3t");
+
+        let mut out = Vec::new();
+        write_secret(&backend, "TOKEN@team", true, &mut out).unwrap();
+        assert_eq!(out, b"s3cr3t\n");
+
+        let mut out = Vec::new();
+        let err = write_secret(&backend, "MISSING", false, &mut out).unwrap_err();
+        assert_eq!(
+            errors::classify(&err),
+            (ErrorCode::SecretNotFound, Some("MISSING"))
+        );
+        assert_eq!(format!("{:#}", err), "Secret MISSING not found");
+        assert!(out.is_empty());
+
+        assert!(may_print(false, false));
+        assert!(!may_print(true, false));
+        assert!(may_print(true, true));
+    }
+
+    #[test]
+    fn test_lookup_all_reports_every_outcome_when_one_fails() {
+        let backend = MockBackend {
+            values: vec![("A".to_string(), "a".to_string())],
+            failing: vec!["C".to_string()],
+        };
+        let err = lookup_all(&backend, &specs(&["A", "B", "C"]), &mut RunStats::default())
+            .err()
+            .unwrap();
+        assert_eq!(errors::classify(&err), (ErrorCode::BackendError, Some("C")));
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to look up 1 of 3 secrets:\n  A: ok\n  B: missing\n  C: error (Platform failure: locked collection)"
+        );
+    }
+
+    #[test]
+    fn test_lookup_all_returns_found_and_missing_when_nothing_fails() {
+        let backend = MockBackend {
+            values: vec![("A".to_string(), "a".to_string())],
+            failing: Vec::new(),
+        };
+        let found = lookup_all(
+            &backend,
+            &specs(&["ALIAS=A", "B"]),
+            &mut RunStats::default(),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].as_ref().unwrap().expose_secret(), "a");
+        assert!(found[1].is_none());
+    }
+
+    fn rotate_with(
+        backend: &mut MockBackend,
+        new: &str,
+        backup: bool,
+        exec: Option<&str>,
+    ) -> Result<()> {
+        rotate(
+            backend,
+            "API_KEY",
+            Some(SecretString::new(new.into())),
+            SecretSource::Prompt {
+                style: PromptStyle::Hidden,
+                raw_input: false,
+                timeout: None,
+            },
+            backup,
+            exec,
+        )
+    }
+
+    fn value(backend: &MockBackend, name: &str) -> Option<String> {
+        backend
+            .values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    #[test]
+    fn rotate_keeps_previous_value_unless_no_backup() {
+        let mut backend = MockBackend {
+            values: vec![("API_KEY".to_string(), "old".to_string())],
+            failing: Vec::new(),
+        };
+        rotate_with(&mut backend, "new", true, None).unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("new"));
+        assert_eq!(value(&backend, "API_KEY__previous").as_deref(), Some("old"));
+
+        // --no-backup also drops the backup left by the earlier rotation
+        rotate_with(&mut backend, "newer", false, None).unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("newer"));
+        assert_eq!(value(&backend, "API_KEY__previous"), None);
+    }
+
+    #[test]
+    fn rotate_rejects_missing_secret_and_unchanged_value() {
+        let mut backend = MockBackend::default();
+        let err = rotate_with(&mut backend, "new", true, None).unwrap_err();
+        assert!(err.to_string().contains("Secret API_KEY not found"));
+        assert!(backend.values.is_empty());
+
+        backend
+            .values
+            .push(("API_KEY".to_string(), "same".to_string()));
+        let err = rotate_with(&mut backend, "same", true, None).unwrap_err();
+        assert!(err.to_string().contains("same as the current one"));
+        assert_eq!(backend.values.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rotate_stores_nothing_when_exec_fails() {
+        let check = r#"sh -c 'test "$API_KEY" = good'"#;
+        let mut backend = MockBackend {
+            values: vec![
+                ("API_KEY".to_string(), "old".to_string()),
+                ("API_KEY__previous".to_string(), "older".to_string()),
+            ],
+            failing: Vec::new(),
+        };
+        let err = rotate_with(&mut backend, "bad", true, Some(check)).unwrap_err();
+        assert!(err.to_string().contains("exited with code 1"));
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("old"));
+        assert_eq!(
+            value(&backend, "API_KEY__previous").as_deref(),
+            Some("older")
+        );
+
+        rotate_with(&mut backend, "good", true, Some(check)).unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("good"));
+        assert_eq!(value(&backend, "API_KEY__previous").as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn store_modes_respect_existing_entries() {
+        let source = || SecretSource::Prompt {
+            style: PromptStyle::Hidden,
+            raw_input: false,
+            timeout: None,
+        };
+        let mut backend = MockBackend::default();
+
+        let err = store_with_options(
+            &mut backend,
+            "API_KEY",
+            Some("first"),
+            source(),
+            StoreMode::UpdateOnly,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        assert!(backend.values.is_empty());
+
+        store_with_options(
+            &mut backend,
+            "API_KEY",
+            Some("first"),
+            source(),
+            StoreMode::IfMissing,
+        )
+        .unwrap();
+        store_with_options(
+            &mut backend,
+            "API_KEY",
+            Some("second"),
+            source(),
+            StoreMode::IfMissing,
+        )
+        .unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("first"));
+
+        store_with_options(
+            &mut backend,
+            "API_KEY",
+            Some("third"),
+            source(),
+            StoreMode::UpdateOnly,
+        )
+        .unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("third"));
+    }
+
+    #[test]
+    fn store_many_validates_every_name_before_storing() {
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let prompt = || SecretSource::Prompt {
+            style: PromptStyle::Hidden,
+            raw_input: false,
+            timeout: None,
+        };
+        let secret = || Some(SecretString::new("value".into()));
+        let mut backend = MockBackend::default();
+
+        assert!(store_many(
+            &mut backend,
+            &names(&["FIRST", "1BAD"]),
+            secret(),
+            prompt(),
+            StoreMode::Upsert
+        )
+        .is_err());
+        let err = store_many(
+            &mut backend,
+            &names(&["FIRST", "FIRST"]),
+            secret(),
+            prompt(),
+            StoreMode::Upsert,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("listed more than once"));
+        assert!(backend.values.is_empty());
+
+        backend
+            .values
+            .push(("SECOND".to_string(), "kept".to_string()));
+        store_many(
+            &mut backend,
+            &names(&["FIRST", "SECOND", "THIRD"]),
+            secret(),
+            prompt(),
+            StoreMode::IfMissing,
+        )
+        .unwrap();
+        assert_eq!(value(&backend, "FIRST").as_deref(), Some("value"));
+        assert_eq!(value(&backend, "SECOND").as_deref(), Some("kept"));
+        assert_eq!(value(&backend, "THIRD").as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn delete_reports_each_name_and_fails_only_on_backend_errors_when_ignoring_missing() {
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let mut backend = MockBackend {
+            values: vec![
+                ("FIRST".to_string(), "1".to_string()),
+                ("SECOND".to_string(), "2".to_string()),
+            ],
+            failing: vec!["LOCKED".to_string()],
+        };
+
+        // A bad name anywhere in the list stops everything before the first delete
+        assert!(delete(&mut backend, &names(&["FIRST", "1BAD"]), false).is_err());
+        assert_eq!(backend.values.len(), 2);
+
+        let err = delete(&mut backend, &names(&["FIRST", "MISSING"]), false).unwrap_err();
+        assert!(err.to_string().contains("Not found: MISSING"));
+        assert_eq!(value(&backend, "FIRST"), None);
+
+        delete(&mut backend, &names(&["MISSING", "SECOND"]), true).unwrap();
+        assert!(backend.values.is_empty());
+
+        let err = delete(&mut backend, &names(&["MISSING", "LOCKED"]), true).unwrap_err();
+        assert!(err.to_string().contains("Failed to delete LOCKED"));
+    }
+
+    #[test]
+    fn fingerprint_is_the_sha256_prefix() {
+        assert_eq!(fingerprint(&SecretString::new("abc".into())), "ba7816bf");
+    }
+
+    #[test]
+    fn edit_requires_an_existing_secret() {
+        let prompt = || SecretSource::Prompt {
+            style: PromptStyle::Hidden,
+            raw_input: false,
+            timeout: None,
+        };
+        let mut backend = MockBackend::default();
+        let err = edit(
+            &mut backend,
+            "API_KEY",
+            Some(SecretString::new("new".into())),
+            prompt(),
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        assert!(backend.values.is_empty());
+
+        backend
+            .values
+            .push(("API_KEY".to_string(), "old".to_string()));
+        edit(
+            &mut backend,
+            "API_KEY",
+            Some(SecretString::new("new".into())),
+            prompt(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(value(&backend, "API_KEY").as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn read_text_file_trims_one_newline_only_when_asked() {
+        let dir = std::env::temp_dir().join(format!("local-secrets-text-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("secret.json");
+        let read =
+            |trim| read_text_file(&path, trim).map(|secret| secret.expose_secret().to_string());
+
+        std::fs::write(&path, "{\"key\": 1}\n\n").unwrap();
+        assert_eq!(read(false).unwrap(), "{\"key\": 1}\n\n");
+        assert_eq!(read(true).unwrap(), "{\"key\": 1}\n");
+
+        std::fs::write(&path, "\r\n").unwrap();
+        assert!(read(true).unwrap_err().to_string().contains("is empty"));
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+        assert!(read(false).unwrap_err().to_string().contains("--binary"));
+        std::fs::write(&path, vec![b'a'; MAX_SECRET_BYTES + 1]).unwrap();
+        assert!(read(false).unwrap_err().to_string().contains("too long"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(read(false)
+            .unwrap_err()
+            .to_string()
+            .contains("Failed to open secret file"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_text_file_reads_from_a_fifo() {
+        let dir = std::env::temp_dir().join(format!("local-secrets-fifo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pipe");
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        let writer_path = path.clone();
+        let writer = std::thread::spawn(move || std::fs::write(writer_path, "piped\n").unwrap());
+        let secret = read_text_file(&path, true).unwrap();
+        writer.join().unwrap();
+        assert_eq!(secret.expose_secret(), "piped");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn text_secret_strips_at_most_one_newline() {
+        let text = |bytes: &[u8], trim| {
+            text_secret(bytes, "standard input", trim)
+                .map(|secret| secret.expose_secret().to_string())
+        };
+        assert_eq!(text(b"token\n", true).unwrap(), "token");
+        assert_eq!(text(b"token\r\n", true).unwrap(), "token");
+        assert_eq!(text(b"token\n\n", true).unwrap(), "token\n");
+        assert_eq!(text(b"token\n", false).unwrap(), "token\n");
+        assert!(text(b"", false)
+            .unwrap_err()
+            .to_string()
+            .contains("standard input is empty"));
+        assert!(text(b"nul\0byte", false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_probe_expands_placeholders_like_run_mode() {
+        let secret = SecretString::from("abc".to_string());
+        let probe = |script: &str| {
+            let args: Vec<String> = ["sh", "-c", script, "sh", "{{TOKEN}}", r"\{\{TOKEN}}"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+            run_probe("TOKEN", &secret, &args, "--probe").unwrap()
+        };
+        assert!(probe(r#"test "$1" = abc && test "$2" = '\{\{TOKEN}}'"#).success());
+        assert!(!probe(r#"test "$1" = '\{\{TOKEN}}'"#).success());
+
+        let unknown = vec!["curl".to_string(), "Bearer {{OTHER}}".to_string()];
+        let err = check_probe(&unknown, "TOKEN", "--probe").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("{{OTHER}} in --probe does not name TOKEN"));
+        assert!(check_probe(&["{{TOKEN".to_string()], "TOKEN", "--probe").is_err());
+    }
+}
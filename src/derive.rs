@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt::Write;
+use zeroize::Zeroizing;
+
+use crate::security::{split_env_spec, validate_env_var_name, validate_namespace};
+
+/// Marker between the target name and the master in a derived `--env` argument
+const DERIVE_MARKER: &str = "=derive:";
+
+/// Length of every derived key in bytes (one SHA-256 block)
+const DERIVED_KEY_BYTES: usize = 32;
+
+/// Longest accepted HKDF info string
+const MAX_INFO_LEN: usize = 256;
+
+/// How derived keys are encoded before injection (`--derive-encoding`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeriveEncoding {
+    /// Lowercase hexadecimal
+    #[default]
+    Hex,
+    /// Standard base64 with padding
+    Base64,
+}
+
+/// `--env TARGET=derive:MASTER:INFO`, where MASTER may be `NAME@namespace`
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeriveSpec<'a> {
+    /// Variable the derived key is injected as
+    pub target: &'a str,
+    /// `--env` style reference to the stored master secret
+    pub master: &'a str,
+    /// HKDF info string; one per service
+    pub info: &'a str,
+}
+
+/// Recognises the derive form of an `--env` argument
+pub fn parse_spec(spec: &str) -> Option<DeriveSpec<'_>> {
+    let (target, rest) = spec.split_once(DERIVE_MARKER)?;
+    let (master, info) = rest.split_once(':')?;
+    Some(DeriveSpec {
+        target,
+        master,
+        info,
+    })
+}
+
+/// Validates the target, master reference and info of a derive argument
+pub fn validate_spec(spec: &DeriveSpec<'_>) -> Result<()> {
+    validate_env_var_name(spec.target).context("Invalid derived variable name")?;
+    let (master, namespace) = split_env_spec(spec.master);
+    validate_env_var_name(master).context("Invalid master secret name")?;
+    if let Some(namespace) = namespace {
+        validate_namespace(namespace).context("Invalid master secret namespace")?;
+    }
+
+    if spec.info.is_empty() {
+        return Err(anyhow::anyhow!("Derivation info cannot be empty"));
+    }
+    if spec.info.len() > MAX_INFO_LEN {
+        return Err(anyhow::anyhow!(
+            "Derivation info too long (max {} bytes)",
+            MAX_INFO_LEN
+        ));
+    }
+    if spec.info.chars().any(char::is_control) {
+        return Err(anyhow::anyhow!(
+            "Derivation info cannot contain control characters"
+        ));
+    }
+    Ok(())
+}
+
+/// HKDF-SHA256 without salt, as recommended when the master is already uniformly random
+pub fn derive_key(
+    master: &[u8],
+    info: &str,
+    encoding: DeriveEncoding,
+) -> Result<Zeroizing<String>> {
+    let mut okm = Zeroizing::new([0u8; DERIVED_KEY_BYTES]);
+    hkdf_sha256(None, master, info.as_bytes(), &mut okm[..])?;
+
+    let mut encoded = Zeroizing::new(String::with_capacity(DERIVED_KEY_BYTES * 2));
+    match encoding {
+        DeriveEncoding::Hex => {
+            for byte in okm.iter() {
+                // Writing to a String cannot fail
+                let _ = write!(encoded, "{:02x}", byte);
+            }
+        }
+        DeriveEncoding::Base64 => STANDARD.encode_string(&okm[..], &mut encoded),
+    }
+    Ok(encoded)
+}
+
+fn hkdf_sha256(salt: Option<&[u8]>, ikm: &[u8], info: &[u8], okm: &mut [u8]) -> Result<()> {
+    Hkdf::<Sha256>::new(salt, ikm)
+        .expand(info, okm)
+        .map_err(|_| anyhow::anyhow!("Requested derived key length is invalid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rfc5869_case_1() {
+        let mut okm = [0u8; 42];
+        hkdf_sha256(
+            Some(&from_hex("000102030405060708090a0b0c")),
+            &from_hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+            &from_hex("f0f1f2f3f4f5f6f7f8f9"),
+            &mut okm,
+        )
+        .unwrap();
+        assert_eq!(
+            okm.to_vec(),
+            from_hex(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+            )
+        );
+    }
+
+    #[test]
+    fn test_rfc5869_case_3_without_salt_or_info() {
+        let mut okm = [0u8; 42];
+        hkdf_sha256(
+            None,
+            &from_hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+            &[],
+            &mut okm,
+        )
+        .unwrap();
+        assert_eq!(
+            okm.to_vec(),
+            from_hex(
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"
+            )
+        );
+    }
+
+    #[test]
+    fn test_derive_key_encodings_and_separation() {
+        let hex = derive_key(b"master", "service-a", DeriveEncoding::Hex).unwrap();
+        assert_eq!(hex.len(), 64);
+        assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let b64 = derive_key(b"master", "service-a", DeriveEncoding::Base64).unwrap();
+        assert_eq!(STANDARD.decode(b64.as_str()).unwrap(), from_hex(&hex));
+
+        let other = derive_key(b"master", "service-b", DeriveEncoding::Hex).unwrap();
+        assert_ne!(*hex, *other);
+    }
+
+    #[test]
+    fn test_parse_and_validate_spec() {
+        assert_eq!(parse_spec("API_KEY"), None);
+        assert_eq!(parse_spec("API_KEY@team"), None);
+
+        let spec = parse_spec("SERVICE_KEY=derive:MASTER_KEY@team:svc:a").unwrap();
+        assert_eq!(spec.target, "SERVICE_KEY");
+        assert_eq!(spec.master, "MASTER_KEY@team");
+        assert_eq!(spec.info, "svc:a");
+        assert!(validate_spec(&spec).is_ok());
+
+        for bad in [
+            "1BAD=derive:MASTER:a",
+            "OK=derive:$(x):a",
+            "OK=derive:MASTER:",
+            "OK=derive:MASTER:a\u{7}",
+        ] {
+            assert!(validate_spec(&parse_spec(bad).unwrap()).is_err(), "{}", bad);
+        }
+    }
+}
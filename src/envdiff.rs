@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::process::{Command, Stdio};
+
+use crate::process;
+
+/// What one run of the command looked like from the outside
+#[derive(Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// Exit code, or `None` when the command was killed by a signal
+    pub code: Option<i32>,
+    /// Stdout followed by stderr
+    pub output: Vec<u8>,
+}
+
+/// How a candidate variable showed up in the command's behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evidence {
+    /// Its canary value was printed
+    Echoed,
+    /// Setting it alone changed the output or exit code
+    ChangesBehaviour,
+}
+
+impl Evidence {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Evidence::Echoed => "canary echoed in output",
+            Evidence::ChangesBehaviour => "output changes when set",
+        }
+    }
+}
+
+/// Unique, recognisable placeholder for `name`; never a real secret
+pub fn canary(name: &str) -> String {
+    let nonce = RandomState::new().build_hasher().finish();
+    format!("ls-canary-{:016x}-{}", nonce, name)
+}
+
+/// Runs `command_args` with every candidate removed from the environment
+/// except those in `set`, and records what it did
+pub fn observe(
+    command_args: &[String],
+    candidates: &[String],
+    set: &[(&str, &str)],
+) -> Result<Observation> {
+    let mut cmd: Command = process::build_command(command_args, true);
+    for name in candidates {
+        cmd.env_remove(name);
+    }
+    for (name, value) in set {
+        cmd.env(name, value);
+    }
+
+    let output = cmd
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run command")?;
+    let mut combined = output.stdout;
+    combined.extend_from_slice(&output.stderr);
+    Ok(Observation {
+        code: output.status.code(),
+        output: combined,
+    })
+}
+
+/// Whether `needle` occurs anywhere in `haystack`
+pub fn contains(haystack: &[u8], needle: &str) -> bool {
+    let needle = needle.as_bytes();
+    !needle.is_empty()
+        && haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+/// Renders the suggestion printed at the end of `env-diff`
+pub fn manifest_block(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+    format!("env = [{}]", quoted.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canaries_are_unique_and_named() {
+        let a = canary("API_KEY");
+        let b = canary("API_KEY");
+        assert_ne!(a, b);
+        assert!(a.starts_with("ls-canary-") && a.ends_with("-API_KEY"));
+    }
+
+    #[test]
+    fn test_contains_and_manifest_block() {
+        assert!(contains(b"token=ls-canary-1-A\n", "ls-canary-1-A"));
+        assert!(!contains(b"token=\n", "ls-canary-1-A"));
+        assert!(!contains(b"anything", ""));
+        assert_eq!(manifest_block(&["A", "B"]), "env = [\"A\", \"B\"]");
+        assert_eq!(manifest_block(&[]), "env = []");
+    }
+}
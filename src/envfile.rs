@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::cleanup::TempSecretFile;
+use crate::json;
+use crate::security::is_world_writable;
+
+/// Environment variable telling the child where the env file was written
+pub const ENV_FILE_VAR: &str = "LOCAL_SECRETS_ENV_FILE";
+
+/// Output format for `--env-file-out`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum EnvFileFormat {
+    /// `NAME=value` lines, quoting values that need it
+    #[default]
+    Dotenv,
+    /// A single JSON object mapping names to values
+    Json,
+}
+
+/// Writes `entries` to a new owner-only file at `path` for the child
+pub fn write(
+    path: &Path,
+    format: EnvFileFormat,
+    entries: &[(String, String)],
+) -> Result<TempSecretFile> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // Security: Anyone could swap or pre-create the file in a shared directory
+    if is_world_writable(dir) {
+        return Err(anyhow::anyhow!(
+            "Refusing to write env file into world-writable directory {}",
+            dir.display()
+        ));
+    }
+
+    let content = render(format, entries);
+    TempSecretFile::create(path, content.as_bytes())
+        .with_context(|| format!("Failed to write env file {}", path.display()))
+}
+
+fn render(format: EnvFileFormat, entries: &[(String, String)]) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(String::new());
+    match format {
+        EnvFileFormat::Dotenv => {
+            for (name, value) in entries {
+                out.push_str(name);
+                out.push('=');
+                push_dotenv_value(&mut out, value);
+                out.push('\n');
+            }
+        }
+        EnvFileFormat::Json => {
+            out.push('{');
+            for (index, (name, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                json::push_string(&mut out, name);
+                out.push(':');
+                json::push_string(&mut out, value);
+            }
+            out.push_str("}\n");
+        }
+    }
+    out
+}
+
+/// Plain values are written bare; anything else is double-quoted with escapes
+fn push_dotenv_value(out: &mut String, value: &str) {
+    let plain = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-.,:/+=@%".contains(c));
+    if plain {
+        out.push_str(value);
+        return;
+    }
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '$' => out.push_str("\\$"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dotenv_quotes_only_when_needed() {
+        let out = render(
+            EnvFileFormat::Dotenv,
+            &entries(&[("PLAIN", "abc-123"), ("SPACED", "a b\"$x\\\n")]),
+        );
+        assert_eq!(&*out, "PLAIN=abc-123\nSPACED=\"a b\\\"\\$x\\\\\\n\"\n");
+    }
+
+    #[test]
+    fn test_json_escapes_values() {
+        let out = render(
+            EnvFileFormat::Json,
+            &entries(&[("A", "x\"y"), ("B", "\u{1}\t")]),
+        );
+        assert_eq!(&*out, "{\"A\":\"x\\\"y\",\"B\":\"\\u0001\\t\"}\n");
+    }
+
+    #[test]
+    fn test_env_file_is_written_and_removed_on_drop() {
+        let dir =
+            std::env::temp_dir().join(format!("local-secrets-envfile-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        let path = dir.join("app.env");
+
+        let file = write(
+            &path,
+            EnvFileFormat::Dotenv,
+            &entries(&[("UNIT_TEST_KEY", "value")]),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "UNIT_TEST_KEY=value\n"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        drop(file);
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_file_refuses_world_writable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir =
+            std::env::temp_dir().join(format!("local-secrets-envfile-ww-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let result = write(&dir.join("app.env"), EnvFileFormat::Json, &[]);
+        std::fs::remove_dir(&dir).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::json;
+use crate::security::display_name;
+
+/// Stable machine-readable failure codes reported by `--errors-json`.
+///
+/// The strings and exit codes are a public contract: never change or reuse
+/// them, only append new variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Anything not classified below
+    Error,
+    /// A variable name, argument or secret value failed validation
+    InvalidInput,
+    /// The requested secret does not exist in the backend
+    SecretNotFound,
+    /// The keyring backend reported a failure
+    BackendError,
+    /// The child process could not be started
+    SpawnFailed,
+    /// Nobody answered an interactive prompt within `--prompt-timeout`
+    PromptTimeout,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Error => "error",
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::SecretNotFound => "secret_not_found",
+            ErrorCode::BackendError => "backend_error",
+            ErrorCode::SpawnFailed => "spawn_failed",
+            ErrorCode::PromptTimeout => "prompt_timeout",
+        }
+    }
+
+    /// Process exit code used with `--errors-json`
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorCode::Error => 1,
+            ErrorCode::InvalidInput => 2,
+            ErrorCode::SecretNotFound => 3,
+            ErrorCode::BackendError => 4,
+            ErrorCode::SpawnFailed => 5,
+            ErrorCode::PromptTimeout => 6,
+        }
+    }
+}
+
+/// Set from `--errors-json`; warnings are then JSON lines as well
+static JSON_WARNINGS: AtomicBool = AtomicBool::new(false);
+
+/// Switches warnings to one-line JSON objects (`--errors-json`)
+pub fn set_json_warnings(enabled: bool) {
+    JSON_WARNINGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Reports a non-fatal problem on stderr.
+///
+/// With `--errors-json` it is a single-line `{"warning":...}` object so that
+/// stderr stays machine-readable; `code` is stable like the error codes.
+pub fn warn(code: &'static str, variable: Option<&str>, message: String) {
+    if JSON_WARNINGS.load(Ordering::Relaxed) {
+        eprintln!("{}", warning_json(code, variable, &message));
+    } else {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+fn warning_json(code: &str, variable: Option<&str>, message: &str) -> String {
+    let message: String = message.chars().filter(|c| !c.is_control()).collect();
+    let mut out = String::from("{\"warning\":");
+    json::push_string(&mut out, code);
+    out.push_str(",\"message\":");
+    json::push_string(&mut out, &message);
+    if let Some(variable) = variable {
+        out.push_str(",\"variable\":");
+        json::push_string(&mut out, &display_name(variable));
+    }
+    out.push('}');
+    out
+}
+
+/// Error carrying a stable code; displays exactly like the error it replaces
+#[derive(Debug)]
+pub struct CodedError {
+    code: ErrorCode,
+    variable: Option<String>,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// Builds an error carrying `code` and, when known, the variable it concerns
+pub fn coded(code: ErrorCode, variable: Option<&str>, message: String) -> anyhow::Error {
+    anyhow::Error::new(CodedError {
+        code,
+        variable: variable.map(str::to_string),
+        message,
+    })
+}
+
+/// Adapter for `map_err` that tags an existing error with `code`
+pub fn tagged(
+    code: ErrorCode,
+    variable: Option<&str>,
+) -> impl FnOnce(anyhow::Error) -> anyhow::Error + '_ {
+    move |err| coded(code, variable, format!("{:#}", err))
+}
+
+/// Finds the code and variable for `err`, falling back to inspecting its causes
+pub fn classify(err: &anyhow::Error) -> (ErrorCode, Option<&str>) {
+    for cause in err.chain() {
+        if let Some(coded) = cause.downcast_ref::<CodedError>() {
+            return (coded.code, coded.variable.as_deref());
+        }
+        if cause.downcast_ref::<keyring::Error>().is_some() {
+            return (ErrorCode::BackendError, None);
+        }
+    }
+    (ErrorCode::Error, None)
+}
+
+/// Renders the single-line JSON object printed to stderr on failure
+pub fn to_json(err: &anyhow::Error, backend: &str) -> String {
+    let (code, variable) = classify(err);
+    // Sanitized: control characters would let a crafted value corrupt log lines
+    let message: String = format!("{:#}", err)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+
+    let mut out = String::from("{\"code\":");
+    json::push_string(&mut out, code.as_str());
+    out.push_str(",\"message\":");
+    json::push_string(&mut out, &message);
+    if let Some(variable) = variable {
+        out.push_str(",\"variable\":");
+        json::push_string(&mut out, &display_name(variable));
+    }
+    out.push_str(",\"backend\":");
+    json::push_string(&mut out, backend);
+    out.push_str(&format!(",\"exit_code\":{}}}", code.exit_code()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let all = [
+            ErrorCode::Error,
+            ErrorCode::InvalidInput,
+            ErrorCode::SecretNotFound,
+            ErrorCode::BackendError,
+            ErrorCode::SpawnFailed,
+            ErrorCode::PromptTimeout,
+        ];
+        let table: Vec<(&str, u8)> = all
+            .iter()
+            .map(|code| (code.as_str(), code.exit_code()))
+            .collect();
+        assert_eq!(
+            table,
+            [
+                ("error", 1),
+                ("invalid_input", 2),
+                ("secret_not_found", 3),
+                ("backend_error", 4),
+                ("spawn_failed", 5),
+                ("prompt_timeout", 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coded_error_survives_context_and_keeps_message() {
+        let err = coded(
+            ErrorCode::SecretNotFound,
+            Some("API_KEY"),
+            "Secret API_KEY not found".to_string(),
+        )
+        .context("Failed to resolve secrets");
+
+        assert_eq!(classify(&err), (ErrorCode::SecretNotFound, Some("API_KEY")));
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to resolve secrets: Secret API_KEY not found"
+        );
+    }
+
+    #[test]
+    fn test_warning_json_snapshot() {
+        assert_eq!(
+            warning_json("critical_variable", Some("PATH"), "Overriding\n 'PATH'"),
+            r#"{"warning":"critical_variable","message":"Overriding 'PATH'","variable":"PATH"}"#
+        );
+    }
+
+    #[test]
+    fn test_json_snapshot() {
+        let err = tagged(ErrorCode::InvalidInput, None)(anyhow::anyhow!("bad\u{1b}[31m name"));
+        assert_eq!(
+            to_json(&err, "keyring"),
+            r#"{"code":"invalid_input","message":"bad[31m name","backend":"keyring","exit_code":2}"#
+        );
+
+        let err = anyhow::anyhow!("plain failure");
+        assert_eq!(
+            to_json(&err, "keyring"),
+            r#"{"code":"error","message":"plain failure","backend":"keyring","exit_code":1}"#
+        );
+    }
+}
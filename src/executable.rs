@@ -0,0 +1,398 @@
+//! Checks on the executable that is about to receive secrets.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::errors::{self, ErrorCode};
+
+/// Files larger than this get a note before hashing, as it can take a moment
+const HASH_PROGRESS_THRESHOLD: u64 = 50 * 1024 * 1024;
+
+/// Programs that run a script named on their command line; pinning them says
+/// nothing about the code that actually receives the secrets
+const INTERPRETERS: &[&str] = &[
+    "bash",
+    "bun",
+    "cmd",
+    "dash",
+    "deno",
+    "env",
+    "fish",
+    "ksh",
+    "node",
+    "perl",
+    "php",
+    "powershell",
+    "pwsh",
+    "python",
+    "ruby",
+    "sh",
+    "zsh",
+];
+
+/// Parses a `--verify-child-sha256` value into lowercase hex
+pub fn parse_sha256(value: &str) -> Result<String> {
+    let hex = value.trim();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!(
+            "Invalid SHA-256 '{}' (expected 64 hex digits)",
+            value
+        ));
+    }
+    Ok(hex.to_ascii_lowercase())
+}
+
+/// Refuses to go on unless the program `cmd` will execute hashes to `expected`.
+///
+/// The program is looked up on the PATH the child will see and followed through
+/// symlinks, so the file that is hashed is the file that is executed. Interpreters
+/// are skipped with a warning, since the script they run is not what gets pinned.
+pub fn verify_sha256(cmd: &Command, expected: &str) -> Result<()> {
+    let program = cmd.get_program();
+    if is_interpreter(program) {
+        errors::warn(
+            "checksum_skipped",
+            None,
+            format!(
+                "--verify-child-sha256 skipped: {} is an interpreter, so the script it runs is not pinned",
+                Path::new(program).display()
+            ),
+        );
+        return Ok(());
+    }
+
+    let path = child_path(cmd);
+    let resolved = resolve(child_program(cmd).as_os_str(), path.as_deref()).ok_or_else(|| {
+        errors::coded(
+            ErrorCode::SpawnFailed,
+            None,
+            format!(
+                "Cannot verify {}: executable not found",
+                Path::new(program).display()
+            ),
+        )
+    })?;
+    let target = resolved
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", resolved.display()))?;
+
+    let actual = sha256_file(&target)?;
+    if actual != expected {
+        let shown = if target == resolved {
+            target.display().to_string()
+        } else {
+            format!("{} (-> {})", resolved.display(), target.display())
+        };
+        return Err(errors::coded(
+            ErrorCode::SpawnFailed,
+            None,
+            format!(
+                "Executable {} does not match --verify-child-sha256\n  expected: {}\n  actual:   {}",
+                shown, expected, actual
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Warns, or with `deny` fails, when the program `cmd` will execute is setuid or setgid.
+///
+/// Such a child runs in another privilege domain: the loader may sanitize the injected
+/// environment, or hand it to code running as the file's owner.
+#[cfg(unix)]
+pub fn check_privileged(cmd: &Command, deny: bool) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = child_path(cmd);
+    let Some(resolved) = resolve(child_program(cmd).as_os_str(), path.as_deref()) else {
+        // Spawning reports the missing program itself
+        return Ok(());
+    };
+    let Ok(meta) = std::fs::metadata(&resolved) else {
+        return Ok(());
+    };
+    let Some(bits) = privilege_bits(meta.mode(), meta.uid(), meta.gid()) else {
+        return Ok(());
+    };
+
+    let message = format!(
+        "{} is {}; injected secrets cross into another privilege domain",
+        resolved.display(),
+        bits
+    );
+    if deny {
+        return Err(errors::coded(
+            ErrorCode::SpawnFailed,
+            None,
+            format!(
+                "Refusing to start privileged child: {} (--deny-privileged-child)",
+                message
+            ),
+        ));
+    }
+    errors::warn("privileged_child", None, message);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_privileged(_cmd: &Command, _deny: bool) -> Result<()> {
+    Ok(())
+}
+
+/// Describes the setuid/setgid bits of a file mode, e.g. `setuid root (uid 0)`
+#[cfg(unix)]
+fn privilege_bits(mode: u32, uid: u32, gid: u32) -> Option<String> {
+    let owner = |id: u32| {
+        if id == 0 {
+            "root (0)".to_string()
+        } else {
+            id.to_string()
+        }
+    };
+    let mut bits = Vec::new();
+    // POSIX values of S_ISUID and S_ISGID; libc's mode_t width differs per platform
+    if mode & 0o4000 != 0 {
+        bits.push(format!("setuid to uid {}", owner(uid)));
+    }
+    if mode & 0o2000 != 0 {
+        bits.push(format!("setgid to gid {}", owner(gid)));
+    }
+    (!bits.is_empty()).then(|| bits.join(" and "))
+}
+
+/// The program `cmd` runs, with a relative path taken from the child's working directory
+fn child_program(cmd: &Command) -> PathBuf {
+    let program = Path::new(cmd.get_program());
+    match cmd.get_current_dir() {
+        Some(dir) if program.is_relative() && program.components().count() > 1 => dir.join(program),
+        _ => program.to_path_buf(),
+    }
+}
+
+/// PATH as the child will see it: an explicit override on `cmd`, else our own
+pub fn child_path(cmd: &Command) -> Option<OsString> {
+    let overridden = cmd
+        .get_envs()
+        .find(|(key, _)| *key == OsStr::new("PATH"))
+        .map(|(_, value)| value.map(OsStr::to_os_string));
+    match overridden {
+        Some(value) => value,
+        None => std::env::var_os("PATH"),
+    }
+}
+
+/// The file `program` refers to, searching `path` when it is a bare name
+pub fn resolve(program: &OsStr, path: Option<&OsStr>) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+
+    let extensions: Vec<OsString> = if cfg!(windows) && program.extension().is_none() {
+        std::env::var_os("PATHEXT")
+            .map(|exts| {
+                exts.to_string_lossy()
+                    .split(';')
+                    .filter(|ext| !ext.is_empty())
+                    .map(OsString::from)
+                    .collect()
+            })
+            .unwrap_or_else(|| vec![".COM".into(), ".EXE".into()])
+    } else {
+        vec![OsString::new()]
+    };
+
+    std::env::split_paths(path?).find_map(|dir| {
+        extensions.iter().find_map(|extension| {
+            let mut name = program.as_os_str().to_os_string();
+            name.push(extension);
+            let candidate = dir.join(name);
+            is_executable(&candidate).then_some(candidate)
+        })
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Whether `program` names a script interpreter such as `python3.12` or `bash`
+fn is_interpreter(program: &OsStr) -> bool {
+    let Some(stem) = Path::new(program).file_stem().and_then(OsStr::to_str) else {
+        return false;
+    };
+    let stem = stem.to_ascii_lowercase();
+    // Versioned names: python3, python3.12, perl5
+    let base = stem.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS.contains(&base)
+}
+
+/// Lowercase hex SHA-256 of a file, read in chunks
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    if size > HASH_PROGRESS_THRESHOLD {
+        eprintln!(
+            "Hashing {} ({} MB) for --verify-child-sha256...",
+            path.display(),
+            size / (1024 * 1024)
+        );
+    }
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sha256() {
+        let hex = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+        assert_eq!(parse_sha256(hex).unwrap(), hex.to_ascii_lowercase());
+        assert!(parse_sha256("ba7816bf").is_err());
+        assert!(parse_sha256(&"g".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_sha256_file_streams_the_whole_file() {
+        let dir = std::env::temp_dir().join(format!("local-secrets-sha-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("abc");
+        std::fs::write(&file, "abc").unwrap();
+        assert_eq!(
+            sha256_file(&file).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        // Crosses the chunk boundary
+        std::fs::write(&file, vec![b'a'; 64 * 1024 + 1]).unwrap();
+        let chunked = sha256_file(&file).unwrap();
+        let direct = Sha256::digest(vec![b'a'; 64 * 1024 + 1]);
+        assert_eq!(
+            chunked,
+            direct
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_interpreters_are_recognised_with_versions() {
+        for program in ["python3.12", "/usr/bin/bash", "node", "pwsh.exe", "perl5"] {
+            assert!(is_interpreter(OsStr::new(program)), "{program}");
+        }
+        for program in ["terraform", "./deploy", "shellcheck", "nodejs-tool"] {
+            assert!(!is_interpreter(OsStr::new(program)), "{program}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_privilege_bits() {
+        assert_eq!(privilege_bits(0o100755, 0, 0), None);
+        assert_eq!(
+            privilege_bits(0o104755, 0, 0).as_deref(),
+            Some("setuid to uid root (0)")
+        );
+        assert_eq!(
+            privilege_bits(0o106755, 1000, 42).as_deref(),
+            Some("setuid to uid 1000 and setgid to gid 42")
+        );
+        assert_eq!(
+            privilege_bits(0o102711, 0, 5).as_deref(),
+            Some("setgid to gid 5")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_privileged_inspects_the_resolved_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("local-secrets-setuid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plain = dir.join("plain");
+        let setuid = dir.join("setuid");
+        for (file, mode) in [(&plain, 0o755), (&setuid, 0o4755)] {
+            std::fs::write(file, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(file, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        assert!(check_privileged(&Command::new(&plain), true).is_ok());
+        assert!(check_privileged(&Command::new(&setuid), false).is_ok());
+        let err = check_privileged(&Command::new(&setuid), true).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("setuid to uid"), "{message}");
+        assert!(message.contains(&setuid.display().to_string()), "{message}");
+
+        // Bare names are resolved on the child's PATH
+        let mut cmd = Command::new("setuid");
+        cmd.env("PATH", &dir);
+        assert!(check_privileged(&cmd, true).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_searches_path_for_executables_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("local-secrets-resolve-{}", std::process::id()));
+        let (first, second) = (dir.join("a"), dir.join("b"));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(first.join("tool"), "not executable").unwrap();
+        std::fs::write(second.join("tool"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(second.join("tool"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        let path = std::env::join_paths([&first, &second]).unwrap();
+        assert_eq!(
+            resolve(OsStr::new("tool"), Some(&path)),
+            Some(second.join("tool"))
+        );
+        assert_eq!(resolve(OsStr::new("missing"), Some(&path)), None);
+        assert_eq!(
+            resolve(first.join("tool").as_os_str(), None),
+            Some(first.join("tool"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use zeroize::Zeroizing;
+
+use crate::json;
+use crate::security::{display_name, split_env_spec, validate_env_var_name, validate_namespace};
+
+/// One `--map helm.key.path=VARIABLE` argument
+#[derive(Debug, PartialEq, Eq)]
+pub struct ValueMapping<'a> {
+    /// Dotted Helm values path, e.g. `db.password`
+    pub path: Vec<&'a str>,
+    /// `--env` style reference to the stored secret
+    pub source: &'a str,
+}
+
+/// Parses and validates a `--map` argument
+pub fn parse_mapping(arg: &str) -> Result<ValueMapping<'_>> {
+    let (path, source) = arg.split_once('=').ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid --map {}: expected helm.key.path=VARIABLE",
+            display_name(arg)
+        )
+    })?;
+
+    let segments: Vec<&str> = path.split('.').collect();
+    // Restricted so a key can never carry Helm's own `--set` syntax (`,`, `[`, `=`, `\`)
+    let valid_segment = |segment: &&str| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    if !segments.iter().all(valid_segment) {
+        return Err(anyhow::anyhow!(
+            "Invalid Helm values path {}: use dot-separated letters, digits, '_' and '-'",
+            display_name(path)
+        ));
+    }
+
+    let (variable, namespace) = split_env_spec(source);
+    validate_env_var_name(variable)?;
+    if let Some(namespace) = namespace {
+        validate_namespace(namespace)?;
+    }
+
+    Ok(ValueMapping {
+        path: segments,
+        source,
+    })
+}
+
+/// Rejects mappings that assign the same path twice or a value beneath another value
+pub fn check_conflicts(mappings: &[ValueMapping<'_>]) -> Result<()> {
+    for (index, a) in mappings.iter().enumerate() {
+        for b in &mappings[index + 1..] {
+            let shared = a
+                .path
+                .iter()
+                .zip(&b.path)
+                .take_while(|(x, y)| x == y)
+                .count();
+            if shared == a.path.len().min(b.path.len()) {
+                return Err(anyhow::anyhow!(
+                    "Conflicting --map paths {} and {}",
+                    a.path.join("."),
+                    b.path.join(".")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a values.yaml document with every value as a double-quoted string.
+///
+/// JSON string escaping is valid YAML double-quoted syntax, so no value can
+/// break out of its scalar whatever characters it contains.
+pub fn render_values(entries: &[(&ValueMapping<'_>, &str)]) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(String::new());
+    let mut open: Vec<&str> = Vec::new();
+    let mut sorted: Vec<&(&ValueMapping<'_>, &str)> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+
+    for (mapping, value) in sorted {
+        let (leaf, parents) = match mapping.path.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+        let shared = open.iter().zip(parents).take_while(|(x, y)| x == y).count();
+        open.truncate(shared);
+        for parent in &parents[shared..] {
+            push_key(&mut out, open.len(), parent);
+            out.push('\n');
+            open.push(parent);
+        }
+        push_key(&mut out, open.len(), leaf);
+        out.push(' ');
+        json::push_string(&mut out, value);
+        out.push('\n');
+    }
+    out
+}
+
+fn push_key(out: &mut String, depth: usize, key: &str) {
+    out.push_str(&"  ".repeat(depth));
+    json::push_string(out, key);
+    out.push(':');
+}
+
+/// Builds `--set-string path=value`, escaping Helm's `,` and `\` separators
+pub fn set_string_arg(mapping: &ValueMapping<'_>, value: &str) -> Zeroizing<String> {
+    let mut arg = Zeroizing::new(mapping.path.join("."));
+    arg.push('=');
+    for c in value.chars() {
+        if c == ',' || c == '\\' {
+            arg.push('\\');
+        }
+        arg.push(c);
+    }
+    arg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mapping_validates_path_and_variable() {
+        let mapping = parse_mapping("db.password=DB_PASS@team").unwrap();
+        assert_eq!(mapping.path, ["db", "password"]);
+        assert_eq!(mapping.source, "DB_PASS@team");
+
+        for bad in [
+            "db.password",
+            "db..password=X",
+            "db[0]=X",
+            "a,b=X",
+            "db=1BAD",
+            "=X",
+        ] {
+            assert!(parse_mapping(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_conflicting_paths_are_rejected() {
+        let a = parse_mapping("db.password=A").unwrap();
+        let b = parse_mapping("db=B").unwrap();
+        let c = parse_mapping("db.user=C").unwrap();
+        assert!(check_conflicts(&[a, c]).is_ok());
+
+        let a = parse_mapping("db.password=A").unwrap();
+        assert!(check_conflicts(&[a, b]).is_err());
+    }
+
+    #[test]
+    fn test_render_values_nests_and_quotes() {
+        let pass = parse_mapping("db.password=A").unwrap();
+        let user = parse_mapping("db.user=B").unwrap();
+        let key = parse_mapping("api.key=C").unwrap();
+        let yaml = render_values(&[(&pass, "p\"w\n: x"), (&key, "k"), (&user, "u")]);
+        assert_eq!(
+            yaml.as_str(),
+            "\"api\":\n  \"key\": \"k\"\n\"db\":\n  \"password\": \"p\\\"w\\n: x\"\n  \"user\": \"u\"\n"
+        );
+    }
+
+    #[test]
+    fn test_set_string_arg_escapes_separators() {
+        let mapping = parse_mapping("db.password=A").unwrap();
+        assert_eq!(
+            set_string_arg(&mapping, "a,b\\c=d").as_str(),
+            "db.password=a\\,b\\\\c=d"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::security::{split_env_spec, validate_env_var_name};
+
+/// Stored values shorter than this are not searched for; they match too much
+const MIN_VALUE_LEN: usize = 8;
+
+/// Largest history file read; anything beyond is ignored with a warning
+const MAX_HISTORY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Replaces every sensitive span in a reported line
+const ELIDED: &str = "***";
+
+/// One history line that appears to contain a secret
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// 1-based line number in the history file
+    pub line: usize,
+    /// The command with every sensitive span replaced by `***`
+    pub excerpt: String,
+    /// Why the line was flagged
+    pub reason: String,
+}
+
+/// Variables that locate history files; without any of them there is nowhere to look
+const LOCATION_VARS: [&str; 4] = ["HISTFILE", "HOME", "USERPROFILE", "APPDATA"];
+
+/// History files that exist for the current user: `$HISTFILE` and the
+/// standard bash, zsh, fish and PowerShell locations.
+///
+/// Fails when none of the locating variables is set (minimal containers, `env -i`),
+/// rather than reporting a clean history that was never looked at.
+pub fn history_files() -> Result<Vec<PathBuf>> {
+    if !LOCATION_VARS
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()))
+    {
+        return Err(anyhow::anyhow!(
+            "Cannot locate shell history: none of {} is set; pass --file PATH",
+            LOCATION_VARS.join(", ")
+        ));
+    }
+
+    let mut candidates = Vec::new();
+    if let Some(histfile) = std::env::var_os("HISTFILE") {
+        candidates.push(PathBuf::from(histfile));
+    }
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        let home = PathBuf::from(home);
+        candidates.push(home.join(".bash_history"));
+        candidates.push(home.join(".zsh_history"));
+        candidates.push(home.join(".zhistory"));
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".local/share"));
+        candidates.push(data_home.join("fish/fish_history"));
+    }
+    if let Some(appdata) = std::env::var_os("APPDATA") {
+        candidates.push(
+            PathBuf::from(appdata)
+                .join("Microsoft/Windows/PowerShell/PSReadLine/ConsoleHost_history.txt"),
+        );
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for candidate in candidates {
+        if candidate.is_file() && !files.contains(&candidate) {
+            files.push(candidate);
+        }
+    }
+    Ok(files)
+}
+
+/// Reads a history file into zeroized memory; zsh may store non-UTF-8 bytes
+pub fn read_history(path: &Path) -> Result<Zeroizing<String>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut bytes = Zeroizing::new(Vec::new());
+    file.take(MAX_HISTORY_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if bytes.len() as u64 > MAX_HISTORY_BYTES {
+        eprintln!(
+            "Warning: only the first {} MiB of {} were checked",
+            MAX_HISTORY_BYTES / (1024 * 1024),
+            path.display()
+        );
+    }
+    Ok(Zeroizing::new(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Flags inline local-secrets values and verbatim occurrences of `values`
+pub fn scan(text: &str, values: &[(&str, &str)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let Some(command) = command_text(line) else {
+            continue;
+        };
+
+        let mut spans = inline_value_spans(command);
+        for (name, value) in values {
+            if value.len() < MIN_VALUE_LEN {
+                continue;
+            }
+            for (start, _) in command.match_indices(value) {
+                spans.push((
+                    start..start + value.len(),
+                    format!("stored value of {}", name),
+                ));
+            }
+        }
+        if spans.is_empty() {
+            continue;
+        }
+
+        spans.sort_by_key(|(range, _)| range.start);
+        let mut reasons: Vec<String> = Vec::new();
+        for (_, reason) in &spans {
+            if !reasons.contains(reason) {
+                reasons.push(reason.clone());
+            }
+        }
+        findings.push(Finding {
+            line: index + 1,
+            excerpt: elide(command, spans.iter().map(|(range, _)| range.clone())),
+            reason: reasons.join(", "),
+        });
+    }
+    findings
+}
+
+/// How to remove a flagged entry from the given history file
+pub fn cleanup_hint(path: &Path) -> &'static str {
+    let name = path.to_string_lossy();
+    if name.contains("fish") {
+        "fish: history delete --contains local-secrets (choose the entries interactively)"
+    } else if name.contains("zsh") || name.contains("zhistory") {
+        "zsh: remove the lines from the file in an editor, then run 'fc -R' in open shells"
+    } else if name.contains("PSReadLine") {
+        "PowerShell: remove the lines from the file in an editor; Clear-History only clears the session"
+    } else {
+        "bash: 'history -d OFFSET' for the running shell, and remove the lines from the file in an editor"
+    }
+}
+
+/// The command part of a history line, or `None` for metadata lines
+fn command_text(line: &str) -> Option<&str> {
+    // bash HISTTIMEFORMAT timestamps
+    if let Some(digits) = line.strip_prefix('#') {
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    // zsh extended history: `: 1700000000:0;command`
+    if let Some(rest) = line.strip_prefix(": ") {
+        if let Some((_, command)) = rest.split_once(';') {
+            return Some(command);
+        }
+    }
+    // fish: `- cmd: command` followed by `  when: ...`
+    if let Some(command) = line.strip_prefix("- cmd: ") {
+        return Some(command);
+    }
+    if line.starts_with("  when:") || line.starts_with("  paths:") || line.starts_with("    - ") {
+        return None;
+    }
+    Some(line)
+}
+
+/// Values passed inline in a local-secrets command line
+fn inline_value_spans(command: &str) -> Vec<(Range<usize>, String)> {
+    let mut spans = Vec::new();
+    let tokens = tokens(command);
+
+    for (position, (range, token)) in tokens.iter().enumerate() {
+        if let Some(value) = token.strip_prefix("LOCAL_SECRETS_TEST_SECRET=") {
+            if !value.is_empty() {
+                let start = range.end - value.len();
+                spans.push((start..range.end, "LOCAL_SECRETS_TEST_SECRET".to_string()));
+            }
+        } else if let Some(value) = token.strip_prefix("--test-secret=") {
+            if !value.is_empty() {
+                let start = range.end - value.len();
+                spans.push((start..range.end, "--test-secret value".to_string()));
+            }
+        } else if *token == "--test-secret" {
+            if let Some((value, _)) = tokens.get(position + 1) {
+                spans.push((value.clone(), "--test-secret value".to_string()));
+            }
+        }
+    }
+
+    // `local-secrets store NAME VALUE`: a pasted value lands in the history even though
+    // the command itself is rejected. `store` takes several names, so only a positional
+    // that cannot be a variable name is taken for a value.
+    let invocation = tokens.iter().position(|(_, token)| is_local_secrets(token));
+    if let Some(invocation) = invocation {
+        let mut rest = tokens[invocation + 1..].iter();
+        if rest.next().is_some_and(|(_, token)| *token == "store") {
+            let mut skip_next = false;
+            for (range, token) in rest {
+                if skip_next {
+                    skip_next = false;
+                } else if *token == "--from-file" || *token == "--test-secret" {
+                    skip_next = true;
+                } else if !token.starts_with('-') && !is_variable_name(token) {
+                    spans.push((range.clone(), "value passed to store".to_string()));
+                }
+            }
+        }
+    }
+    spans
+}
+
+fn is_variable_name(token: &str) -> bool {
+    let (name, _) = split_env_spec(token);
+    validate_env_var_name(name).is_ok()
+}
+
+fn is_local_secrets(token: &str) -> bool {
+    let program = token.rsplit(['/', '\\']).next().unwrap_or(token);
+    program == "local-secrets" || program == "local-secrets.exe"
+}
+
+/// Whitespace-separated tokens with their byte ranges; quotes keep spaces inside a token
+fn tokens(command: &str) -> Vec<(Range<usize>, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    for (index, c) in command.char_indices() {
+        match (start, quote) {
+            (None, _) if c.is_whitespace() => {}
+            (None, _) => {
+                start = Some(index);
+                if c == '\'' || c == '"' {
+                    quote = Some(c);
+                }
+            }
+            (Some(_), Some(open)) if c == open => quote = None,
+            (Some(_), None) if c == '\'' || c == '"' => quote = Some(c),
+            (Some(begin), None) if c.is_whitespace() => {
+                tokens.push((begin..index, &command[begin..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((begin..command.len(), &command[begin..]));
+    }
+    tokens
+}
+
+/// Replaces the given spans with `***` and strips control characters
+fn elide(command: &str, spans: impl Iterator<Item = Range<usize>>) -> String {
+    let mut out = String::new();
+    let mut cursor = 0;
+    for span in spans {
+        if span.end <= cursor {
+            continue;
+        }
+        out.push_str(&command[cursor..span.start.max(cursor)]);
+        out.push_str(ELIDED);
+        cursor = span.end;
+    }
+    out.push_str(&command[cursor..]);
+    out.chars().filter(|c| !c.is_control()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_flags_inline_values_and_elides_them() {
+        let history = "ls -la\n\
+                       local-secrets store API_KEY --test-secret hunter2hunter2\n\
+                       LOCAL_SECRETS_TEST_SECRET='pa ss' local-secrets --env X -- env\n\
+                       ./target/debug/local-secrets store DB_PASS s3cr3t-value\n\
+                       local-secrets store DB_PASS --from-file ./key.bin --binary\n";
+        let findings = scan(history, &[]);
+        assert_eq!(findings.len(), 3);
+        assert_eq!(findings[0].line, 2);
+        assert_eq!(
+            findings[0].excerpt,
+            "local-secrets store API_KEY --test-secret ***"
+        );
+        assert_eq!(
+            findings[1].excerpt,
+            "LOCAL_SECRETS_TEST_SECRET=*** local-secrets --env X -- env"
+        );
+        assert_eq!(
+            findings[2].excerpt,
+            "./target/debug/local-secrets store DB_PASS ***"
+        );
+        assert_eq!(findings[2].reason, "value passed to store");
+    }
+
+    #[test]
+    fn test_scan_takes_every_store_positional_for_a_name() {
+        let history = "local-secrets store API_KEY DB_PASS
+                       local-secrets store API_KEY DB_PASS@work --if-missing
+";
+        assert!(scan(history, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_scan_finds_stored_values_in_any_command() {
+        let history = ": 1700000000:0;curl -H 'Authorization: Bearer ghp_abcdef123456' api\n\
+                       - cmd: echo short\n  when: 1700000000\n";
+        let findings = scan(
+            history,
+            &[("GITHUB_PAT", "ghp_abcdef123456"), ("SHORT", "short")],
+        );
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].excerpt,
+            "curl -H 'Authorization: Bearer ***' api"
+        );
+        assert_eq!(findings[0].reason, "stored value of GITHUB_PAT");
+    }
+
+    #[test]
+    fn test_metadata_lines_are_skipped() {
+        assert_eq!(command_text("#1700000000"), None);
+        assert_eq!(command_text("  when: 1700000000"), None);
+        assert_eq!(command_text(": 1700000000:0;ls"), Some("ls"));
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use zeroize::Zeroizing;
+
+use crate::json;
+use crate::security::display_name;
+
+/// Output shapes understood by IDE run configurations (`ide-resolve --format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum IdeFormat {
+    /// JSON object of name to value, for VS Code `envFile`-style hooks
+    VscodeEnv,
+    /// `<envs>` block of a JetBrains run configuration
+    JetbrainsXml,
+}
+
+/// Renders `entries` as `format`; the result holds secret values and is zeroized on drop
+pub fn render(format: IdeFormat, entries: &[(&str, &str)]) -> Result<Zeroizing<String>> {
+    let mut out = Zeroizing::new(String::new());
+    match format {
+        IdeFormat::VscodeEnv => {
+            out.push('{');
+            for (index, (name, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                json::push_string(&mut out, name);
+                out.push(':');
+                json::push_string(&mut out, value);
+            }
+            out.push_str("}\n");
+        }
+        IdeFormat::JetbrainsXml => {
+            out.push_str("<envs>\n");
+            for (name, value) in entries {
+                // Other C0 controls cannot appear in XML 1.0 at all
+                if value
+                    .chars()
+                    .any(|c| (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r'))
+                {
+                    return Err(anyhow::anyhow!(
+                        "Secret {} contains control characters that XML cannot represent",
+                        display_name(name)
+                    ));
+                }
+                out.push_str("  <env name=\"");
+                push_xml_attribute(&mut out, name);
+                out.push_str("\" value=\"");
+                push_xml_attribute(&mut out, value);
+                out.push_str("\" />\n");
+            }
+            out.push_str("</envs>\n");
+        }
+    }
+    Ok(out)
+}
+
+/// Escapes a value for a double-quoted XML attribute; line breaks and tabs become
+/// character references so the IDE reads back exactly the stored value
+fn push_xml_attribute(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push_str(&format!("&#x{:X};", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vscode_env_is_escaped_json() {
+        let out = render(
+            IdeFormat::VscodeEnv,
+            &[("API_KEY", "a\"b\\c"), ("MULTI", "x\ny")],
+        )
+        .unwrap();
+        assert_eq!(
+            out.as_str(),
+            "{\"API_KEY\":\"a\\\"b\\\\c\",\"MULTI\":\"x\\ny\"}\n"
+        );
+        assert_eq!(render(IdeFormat::VscodeEnv, &[]).unwrap().as_str(), "{}\n");
+    }
+
+    #[test]
+    fn test_jetbrains_xml_escapes_attributes() {
+        let out = render(
+            IdeFormat::JetbrainsXml,
+            &[("API_KEY", "<a href=\"x\">&'</a>"), ("MULTI", "x\ny\tz")],
+        )
+        .unwrap();
+        assert_eq!(
+            out.as_str(),
+            "<envs>\n  <env name=\"API_KEY\" value=\"&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;\" />\n  <env name=\"MULTI\" value=\"x&#xA;y&#x9;z\" />\n</envs>\n"
+        );
+
+        assert!(render(IdeFormat::JetbrainsXml, &[("BELL", "a\u{7}")]).is_err());
+    }
+}
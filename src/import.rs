@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use secrecy::SecretString;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+use crate::security::{validate_env_var_name, validate_secret_value};
+
+/// Largest decrypted output read from sops; anything beyond is never buffered
+const MAX_SOPS_OUTPUT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Largest plain `.env` file read, the same bound as decrypted sops output
+const MAX_DOTENV_BYTES: u64 = MAX_SOPS_OUTPUT_BYTES;
+
+/// Syntax of the text handed to [`parse_entries`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `sops --output-type dotenv`: values are taken verbatim
+    Sops,
+    /// Hand-written `.env` files: `export ` prefixes and quoted values
+    Dotenv,
+}
+
+/// Reads a plain `.env` file, refusing files larger than the import limit
+pub fn read_dotenv(path: &Path) -> Result<Zeroizing<String>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut bytes = Zeroizing::new(Vec::new());
+    file.take(MAX_DOTENV_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if bytes.len() as u64 > MAX_DOTENV_BYTES {
+        return Err(anyhow::anyhow!(
+            "{} is too large to import (max {} bytes)",
+            path.display(),
+            MAX_DOTENV_BYTES
+        ));
+    }
+    String::from_utf8(bytes.to_vec())
+        .map(Zeroizing::new)
+        .with_context(|| format!("{} is not valid UTF-8", path.display()))
+}
+
+/// How much of sops' stderr is kept for the error message
+const MAX_SOPS_STDERR_BYTES: u64 = 64 * 1024;
+
+/// Decrypts a sops-managed file by piping `sops -d` into memory.
+///
+/// The plaintext never touches the disk: sops writes it to our stdout pipe.
+/// At most one byte past the size limit is read; a larger output stops sops.
+pub fn decrypt_sops(path: &Path) -> Result<Zeroizing<String>> {
+    let mut child = Command::new("sops")
+        .args(["--decrypt", "--output-type", "dotenv"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!(
+                "sops not found on PATH; install it from https://github.com/getsops/sops to import encrypted files"
+            ),
+            _ => anyhow::anyhow!("Failed to run sops: {}", err),
+        })?;
+
+    // Drained on its own thread so a chatty sops cannot stall on a full pipe
+    let stderr = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut text = Vec::new();
+            let _ = (&mut stderr)
+                .take(MAX_SOPS_STDERR_BYTES)
+                .read_to_end(&mut text);
+            let _ = std::io::copy(&mut stderr, &mut std::io::sink());
+            text
+        })
+    });
+
+    let mut stdout = Zeroizing::new(Vec::new());
+    if let Some(pipe) = child.stdout.take() {
+        pipe.take(MAX_SOPS_OUTPUT_BYTES + 1)
+            .read_to_end(&mut stdout)
+            .context("Failed to read sops output")?;
+    }
+    if stdout.len() as u64 > MAX_SOPS_OUTPUT_BYTES {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(anyhow::anyhow!(
+            "sops output for {} is too large (max {} bytes)",
+            path.display(),
+            MAX_SOPS_OUTPUT_BYTES
+        ));
+    }
+
+    let status = child.wait().context("Failed to wait for sops")?;
+    if !status.success() {
+        let stderr = stderr
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "sops failed to decrypt {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&stderr).trim()
+        ));
+    }
+
+    String::from_utf8(stdout.to_vec())
+        .map(Zeroizing::new)
+        .context("sops output is not valid UTF-8")
+}
+
+/// Parses `KEY=value` lines, skipping blank lines and `#` comments.
+///
+/// Every entry is validated before any is returned, so a bad line aborts the
+/// whole import instead of leaving it half done.
+pub fn parse_entries(text: &str, format: Format) -> Result<Vec<(String, SecretString)>> {
+    let mut entries: Vec<(String, SecretString)> = Vec::new();
+    let mut defined_on = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let mut trimmed = line.trim_start();
+        if trimmed.trim_end().is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if format == Format::Dotenv {
+            if let Some(rest) = trimmed.strip_prefix("export ") {
+                trimmed = rest.trim_start();
+            }
+        }
+
+        // Unquoted values are taken verbatim; only the name is trimmed
+        let (name, raw) = trimmed
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Line {}: expected KEY=VALUE", line_number))?;
+        let name = name.trim();
+        validate_env_var_name(name).with_context(|| format!("Line {}", line_number))?;
+
+        let value = match format {
+            Format::Sops => Zeroizing::new(raw.to_string()),
+            Format::Dotenv => unquote(raw)
+                .map_err(|reason| anyhow::anyhow!("Line {}: {} {}", line_number, name, reason))?,
+        };
+        validate_secret_value(&value).with_context(|| format!("Line {}: {}", line_number, name))?;
+        if value.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Line {}: {} has an empty value",
+                line_number,
+                name
+            ));
+        }
+        if let Some(index) = entries.iter().position(|(existing, _)| existing == name) {
+            return Err(anyhow::anyhow!(
+                "Line {}: {} is already defined on line {}",
+                line_number,
+                name,
+                defined_on[index]
+            ));
+        }
+
+        entries.push((name.to_string(), SecretString::new(value.as_str().into())));
+        defined_on.push(line_number);
+    }
+    Ok(entries)
+}
+
+/// Value of a `.env` assignment: `'single'` quotes are literal, `"double"` quotes
+/// understand `\n`, `\r`, `\t`, `\"` and `\\`, anything else is taken as is
+fn unquote(raw: &str) -> std::result::Result<Zeroizing<String>, &'static str> {
+    let quote = match raw.chars().next() {
+        Some(quote @ ('"' | '\'')) => quote,
+        _ => return Ok(Zeroizing::new(raw.to_string())),
+    };
+    let rest = &raw[1..];
+
+    let mut value = Zeroizing::new(String::new());
+    let mut chars = rest.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c == quote {
+            // Only whitespace or a comment may follow the closing quote
+            let after = rest[index + 1..].trim_start();
+            if !after.is_empty() && !after.starts_with('#') {
+                return Err("has text after the closing quote");
+            }
+            return Ok(value);
+        }
+        if c == '\\' && quote == '"' {
+            match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => break,
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    Err("has an unterminated quote (multi-line values are not supported)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn test_parse_entries_skips_comments_and_blank_lines() {
+        let entries = parse_entries(
+            "# sops output\n\nAPI_KEY=abc=def\nDB_PASS=x y\n",
+            Format::Sops,
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "API_KEY");
+        assert_eq!(entries[0].1.expose_secret(), "abc=def");
+        assert_eq!(entries[1].1.expose_secret(), "x y");
+    }
+
+    #[test]
+    fn test_parse_entries_reports_line_numbers() {
+        let err = parse_entries("GOOD=1\nnot a pair\n", Format::Sops).unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 2"));
+
+        let err = parse_entries("GOOD=1\n\n$(bad)=x\n", Format::Sops).unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 3"));
+
+        let err = parse_entries("EMPTY=\n", Format::Sops).unwrap_err();
+        assert!(format!("{:#}", err).contains("empty value"));
+
+        let err = parse_entries("A=1\nB=2\nA=3\n", Format::Sops).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Line 3: A is already defined on line 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_quotes_and_export_prefixes() {
+        let text = "# app\nexport API_KEY=plain value\n  export   QUOTED=\"a \\\"b\\\" \\\\n\\n#x\" # note\nSINGLE='$HOME \\n'\nHASH=abc#def\n";
+        let entries = parse_entries(text, Format::Dotenv).unwrap();
+        let values: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.expose_secret()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("API_KEY", "plain value"),
+                ("QUOTED", "a \"b\" \\n\n#x"),
+                ("SINGLE", "$HOME \\n"),
+                ("HASH", "abc#def"),
+            ]
+        );
+
+        // sops output keeps quotes and the word export as part of the data
+        let entries = parse_entries("KEY='x'\n", Format::Sops).unwrap();
+        assert_eq!(entries[0].1.expose_secret(), "'x'");
+    }
+
+    #[test]
+    fn test_parse_dotenv_rejects_malformed_quotes_with_line_numbers() {
+        for (text, message) in [
+            ("A=1\nB=\"open\n", "Line 2: B has an unterminated quote"),
+            ("A='x' y\n", "Line 1: A has text after the closing quote"),
+            ("A=\"\"\n", "Line 1: A has an empty value"),
+            ("export =x\n", "Line 1"),
+        ] {
+            let err = parse_entries(text, Format::Dotenv).unwrap_err();
+            assert!(
+                format!("{:#}", err).starts_with(message),
+                "{text:?}: {err:#}"
+            );
+        }
+    }
+}
@@ -1,36 +1,216 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use mimalloc::MiMalloc;
+use std::path::PathBuf;
 use std::process::ExitCode;
+use std::time::Duration;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
 mod backend;
+mod batch;
+mod binary;
+mod cleanup;
+mod clipboard;
 mod commands;
+mod derive;
+mod envdiff;
+mod envfile;
+mod errors;
+mod executable;
+mod helm;
+mod history;
+mod ide;
+mod import;
+mod json;
+mod mapfile;
+mod mask;
+mod otel;
+mod process;
+mod prompt;
 mod security;
+mod stats;
+mod tree;
+#[cfg(feature = "self-update")]
+mod update;
+mod watch;
 
-use backend::{KeyringBackend, SecretBackend};
-use security::validate_cli_security;
+use backend::{BackendKind, SecretBackend};
+use batch::QueuedCommand;
+use commands::{RunOptions, SecretSource, StoreMode};
+use derive::DeriveEncoding;
+use envfile::EnvFileFormat;
+use errors::ErrorCode;
+use prompt::PromptStyle;
+use security::{injected_name, split_no_save, validate_cli_security};
 
 #[derive(Parser)]
 #[command(name = "local-secrets")]
 #[command(about = "Securely store secrets in your OS keyring and inject them into child processes")]
+#[command(
+    after_help = "Run a command with `local-secrets run --env NAME -- COMMAND [ARGS]...`. The run flags above also work without `run` for compatibility; new run flags are only added to `run`."
+)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Environment variable name to inject (can be used multiple times)
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// Export trace spans to this OTLP/HTTP collector (default: $OTEL_EXPORTER_OTLP_ENDPOINT)
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true, value_name = "URL")]
+    otel_endpoint: Option<String>,
+
+    /// Give up on an unanswered secret prompt after SECONDS and exit with code 6
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    prompt_timeout: Option<u64>,
+
+    /// Keep secrets of this project apart from identically named ones elsewhere
+    /// (keyring service `local-secrets:<NAME>`); default: $LOCAL_SECRETS_NAMESPACE
+    #[arg(long, global = true, value_name = "NAME")]
+    namespace: Option<String>,
+
+    /// Where secrets live: the OS keyring, or (read-only) systemd credentials in $CREDENTIALS_DIRECTORY
+    #[arg(long, global = true, value_enum, default_value = "keyring")]
+    backend: BackendKind,
+
+    /// What to show while a secret is typed: nothing, `*` per character, or a character count
+    #[arg(long, global = true, value_enum, default_value = "hidden")]
+    prompt_style: PromptStyle,
+
+    /// Keep terminal escape sequences in secrets typed at a prompt instead of stripping them
+    #[arg(long, global = true)]
+    raw_input: bool,
+
+    /// On failure, print a single JSON object with a stable error code to stderr
+    #[arg(long, global = true)]
+    errors_json: bool,
+}
+
+/// Run-mode flags, shared by `run` and the legacy bare invocation.
+///
+/// Flags new to run mode belong on `Commands::Run` only, so the bare form stays as it is.
+#[derive(Args)]
+struct RunArgs {
+    /// Environment variable name to inject (can be used multiple times); `NAME!` never saves a prompted value
     #[arg(long, action = clap::ArgAction::Append)]
     env: Vec<String>,
 
+    /// TOML file of `[map] TARGET = "SOURCE"` aliases to inject; --env entries win
+    #[arg(long, value_name = "PATH")]
+    map_file: Option<PathBuf>,
+
+    /// File listing variables that must be injected, one per line (# comments allowed)
+    #[arg(long, value_name = "PATH")]
+    env_required_from: Option<PathBuf>,
+
+    /// Print what each injected variable resolves from and exit without running anything
+    #[arg(long)]
+    dry_run: bool,
+
     /// Don't save missing secrets to the keyring
     #[arg(long)]
     no_save_missing: bool,
 
-    /// Test-only parameter: Provide secret value for automated testing (only available in test builds)
+    /// Run the child as this user (name or uid; Unix only, requires root)
+    #[arg(long)]
+    user: Option<String>,
+
+    /// Run the child with this primary group (name or gid; Unix only, requires root)
+    #[arg(long)]
+    group: Option<String>,
+
+    /// File-creation mask for the child, in octal (e.g. 077; Unix only)
+    #[arg(long, value_parser = process::parse_umask)]
+    umask: Option<u32>,
+
+    /// Cap the child's address space, e.g. 2G or 512M (Unix only)
+    #[arg(long, value_name = "SIZE", value_parser = process::parse_size)]
+    limit_mem: Option<u64>,
+
+    /// Cap the child's CPU time, e.g. 300s, 5m or 1h (Unix only)
+    #[arg(long, value_name = "TIME", value_parser = process::parse_cpu_time)]
+    limit_cpu: Option<u64>,
+
+    /// Cap the number of files the child can have open (Unix only)
+    #[arg(long, value_name = "N", value_parser = process::parse_nofile)]
+    limit_nofile: Option<u64>,
+
+    /// Only start the child if its executable (after PATH and symlink resolution) has this SHA-256
+    #[arg(long, value_name = "HEX", value_parser = executable::parse_sha256)]
+    verify_child_sha256: Option<String>,
+
+    /// Refuse to inject secrets into a setuid/setgid child instead of warning (Unix)
+    #[arg(long)]
+    deny_privileged_child: bool,
+
+    /// Warn when the inherited umask is more permissive than 022 while injecting secrets
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    warn_umask: bool,
+
+    /// Load PATH and other variables from your login shell before injecting secrets (Unix only)
+    #[arg(long)]
+    login_env: bool,
+
+    /// Run PowerShell (.ps1) scripts through `powershell -File` on Windows
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    windows_script_auto: bool,
+
+    /// Inject binary secrets as the path to a private temp file holding the decoded bytes
+    #[arg(long)]
+    decode_to_file: bool,
+
+    /// Also write the injected variables to this file (0600, removed when the child exits)
+    #[arg(long, value_name = "PATH")]
+    env_file_out: Option<PathBuf>,
+
+    /// Format of the --env-file-out file
+    #[arg(long, value_enum, default_value_t = EnvFileFormat::Dotenv, requires = "env_file_out")]
+    env_file_format: EnvFileFormat,
+
+    /// Print secret resolution timings and child CPU/memory usage to stderr after the run
+    #[arg(long)]
+    stats: bool,
+
+    /// Print one line for CI logs after the run, e.g. `local-secrets: injected=4 missing=0
+    /// backend=keyring exit=0 duration=12.3s`; fields are only ever appended, never renamed
+    #[arg(long)]
+    summary: bool,
+
+    /// Run each line of FILE (or stdin for `-`) as a command with the same secrets, in order
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["command_args", "watch"])]
+    commands: Option<PathBuf>,
+
+    /// With --commands, run the remaining commands after one fails
+    #[arg(long, requires = "commands")]
+    keep_going: bool,
+
+    /// Restart the child whenever one of the injected secrets changes in the keyring
+    #[arg(long)]
+    watch: bool,
+
+    /// Seconds between keyring checks in --watch mode
+    #[arg(long, value_name = "SECONDS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..), requires = "watch")]
+    watch_interval: u64,
+
+    /// Fail instead of warning when a secret is blank, whitespace-only, quotes-only or null/undefined/None
+    #[arg(long)]
+    fail_on_empty: bool,
+
+    /// Give the command a private temp directory (TMPDIR/TEMP/TMP) that is shredded after it exits
+    #[arg(long)]
+    isolate_tmp: bool,
+
+    /// Encoding of keys injected with --env NAME=derive:MASTER:INFO
+    #[arg(long, value_enum, default_value_t = DeriveEncoding::Hex)]
+    derive_encoding: DeriveEncoding,
+
+    /// Test builds only: secret for missing variables instead of prompting; takes
+    /// precedence over LOCAL_SECRETS_TEST_SECRET, and an empty value is an error
     #[cfg(feature = "test-secret-param")]
-    #[arg(long, hide = true)]
+    #[arg(long)]
     test_secret: Option<String>,
 
     /// Command and arguments to execute (everything after --)
@@ -38,77 +218,753 @@ struct Cli {
     command_args: Vec<String>,
 }
 
+/// Run-mode flags that only the `run` subcommand accepts
+#[derive(Args)]
+struct RunOnlyArgs {
+    /// File of variables to inject, one NAME or TARGET=SOURCE per line (# comments
+    /// allowed); --env entries win over the file
+    #[arg(long, value_name = "PATH")]
+    env_file: Option<PathBuf>,
+
+    /// Replace injected secret values in the child's stdout and stderr with ***MASKED***
+    #[arg(long)]
+    mask_output: bool,
+
+    /// Stop the child's whole process tree when the child or local-secrets exits: a job
+    /// object on Windows, a process group on Unix when there is no controlling terminal
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    kill_tree: bool,
+
+    /// Stop the child after this long (e.g. 30s, 5m, 2h) and exit with code 124
+    #[arg(long, value_name = "DURATION", value_parser = process::parse_duration, conflicts_with = "watch")]
+    timeout: Option<Duration>,
+
+    /// How long a timed-out child gets after SIGTERM before it is killed
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = process::parse_duration, requires = "timeout")]
+    kill_after: Duration,
+
+    /// Run the child in this directory; relative paths start from the current directory
+    #[arg(long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+
+    /// Run this script with `$SHELL -c` (powershell -Command on Windows) instead of a
+    /// command after --; secrets still arrive only through the environment
+    #[arg(long, value_name = "SCRIPT", conflicts_with_all = ["command_args", "commands"])]
+    shell: Option<String>,
+
+    /// Replace {{VAR}} in the command's arguments with the secret injected as VAR
+    /// (\{\{ for literal braces); other users can see arguments in process listings
+    #[arg(long, conflicts_with = "shell")]
+    substitute_args: bool,
+
+    /// Render this template's {{VAR}} placeholders with the secrets and feed the result
+    /// to the child's stdin, followed by EOF
+    #[arg(long, value_name = "PATH")]
+    stdin_template: Option<PathBuf>,
+}
+
+/// The bare form behaves as before `run` had any of its flags; in particular it
+/// leaves the child's background processes alone (`--kill-tree false`)
+impl Default for RunOnlyArgs {
+    fn default() -> Self {
+        Self {
+            env_file: None,
+            mask_output: false,
+            kill_tree: false,
+            timeout: None,
+            kill_after: Duration::from_secs(10),
+            cwd: None,
+            shell: None,
+            substitute_args: false,
+            stdin_template: None,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
+    /// Run a command with secrets injected: run --env NAME -- COMMAND [ARGS]...
+    Run {
+        #[command(flatten)]
+        args: Box<RunArgs>,
+        #[command(flatten)]
+        only: RunOnlyArgs,
+    },
     /// Store a secret in the keyring
     Store {
+        /// Environment variable name; several names are prompted for in turn
+        #[arg(required = true, value_name = "VARIABLE")]
+        variables: Vec<String>,
+        /// Do nothing if the variable is already stored
+        #[arg(long)]
+        if_missing: bool,
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+    /// Change a secret that is already in the keyring; fails if it does not exist
+    Update {
         /// Environment variable name
         variable: String,
-        /// Test-only parameter: Provide secret value for automated testing (only available in test builds)
-        #[cfg(feature = "test-secret-param")]
-        #[arg(long, hide = true)]
-        test_secret: Option<String>,
+        #[command(flatten)]
+        source: SourceArgs,
     },
     /// Delete a secret from the keyring  
     Delete {
+        /// Environment variable names
+        #[arg(required = true, value_name = "VARIABLE")]
+        variables: Vec<String>,
+        /// Succeed when a secret does not exist
+        #[arg(long)]
+        ignore_missing: bool,
+    },
+    /// Print one stored secret to stdout, e.g. TOKEN=$(local-secrets get GITHUB_PAT)
+    Get {
+        /// Environment variable name, optionally NAME@namespace
+        variable: String,
+        /// End the value with a newline
+        #[arg(long)]
+        newline: bool,
+        /// Print even when stdout is a terminal
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check a stored secret by running a probe command with it injected
+    Verify {
         /// Environment variable name
         variable: String,
+        /// Command whose exit status decides validity; the secret is in its environment
+        #[arg(long, value_name = "COMMAND")]
+        probe: String,
+    },
+    /// Re-enter an existing secret (typed twice), showing its current length and fingerprint
+    Edit {
+        /// Environment variable name
+        variable: String,
+        /// Leave the secret unchanged when nothing is entered
+        #[arg(long)]
+        keep_on_empty: bool,
+        /// Test builds only: new value instead of prompting; takes precedence
+        /// over LOCAL_SECRETS_TEST_SECRET, and an empty value is an error
+        #[cfg(feature = "test-secret-param")]
+        #[arg(long)]
+        test_secret: Option<String>,
+    },
+    /// Replace a stored secret, keeping the old value as VARIABLE__previous
+    Rotate {
+        /// Environment variable name
+        variable: String,
+        /// Do not keep the old value (an earlier VARIABLE__previous is removed)
+        #[arg(long)]
+        no_backup: bool,
+        /// Command to check the new value with; on failure the old value is restored
+        #[arg(long, value_name = "COMMAND")]
+        exec: Option<String>,
+        /// Test builds only: new value instead of prompting; takes precedence
+        /// over LOCAL_SECRETS_TEST_SECRET, and an empty value is an error
+        #[cfg(feature = "test-secret-param")]
+        #[arg(long)]
+        test_secret: Option<String>,
+    },
+    /// Import KEY=VALUE entries from a .env file (or, with --sops, an encrypted file) into the keyring
+    Import {
+        /// File to import
+        path: PathBuf,
+        /// Decrypt the file with `sops -d` (plaintext stays in memory)
+        #[arg(long)]
+        sops: bool,
+        /// List what would be stored without storing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Replace secrets that are already stored instead of skipping them
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Run helm with secrets mapped to chart values (piped to `--values -` by default)
+    HelmArgs {
+        /// Chart values path and the secret that fills it, e.g. db.password=DB_PASS
+        #[arg(long = "map", value_name = "PATH=VARIABLE", required = true)]
+        maps: Vec<String>,
+        /// Pass values as --set-string arguments instead (visible in process listings)
+        #[arg(long)]
+        set_string: bool,
+        /// Arguments for helm (everything after --)
+        #[arg(last = true)]
+        helm_args: Vec<String>,
+    },
+    /// Show a secret's length, fingerprint and backend, never its value
+    Info {
+        /// Environment variable name, optionally NAME@namespace
+        variable: String,
+    },
+    /// Show where a secret lives in the keyring (service, account, store), never its value
+    Where {
+        /// Environment variable name, optionally NAME@namespace
+        variable: String,
+        /// Print a single JSON object instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find out which of the candidate variables a command reads, using canary values
+    EnvDiff {
+        /// Variable to test; repeat for each candidate
+        #[arg(long = "candidate", value_name = "NAME", required = true)]
+        candidates: Vec<String>,
+        /// Command and arguments to probe (everything after --); it is run several times
+        #[arg(last = true, required = true)]
+        command_args: Vec<String>,
+    },
+    /// Scan shell history for secrets typed inline (read-only)
+    HistoryCheck {
+        /// Also report lines containing these stored secrets' values verbatim
+        variables: Vec<String>,
+        /// History file to scan instead of the detected bash/zsh/fish/PowerShell files
+        #[arg(long = "file", value_name = "PATH")]
+        files: Vec<PathBuf>,
+    },
+    /// Print secrets as a VS Code env JSON object or JetBrains <envs> block (plain text!)
+    IdeResolve {
+        /// Variables to resolve, NAME, NAME@namespace or TARGET=SOURCE
+        variables: Vec<String>,
+        /// Also resolve the aliases (and optional names) from this map file
+        #[arg(long, value_name = "PATH")]
+        map_file: Option<PathBuf>,
+        /// Output shape
+        #[arg(long, value_enum)]
+        format: ide::IdeFormat,
+        /// Acknowledge that secret values are written to stdout in plain text
+        #[arg(long)]
+        unsafe_plain_output: bool,
+    },
+    /// Check GitHub for a newer release (nothing is downloaded)
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether an update exists; required, as installing is left to your package manager
+        #[arg(long, required = true)]
+        check: bool,
+    },
+    /// Low-level keyring diagnostics for bug reports
+    Debug {
+        #[command(subcommand)]
+        action: DebugAction,
+    },
+    /// Built-in child process for integration tests and diagnostics
+    #[command(name = "__selftest", hide = true)]
+    Selftest {
+        #[command(subcommand)]
+        action: SelftestAction,
     },
 }
 
+/// Where `store` and `update` read the secret from
+#[derive(Args)]
+struct SourceArgs {
+    /// Read the secret from the system clipboard instead of prompting
+    #[arg(long)]
+    from_clipboard: bool,
+    /// Clear the clipboard after the secret has been stored (with --from-clipboard)
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, requires = "from_clipboard")]
+    clear_clipboard: bool,
+    /// Store the file as binary data (base64 envelope); requires --from-file
+    #[arg(long, requires = "from_file", conflicts_with = "from_clipboard")]
+    binary: bool,
+    /// Read the secret from a file (a pipe such as /dev/stdin works too)
+    #[arg(long, value_name = "PATH", conflicts_with = "from_clipboard")]
+    from_file: Option<PathBuf>,
+    /// Drop one trailing newline from the --from-file contents
+    #[arg(long, requires = "from_file", conflicts_with = "binary")]
+    trim_newline: bool,
+    /// Read the secret from standard input, e.g. `op read ... | local-secrets store TOKEN --stdin`
+    #[arg(long, conflicts_with_all = ["from_file", "from_clipboard"])]
+    stdin: bool,
+    /// Keep a trailing newline read with --stdin
+    #[arg(long, requires = "stdin")]
+    keep_newline: bool,
+    /// Store the standard output of the command given after --, e.g. `-- gcloud auth print-access-token`
+    #[arg(long, requires = "producer", conflicts_with_all = ["from_file", "from_clipboard", "stdin"])]
+    from_command: bool,
+    /// Command for --from-command (everything after --)
+    #[arg(last = true, requires = "from_command", value_name = "COMMAND")]
+    producer: Vec<String>,
+    /// Test builds only: secret to store instead of prompting; takes precedence
+    /// over LOCAL_SECRETS_TEST_SECRET, and an empty value is an error
+    #[cfg(feature = "test-secret-param")]
+    #[arg(long)]
+    test_secret: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum DebugAction {
+    /// Show the identifiers of an entry and try each keyring primitive on it with timings
+    Entry {
+        /// Environment variable name, optionally NAME@namespace
+        variable: String,
+        /// Acknowledge that raw platform errors are printed
+        #[arg(long)]
+        debug: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SelftestAction {
+    /// Print the value of an environment variable (exit 2 when unset)
+    EchoEnv { variable: String },
+    /// Sleep for the given number of seconds
+    Sleep { seconds: u64 },
+    /// Exit with the given code
+    Exit { code: u8 },
+}
+
 fn main() -> ExitCode {
-    if let Err(err) = run() {
-        eprintln!("Error: {:#}", err);
-        return ExitCode::FAILURE;
+    let cli = Cli::parse();
+    let errors_json = cli.errors_json;
+    errors::set_json_warnings(errors_json);
+
+    // The OS keyring unless --backend picks another store
+    let mut backend: Box<dyn SecretBackend> = cli.backend.open();
+
+    let result = match selected_namespace(cli.namespace.as_deref()) {
+        Ok(Some(namespace)) => {
+            backend = backend.namespaced(&namespace);
+            run(cli, &mut *backend)
+        }
+        Ok(None) => run(cli, &mut *backend),
+        Err(err) => Err(err),
+    };
+    otel::flush();
+    match result {
+        Ok(code) => code,
+        Err(err) if errors_json => {
+            eprintln!("{}", errors::to_json(&err, backend.name()));
+            ExitCode::from(errors::classify(&err).0.exit_code())
+        }
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            // A timed-out prompt is distinguishable even without --errors-json
+            match errors::classify(&err).0 {
+                code @ ErrorCode::PromptTimeout => ExitCode::from(code.exit_code()),
+                _ => ExitCode::FAILURE,
+            }
+        }
     }
-    ExitCode::SUCCESS
 }
 
-fn run() -> Result<()> {
-    let cli = Cli::parse();
+/// Environment variable that selects a namespace when --namespace is not given
+const NAMESPACE_ENV: &str = "LOCAL_SECRETS_NAMESPACE";
+
+/// The namespace from --namespace or `LOCAL_SECRETS_NAMESPACE` (ignored when empty), validated
+fn selected_namespace(flag: Option<&str>) -> Result<Option<String>> {
+    let namespace = match flag {
+        Some(namespace) => namespace.to_string(),
+        None => match std::env::var(NAMESPACE_ENV) {
+            Ok(namespace) if !namespace.is_empty() => namespace,
+            _ => return Ok(None),
+        },
+    };
+    security::validate_namespace(&namespace)
+        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+    Ok(Some(namespace))
+}
 
-    // Use keyring backend for secure secret storage
-    let mut backend: Box<dyn SecretBackend> = Box::new(KeyringBackend::new());
+/// `store` and `update`: picks the secret source from the flags, prompting by default
+fn store(
+    backend: &mut dyn SecretBackend,
+    variable: &str,
+    args: SourceArgs,
+    prompt: SecretSource,
+    mode: StoreMode,
+) -> Result<()> {
+    let source = if let Some(path) = args.from_file {
+        if args.binary {
+            SecretSource::BinaryFile(path)
+        } else {
+            SecretSource::TextFile {
+                path,
+                trim_newline: args.trim_newline,
+            }
+        }
+    } else if args.from_command {
+        SecretSource::Command(args.producer)
+    } else if args.stdin {
+        SecretSource::Stdin {
+            keep_newline: args.keep_newline,
+        }
+    } else if args.from_clipboard {
+        SecretSource::Clipboard {
+            clear_after: args.clear_clipboard,
+        }
+    } else {
+        prompt
+    };
+
+    #[cfg(feature = "test-secret-param")]
+    {
+        commands::store_with_test_value(
+            backend,
+            variable,
+            args.test_secret.as_deref(),
+            source,
+            mode,
+        )
+    }
+    #[cfg(not(feature = "test-secret-param"))]
+    {
+        commands::store(backend, variable, source, mode)
+    }
+}
+
+fn run(cli: Cli, backend: &mut dyn SecretBackend) -> Result<ExitCode> {
+    // Transient secret files must not survive Ctrl-C, SIGTERM or a panic
+    cleanup::install()?;
+
+    #[cfg(feature = "otel")]
+    otel::init(cli.otel_endpoint.as_deref())
+        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
 
+    let prompt = || SecretSource::Prompt {
+        style: cli.prompt_style,
+        raw_input: cli.raw_input,
+        timeout: cli.prompt_timeout.map(Duration::from_secs),
+    };
     match cli.command {
         Some(Commands::Store {
+            variables,
+            if_missing,
+            source,
+        }) => {
+            let mode = if if_missing {
+                StoreMode::IfMissing
+            } else {
+                StoreMode::Upsert
+            };
+            if let [variable] = variables.as_slice() {
+                store(backend, variable, source, prompt(), mode)?;
+            } else {
+                if source.from_file.is_some()
+                    || source.from_clipboard
+                    || source.stdin
+                    || source.from_command
+                {
+                    return Err(errors::coded(
+                        ErrorCode::InvalidInput,
+                        None,
+                        "--from-clipboard, --from-file, --stdin and --from-command store a single variable"
+                            .to_string(),
+                    ));
+                }
+                #[cfg(feature = "test-secret-param")]
+                let provided = commands::test_secret(source.test_secret.as_deref())?;
+                #[cfg(not(feature = "test-secret-param"))]
+                let provided = commands::test_secret(None)?;
+                commands::store_many(backend, &variables, provided, prompt(), mode)?;
+            }
+        }
+        Some(Commands::Update { variable, source }) => {
+            store(backend, &variable, source, prompt(), StoreMode::UpdateOnly)?;
+        }
+        Some(Commands::Delete {
+            variables,
+            ignore_missing,
+        }) => {
+            commands::delete(&mut *backend, &variables, ignore_missing)?;
+        }
+        Some(Commands::Verify { variable, probe }) => {
+            return commands::verify(&*backend, &variable, &probe);
+        }
+        Some(Commands::Edit {
             variable,
+            keep_on_empty,
             #[cfg(feature = "test-secret-param")]
             test_secret,
         }) => {
             #[cfg(feature = "test-secret-param")]
-            {
-                commands::store_with_test_value(&mut *backend, &variable, test_secret.as_deref())?;
-            }
+            let provided = commands::test_secret(test_secret.as_deref())?;
             #[cfg(not(feature = "test-secret-param"))]
-            {
-                commands::store(&mut *backend, &variable)?;
-            }
+            let provided = commands::test_secret(None)?;
+            commands::edit(&mut *backend, &variable, provided, prompt(), keep_on_empty)?;
         }
-        Some(Commands::Delete { variable }) => {
-            commands::delete(&mut *backend, &variable)?;
+        Some(Commands::Rotate {
+            variable,
+            no_backup,
+            exec,
+            #[cfg(feature = "test-secret-param")]
+            test_secret,
+        }) => {
+            #[cfg(feature = "test-secret-param")]
+            let provided = commands::test_secret(test_secret.as_deref())?;
+            #[cfg(not(feature = "test-secret-param"))]
+            let provided = commands::test_secret(None)?;
+            commands::rotate(
+                &mut *backend,
+                &variable,
+                provided,
+                prompt(),
+                !no_backup,
+                exec.as_deref(),
+            )?;
+        }
+        Some(Commands::Import {
+            path,
+            sops,
+            dry_run,
+            overwrite,
+        }) => {
+            commands::import(&mut *backend, &path, sops, dry_run, overwrite)?;
+        }
+        Some(Commands::HelmArgs {
+            maps,
+            set_string,
+            helm_args,
+        }) => {
+            return commands::helm_args(&*backend, &maps, set_string, &helm_args);
+        }
+        Some(Commands::Get {
+            variable,
+            newline,
+            force,
+        }) => {
+            commands::get(&*backend, &variable, newline, force)?;
+        }
+        Some(Commands::Info { variable }) => {
+            commands::info(&*backend, &variable)?;
+        }
+        Some(Commands::Where { variable, json }) => {
+            return commands::where_is(&*backend, &variable, json);
+        }
+        Some(Commands::EnvDiff {
+            candidates,
+            command_args,
+        }) => {
+            return commands::env_diff(&candidates, &command_args);
+        }
+        Some(Commands::HistoryCheck { variables, files }) => {
+            return commands::history_check(&*backend, &variables, &files);
+        }
+        Some(Commands::IdeResolve {
+            variables,
+            map_file,
+            format,
+            unsafe_plain_output,
+        }) => {
+            let (specs, optional) = match map_file {
+                Some(path) => {
+                    let map = mapfile::load(&path)
+                        .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+                    (mapfile::merge(&variables, &map), map.optional)
+                }
+                None => (variables, Vec::new()),
+            };
+            return commands::ide_resolve(
+                &*backend,
+                &specs,
+                &optional,
+                format,
+                unsafe_plain_output,
+            );
+        }
+        #[cfg(feature = "self-update")]
+        Some(Commands::SelfUpdate { check: _ }) => {
+            return update::check();
+        }
+        Some(Commands::Debug {
+            action: DebugAction::Entry { variable, debug },
+        }) => {
+            return commands::debug_entry(&*backend, &variable, debug);
+        }
+        Some(Commands::Selftest { action }) => {
+            // The self-test child never touches the backend
+            return Ok(match action {
+                SelftestAction::EchoEnv { variable } => commands::selftest_echo_env(&variable),
+                SelftestAction::Sleep { seconds } => commands::selftest_sleep(seconds),
+                SelftestAction::Exit { code } => ExitCode::from(code),
+            });
+        }
+        Some(Commands::Run { args, only }) => {
+            run_mode(
+                backend,
+                *args,
+                only,
+                cli.prompt_style,
+                cli.raw_input,
+                cli.prompt_timeout.map(Duration::from_secs),
+                &["local-secrets", "run", "--help"],
+            )?;
         }
         None => {
-            // Check if command arguments are provided
-            if cli.command_args.is_empty() && cli.env.is_empty() {
-                // No subcommand and no command to run - show help
-                Cli::parse_from(["local-secrets", "--help"]);
-            } else if cli.command_args.is_empty() {
-                anyhow::bail!("No command specified. Provide command arguments after --");
-            } else {
-                // Security validation before execution
-                validate_cli_security(&cli.env, &cli.command_args)?;
-
-                // Run mode - inject environment variables and execute command
-                commands::run_with_env(
-                    &mut *backend,
-                    &cli.env,
-                    cli.no_save_missing,
-                    &cli.command_args,
-                )?;
+            run_mode(
+                backend,
+                cli.run,
+                RunOnlyArgs::default(),
+                cli.prompt_style,
+                cli.raw_input,
+                cli.prompt_timeout.map(Duration::from_secs),
+                &["local-secrets", "--help"],
+            )?;
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Run mode: injects the requested secrets into the command (or each queued command)
+fn run_mode(
+    backend: &mut dyn SecretBackend,
+    args: RunArgs,
+    only: RunOnlyArgs,
+    prompt_style: PromptStyle,
+    raw_input: bool,
+    prompt_timeout: Option<Duration>,
+    help: &[&str],
+) -> Result<()> {
+    // `NAME!` is prompted for when missing but never saved
+    let mut no_save = Vec::new();
+    let cli_env: Vec<String> = args
+        .env
+        .iter()
+        .map(|spec| {
+            let (spec, marked) = split_no_save(spec);
+            if marked {
+                no_save.push(injected_name(spec).to_string());
             }
+            spec.to_string()
+        })
+        .collect();
+
+    // Listed variables join --env before anything is resolved, so a bad file fails early
+    let listed = match &only.env_file {
+        Some(path) => {
+            mapfile::load_env_list(path).map_err(errors::tagged(ErrorCode::InvalidInput, None))?
+        }
+        None => Vec::new(),
+    };
+    let mut explicit = cli_env.clone();
+    mapfile::add_required(&mut explicit, &listed);
+
+    let (mut env_specs, optional) = match &args.map_file {
+        Some(path) => {
+            let map = mapfile::load(path).map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+            no_save.extend(map.no_save.iter().cloned());
+            (mapfile::merge(&explicit, &map), map.optional)
         }
+        None => (explicit, Vec::new()),
+    };
+    // Required names join whatever --env and the map file already provide
+    let required = match &args.env_required_from {
+        Some(path) => {
+            mapfile::load_required(path).map_err(errors::tagged(ErrorCode::InvalidInput, None))?
+        }
+        None => Vec::new(),
+    };
+    mapfile::add_required(&mut env_specs, &required);
+
+    if args.dry_run {
+        validate_cli_security(&env_specs, &[], None)
+            .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        print!(
+            "{}",
+            mapfile::render_table(&env_specs, &cli_env, &listed, &required, &optional, &no_save)
+        );
+        return Ok(());
     }
 
+    // --shell stands in for the command; the script only reaches the shell as one argument
+    let command_args = match &only.shell {
+        Some(script) => {
+            security::validate_shell_script(script)
+                .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+            process::shell_command(script)
+        }
+        None => args.command_args.clone(),
+    };
+
+    // Check if command arguments are provided
+    let no_command = command_args.is_empty() && args.commands.is_none();
+    if no_command && env_specs.is_empty() {
+        // No subcommand and no command to run - show help
+        Cli::parse_from(help);
+    } else if no_command {
+        anyhow::bail!("No command specified. Provide command arguments after --");
+    } else {
+        // Security validation before execution
+        let validating = otel::span("validate");
+        let cwd = only
+            .cwd
+            .as_deref()
+            .map(process::working_dir)
+            .transpose()
+            .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        validate_cli_security(&env_specs, &command_args, cwd.as_deref())
+            .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        for spec in &env_specs {
+            security::warn_if_critical(injected_name(spec));
+        }
+        let queue = match &args.commands {
+            Some(source) => batch::load(source, cwd.as_deref())
+                .map_err(errors::tagged(ErrorCode::InvalidInput, None))?,
+            None => vec![QueuedCommand::single(command_args)],
+        };
+        if only.substitute_args {
+            commands::check_placeholders(&queue, &env_specs)
+                .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        }
+        let stdin_template = only
+            .stdin_template
+            .as_deref()
+            .map(|path| commands::load_stdin_template(path, &env_specs))
+            .transpose()
+            .map_err(errors::tagged(ErrorCode::InvalidInput, None))?;
+        #[cfg(feature = "test-secret-param")]
+        let test_secret = commands::test_secret(args.test_secret.as_deref())?;
+        #[cfg(not(feature = "test-secret-param"))]
+        let test_secret = commands::test_secret(None)?;
+        validating.end();
+
+        // Run mode - inject environment variables and execute command
+        let options = RunOptions {
+            user: args.user,
+            group: args.group,
+            umask: args.umask,
+            warn_umask: args.warn_umask,
+            verify_child_sha256: args.verify_child_sha256,
+            deny_privileged_child: args.deny_privileged_child,
+            limits: process::Limits {
+                memory: args.limit_mem,
+                cpu: args.limit_cpu,
+                nofile: args.limit_nofile,
+            },
+            login_env: args.login_env,
+            windows_script_auto: args.windows_script_auto,
+            decode_to_file: args.decode_to_file,
+            env_file_out: args.env_file_out,
+            env_file_format: args.env_file_format,
+            stats: args.stats,
+            summary: args.summary,
+            watch_interval: args.watch.then(|| Duration::from_secs(args.watch_interval)),
+            isolate_tmp: args.isolate_tmp,
+            derive_encoding: args.derive_encoding,
+            optional,
+            no_save,
+            fail_on_empty: args.fail_on_empty,
+            raw_input,
+            prompt_style,
+            prompt_timeout,
+            keep_going: args.keep_going,
+            mask_output: only.mask_output,
+            kill_tree: only.kill_tree,
+            timeout: only.timeout,
+            kill_after: only.kill_after,
+            substitute_args: only.substitute_args,
+            stdin_template,
+            cwd,
+            test_secret,
+        };
+
+        commands::run_with_env(
+            &mut *backend,
+            &env_specs,
+            args.no_save_missing,
+            &queue,
+            &options,
+        )?;
+    }
     Ok(())
 }
@@ -0,0 +1,414 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+use toml_edit::{ImDocument, Item, Key};
+
+use crate::security::{
+    display_name, injected_name, split_env_spec, validate_env_var_name, validate_namespace,
+};
+
+/// Largest accepted map file; real ones are a few hundred bytes
+const MAX_MAP_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Aliases loaded from `--map-file`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MapFile {
+    /// `(TARGET, SOURCE)` pairs from the `[map]` table, in file order
+    pub entries: Vec<(String, String)>,
+    /// Injected names that may be missing from the keyring
+    pub optional: Vec<String>,
+    /// Injected names whose prompted value is never saved
+    pub no_save: Vec<String>,
+}
+
+/// Reads and validates a map file
+pub fn load(path: &Path) -> Result<MapFile> {
+    let text = read_bounded(path, "map file")?;
+    parse(&text).with_context(|| format!("Invalid map file {}", path.display()))
+}
+
+/// Reads and validates a `run --env-file` list
+pub fn load_env_list(path: &Path) -> Result<Vec<String>> {
+    let text = read_bounded(path, "env list file")?;
+    parse_env_list(&text).with_context(|| format!("Invalid env list file {}", path.display()))
+}
+
+/// Reads and validates an `--env-required-from` file
+pub fn load_required(path: &Path) -> Result<Vec<String>> {
+    let text = read_bounded(path, "required secrets file")?;
+    parse_required(&text)
+        .with_context(|| format!("Invalid required secrets file {}", path.display()))
+}
+
+fn read_bounded(path: &Path, kind: &str) -> Result<String> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {} {}", kind, path.display()))?;
+    let mut text = String::new();
+    file.take(MAX_MAP_FILE_BYTES + 1)
+        .read_to_string(&mut text)
+        .with_context(|| format!("Failed to read {} {}", kind, path.display()))?;
+    if text.len() as u64 > MAX_MAP_FILE_BYTES {
+        return Err(anyhow::anyhow!(
+            "{} {} is too large (max {} bytes)",
+            kind,
+            path.display(),
+            MAX_MAP_FILE_BYTES
+        ));
+    }
+    Ok(text)
+}
+
+/// Parses one variable name per line; blank lines and `#` comments are ignored.
+///
+/// Like map files, every line is checked before anything is reported.
+pub fn parse_required(text: &str) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+    let mut problems = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let name = line.split('#').next().unwrap_or_default().trim();
+        if name.is_empty() {
+            continue;
+        }
+        match validate_env_var_name(name) {
+            Ok(()) if !names.iter().any(|known| known == name) => names.push(name.to_string()),
+            Ok(()) => {}
+            Err(err) => problems.push(format!("Line {}: {:#}", index + 1, err)),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(names)
+    } else {
+        Err(anyhow::anyhow!(
+            "{} invalid line{}:\n  {}",
+            problems.len(),
+            if problems.len() == 1 { "" } else { "s" },
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Parses one `NAME`, `NAME@namespace` or `TARGET=SOURCE` per line; blank lines and
+/// `#` comments are ignored and a repeated target keeps its first line.
+pub fn parse_env_list(text: &str) -> Result<Vec<String>> {
+    let mut specs: Vec<String> = Vec::new();
+    let mut problems = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let spec = line.split('#').next().unwrap_or_default().trim();
+        if spec.is_empty() {
+            continue;
+        }
+        let checked = match spec.split_once('=') {
+            Some((target, source)) => check_alias(target.trim(), source.trim())
+                .map(|source| format!("{}={}", target.trim(), source)),
+            None => check_source(spec).map(|()| spec.to_string()),
+        };
+        match checked {
+            Ok(spec) => add_required(&mut specs, &[spec]),
+            Err(err) => problems.push(format!("Line {}: {:#}", index + 1, err)),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(specs)
+    } else {
+        Err(anyhow::anyhow!(
+            "{} invalid line{}:\n  {}",
+            problems.len(),
+            if problems.len() == 1 { "" } else { "s" },
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Appends specs whose variable is not injected yet; duplicates are dropped silently
+pub fn add_required(specs: &mut Vec<String>, required: &[String]) {
+    for spec in required {
+        let name = injected_name(spec);
+        if !specs.iter().any(|known| injected_name(known) == name) {
+            specs.push(spec.clone());
+        }
+    }
+}
+
+/// Parses `[map] TARGET = "SOURCE"` pairs and a top-level `optional = [...]`.
+///
+/// Every entry is checked before anything is reported, so one run lists all
+/// the problems in the file instead of only the first.
+pub fn parse(text: &str) -> Result<MapFile> {
+    let document = ImDocument::parse(text).map_err(|err| {
+        let line = err.span().map_or(1, |span| line_of(text, span));
+        anyhow::anyhow!("Line {}: {}", line, err.message().trim())
+    })?;
+
+    let mut map = MapFile::default();
+    let mut problems = Vec::new();
+    let root = document.as_table();
+    for (key, item) in root.iter() {
+        let line = key_line(text, root.key(key));
+        match key {
+            "map" => match item.as_table_like() {
+                Some(table) => {
+                    for (target, value) in table.iter() {
+                        let line = key_line(text, table.key(target));
+                        match check_entry(target, value) {
+                            Ok(source) => map.entries.push((target.to_string(), source)),
+                            Err(err) => problems.push(format!("Line {}: {:#}", line, err)),
+                        }
+                    }
+                }
+                None => problems.push(format!("Line {}: 'map' must be a table", line)),
+            },
+            "optional" => parse_names(text, key, item, line, &mut map.optional, &mut problems),
+            "no_save" => parse_names(text, key, item, line, &mut map.no_save, &mut problems),
+            other => problems.push(format!(
+                "Line {}: unknown key '{}' (expected [map], optional and no_save)",
+                line,
+                display_name(other)
+            )),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(map)
+    } else {
+        Err(anyhow::anyhow!(
+            "{} invalid entr{}:\n  {}",
+            problems.len(),
+            if problems.len() == 1 { "y" } else { "ies" },
+            problems.join("\n  ")
+        ))
+    }
+}
+
+/// Collects a top-level array of variable names such as `optional`
+fn parse_names(
+    text: &str,
+    key: &str,
+    item: &Item,
+    line: usize,
+    names: &mut Vec<String>,
+    problems: &mut Vec<String>,
+) {
+    let Some(array) = item.as_array() else {
+        problems.push(format!(
+            "Line {}: '{}' must be an array of names",
+            line, key
+        ));
+        return;
+    };
+    for name in array.iter() {
+        let line = name.span().map_or(line, |span| line_of(text, span));
+        match name.as_str() {
+            Some(name) => match validate_env_var_name(name) {
+                Ok(()) => names.push(name.to_string()),
+                Err(err) => problems.push(format!("Line {}: {:#}", line, err)),
+            },
+            None => problems.push(format!("Line {}: '{}' entries must be strings", line, key)),
+        }
+    }
+}
+
+/// Validates one `TARGET = "SOURCE"` entry and returns SOURCE
+fn check_entry(target: &str, value: &Item) -> Result<String> {
+    validate_env_var_name(target)
+        .with_context(|| format!("Invalid target name {}", display_name(target)))?;
+    let source = value.as_str().ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} must map to a secret name in quotes",
+            display_name(target)
+        )
+    })?;
+    check_alias(target, source)
+}
+
+/// Validates `TARGET=SOURCE` and returns SOURCE
+fn check_alias(target: &str, source: &str) -> Result<String> {
+    validate_env_var_name(target)
+        .with_context(|| format!("Invalid target name {}", display_name(target)))?;
+    check_source(source).with_context(|| format!("Invalid source for {}", display_name(target)))?;
+    Ok(source.to_string())
+}
+
+/// Validates a secret reference, `NAME` or `NAME@namespace`
+fn check_source(source: &str) -> Result<()> {
+    let (name, namespace) = split_env_spec(source);
+    validate_env_var_name(name)?;
+    if let Some(namespace) = namespace {
+        validate_namespace(namespace)?;
+    }
+    Ok(())
+}
+
+/// Merges map file aliases beneath `--env`: a name given on the command line wins
+pub fn merge(cli_env: &[String], map: &MapFile) -> Vec<String> {
+    let mut specs = cli_env.to_vec();
+    for (target, source) in &map.entries {
+        if !cli_env.iter().any(|spec| injected_name(spec) == target) {
+            specs.push(format!("{}={}", target, source));
+        }
+    }
+    specs
+}
+
+/// Table of what each injected name resolves from, for `--dry-run`
+pub fn render_table(
+    specs: &[String],
+    cli_env: &[String],
+    listed: &[String],
+    required: &[String],
+    optional: &[String],
+    no_save: &[String],
+) -> String {
+    let width = specs
+        .iter()
+        .map(|spec| injected_name(spec).len())
+        .max()
+        .unwrap_or_default();
+    let mut out = String::new();
+    for spec in specs {
+        let target = injected_name(spec);
+        let origin = if cli_env.contains(spec) {
+            "--env"
+        } else if listed.contains(spec) {
+            "env file"
+        } else if required.contains(spec) {
+            "required file"
+        } else {
+            "map file"
+        };
+        let mut notes = origin.to_string();
+        if optional.iter().any(|name| name == target) {
+            notes.push_str(", optional");
+        }
+        if no_save.iter().any(|name| name == target) {
+            notes.push_str(", not saved");
+        }
+        out.push_str(&format!(
+            "{:width$}  <- {}  ({})\n",
+            target,
+            crate::security::source_spec(spec),
+            notes,
+            width = width
+        ));
+    }
+    out
+}
+
+fn key_line(text: &str, key: Option<&Key>) -> usize {
+    key.and_then(Key::span)
+        .map_or(0, |span| line_of(text, span))
+}
+
+fn line_of(text: &str, span: Range<usize>) -> usize {
+    let end = span.start.min(text.len());
+    text.as_bytes()[..end]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_map_and_optional() {
+        let map = parse(
+            "optional = [\"NPM_TOKEN\"]\nno_save = [\"OTP\"]\n\n[map]\nGH_TOKEN = \"GITHUB_PAT\"\nNPM_TOKEN = \"NPM_TOKEN@org\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.entries,
+            [
+                ("GH_TOKEN".to_string(), "GITHUB_PAT".to_string()),
+                ("NPM_TOKEN".to_string(), "NPM_TOKEN@org".to_string()),
+            ]
+        );
+        assert_eq!(map.optional, ["NPM_TOKEN"]);
+        assert_eq!(map.no_save, ["OTP"]);
+
+        let err = parse("no_save = \"OTP\"\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 1: 'no_save' must be an array of names"));
+    }
+
+    #[test]
+    fn test_parse_reports_every_bad_entry_with_its_line() {
+        let err = parse(
+            "[map]\nGOOD = \"SOURCE\"\n\"1BAD\" = \"SOURCE\"\nNUMBER = 5\nBAD_SOURCE = \"$(x)\"\n",
+        )
+        .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("3 invalid entries"), "{}", message);
+        assert!(message.contains("Line 3: Invalid target name 1BAD"));
+        assert!(message.contains("Line 4: NUMBER must map to a secret name in quotes"));
+        assert!(message.contains("Line 5: Invalid source for BAD_SOURCE"));
+
+        let err = parse("[map]\nA = \"B\"\nA = \"C\"\n").unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Line 3:"));
+
+        let err = parse("[profile]\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 1: unknown key 'profile'"));
+    }
+
+    #[test]
+    fn test_parse_required_names() {
+        let names =
+            parse_required("# every environment\nAPI_KEY\n\nDB_PASS  # prod only\nAPI_KEY\n")
+                .unwrap();
+        assert_eq!(names, ["API_KEY", "DB_PASS"]);
+
+        let err = parse_required("OK\n1BAD\nALSO OK\n").unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("2 invalid lines"), "{}", message);
+        assert!(message.contains("Line 2:") && message.contains("Line 3:"));
+
+        let mut specs = vec!["GH=GITHUB_PAT".to_string(), "API_KEY".to_string()];
+        add_required(&mut specs, &["GH".to_string(), "DB_PASS".to_string()]);
+        assert_eq!(specs, ["GH=GITHUB_PAT", "API_KEY", "DB_PASS"]);
+    }
+
+    #[test]
+    fn test_parse_env_list_allows_aliases_and_dedupes() {
+        let specs = parse_env_list(
+            "# project secrets\nAPI_KEY\nGH_TOKEN = GITHUB_PAT  # alias\nNPM_TOKEN@org\n\nAPI_KEY\nGH_TOKEN=OTHER\n",
+        )
+        .unwrap();
+        assert_eq!(specs, ["API_KEY", "GH_TOKEN=GITHUB_PAT", "NPM_TOKEN@org"]);
+
+        let err = parse_env_list("OK\nBAD=1SOURCE\n=EMPTY\n").unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("2 invalid lines"), "{}", message);
+        assert!(
+            message.contains("Line 2: Invalid source for BAD"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("Line 3: Invalid target name"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_cli_wins_over_map_file() {
+        let map = parse("[map]\nGH_TOKEN = \"GITHUB_PAT\"\nAPI_KEY = \"OTHER\"\n").unwrap();
+        let cli = vec!["API_KEY".to_string()];
+        let specs = merge(&cli, &map);
+        assert_eq!(specs, ["API_KEY", "GH_TOKEN=GITHUB_PAT"]);
+        assert_eq!(
+            render_table(
+                &specs,
+                &cli,
+                &[],
+                &[],
+                &["GH_TOKEN".to_string()],
+                &["API_KEY".to_string()]
+            ),
+            "API_KEY   <- API_KEY  (--env, not saved)\nGH_TOKEN  <- GITHUB_PAT  (map file, optional)\n"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use zeroize::Zeroizing;
+
+/// What a masked secret is replaced with
+pub const MASK: &[u8] = b"***MASKED***";
+
+/// Values shorter than this are not masked; they would match ordinary output
+pub const MIN_MASKED_LEN: usize = 4;
+
+/// Secret values to redact from the child's output (`run --mask-output`)
+#[derive(Clone)]
+pub struct Patterns(Arc<Vec<Zeroizing<Vec<u8>>>>);
+
+impl Patterns {
+    /// Longest values first, so a secret containing another is masked whole
+    pub fn new<'a>(values: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut patterns: Vec<Zeroizing<Vec<u8>>> = values
+            .into_iter()
+            .filter(|value| value.len() >= MIN_MASKED_LEN)
+            .map(|value| Zeroizing::new(value.to_vec()))
+            .collect();
+        patterns.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        patterns.dedup();
+        Patterns(Arc::new(patterns))
+    }
+
+    /// Routes the child's stdout and stderr through this process; call before spawning
+    pub fn capture(cmd: &mut Command) {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    /// Starts forwarding the child's captured output with the secrets replaced
+    pub fn relay(&self, child: &mut Child) -> Relays {
+        let mut threads = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            let patterns = self.clone();
+            threads.push(std::thread::spawn(move || {
+                patterns.filter(stdout, std::io::stdout())
+            }));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let patterns = self.clone();
+            threads.push(std::thread::spawn(move || {
+                patterns.filter(stderr, std::io::stderr())
+            }));
+        }
+        Relays(threads)
+    }
+
+    /// Copies `input` to `output`, replacing every occurrence of a pattern with [`MASK`].
+    ///
+    /// Bytes that could still be the start of a secret are held back until the next
+    /// read decides; everything else is forwarded as soon as it arrives. When `output`
+    /// fails, `input` is dropped, so the child's next write fails with EPIPE as it
+    /// would have without the relay.
+    pub fn filter(&self, mut input: impl Read, mut output: impl Write) {
+        let mut pending = Zeroizing::new(Vec::new());
+        let mut chunk = Zeroizing::new(vec![0u8; 8 * 1024]);
+        loop {
+            let read = match input.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            pending.extend_from_slice(&chunk[..read]);
+            let (masked, consumed) = self.scan(&pending, false);
+            pending.drain(..consumed);
+            if output
+                .write_all(&masked)
+                .and_then(|()| output.flush())
+                .is_err()
+            {
+                return;
+            }
+        }
+        let (masked, _) = self.scan(&pending, true);
+        let _ = output.write_all(&masked).and_then(|()| output.flush());
+    }
+
+    /// Masks `buffer`, returning the output and how many input bytes it covers.
+    ///
+    /// Unless `at_end`, a tail that is a proper prefix of a pattern is left unconsumed.
+    fn scan(&self, buffer: &[u8], at_end: bool) -> (Zeroizing<Vec<u8>>, usize) {
+        let mut out = Zeroizing::new(Vec::with_capacity(buffer.len()));
+        let mut index = 0;
+        'bytes: while index < buffer.len() {
+            let rest = &buffer[index..];
+            for pattern in self.0.iter() {
+                if rest.starts_with(pattern) {
+                    out.extend_from_slice(MASK);
+                    index += pattern.len();
+                    continue 'bytes;
+                }
+            }
+            if !at_end && self.0.iter().any(|pattern| pattern.starts_with(rest)) {
+                break;
+            }
+            out.push(buffer[index]);
+            index += 1;
+        }
+        (out, index)
+    }
+}
+
+/// Threads forwarding one child's output
+pub struct Relays(Vec<JoinHandle<()>>);
+
+impl Relays {
+    /// Waits until everything the child wrote has been forwarded
+    pub fn finish(self) {
+        for thread in self.0 {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Delivers the input a few bytes at a time, like a child writing unbuffered
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.step.min(self.data.len()).min(buf.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn masked(patterns: &[&str], input: &[u8], step: usize) -> Vec<u8> {
+        let patterns = Patterns::new(patterns.iter().map(|p| p.as_bytes()));
+        let mut output = Vec::new();
+        patterns.filter(Trickle { data: input, step }, &mut output);
+        output
+    }
+
+    #[test]
+    fn masks_secrets_split_across_reads() {
+        let input = b"token=hunter22 and again hunter22\n";
+        for step in 1..input.len() {
+            assert_eq!(
+                masked(&["hunter22"], input, step),
+                b"token=***MASKED*** and again ***MASKED***\n",
+                "step {}",
+                step
+            );
+        }
+    }
+
+    #[test]
+    fn masks_several_secrets_and_prefers_the_longest() {
+        assert_eq!(
+            masked(&["abcd", "abcdef", "wxyz"], b"abcdefg abcd wxyz", 3),
+            b"***MASKED***g ***MASKED*** ***MASKED***"
+        );
+    }
+
+    #[test]
+    fn passes_other_bytes_through_unchanged() {
+        let input: Vec<u8> = (0..=255u8).chain([0xff, b'h', b'u', b'n', 0x00]).collect();
+        assert_eq!(masked(&["hunter22"], &input, 7), input);
+        // A held-back prefix is released once the input ends
+        assert_eq!(
+            masked(&["hunter22"], b"ends with hunt", 4),
+            b"ends with hunt"
+        );
+    }
+
+    #[test]
+    fn short_values_are_not_masked() {
+        assert_eq!(masked(&["abc", ""], b"abc abc", 2), b"abc abc");
+    }
+}
@@ -0,0 +1,326 @@
+//! Opt-in OpenTelemetry spans for validation, secret resolution and the child process.
+//!
+//! Built only with the `otel` feature; without it every function here is an empty
+//! inline stub. Spans carry variable names, backend names and exit codes, never values.
+
+#[cfg(feature = "otel")]
+pub use exporter::{flush, init, span};
+
+#[cfg(not(feature = "otel"))]
+pub use disabled::{flush, span};
+
+#[cfg(not(feature = "otel"))]
+mod disabled {
+    /// Inert span; tracing is compiled out
+    pub struct Span;
+
+    impl Span {
+        #[inline(always)]
+        pub fn attr_str(&mut self, _key: &'static str, _value: &str) {}
+
+        #[inline(always)]
+        pub fn attr_int(&mut self, _key: &'static str, _value: i64) {}
+
+        #[inline(always)]
+        pub fn end(self) {}
+    }
+
+    #[inline(always)]
+    pub fn span(_name: &'static str) -> Span {
+        Span
+    }
+
+    #[inline(always)]
+    pub fn flush() {}
+}
+
+#[cfg(feature = "otel")]
+mod exporter {
+    use anyhow::{Context, Result};
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::sync::{Mutex, PoisonError};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::json;
+
+    /// Standard variable consulted when `--otel-endpoint` is not given
+    const ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+    /// Export must never hold up the command noticeably
+    const EXPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// `None` until `init` finds an endpoint; spans are then dropped unrecorded
+    static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+    struct State {
+        endpoint: Endpoint,
+        trace_id: String,
+        spans: Vec<Finished>,
+    }
+
+    /// Plain-HTTP OTLP collector address
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Endpoint {
+        pub(super) host: String,
+        pub(super) port: u16,
+        pub(super) path: String,
+    }
+
+    enum Value {
+        Str(String),
+        Int(i64),
+    }
+
+    struct Finished {
+        id: String,
+        name: &'static str,
+        start: u128,
+        end: u128,
+        attributes: Vec<(&'static str, Value)>,
+    }
+
+    struct Open {
+        name: &'static str,
+        start: u128,
+        attributes: Vec<(&'static str, Value)>,
+    }
+
+    /// A span that is recorded when dropped
+    pub struct Span {
+        open: Option<Open>,
+    }
+
+    impl Span {
+        pub fn attr_str(&mut self, key: &'static str, value: &str) {
+            if let Some(open) = &mut self.open {
+                open.attributes.push((key, Value::Str(value.to_string())));
+            }
+        }
+
+        pub fn attr_int(&mut self, key: &'static str, value: i64) {
+            if let Some(open) = &mut self.open {
+                open.attributes.push((key, Value::Int(value)));
+            }
+        }
+
+        /// Records the span now rather than at the end of the scope
+        pub fn end(self) {}
+    }
+
+    impl Drop for Span {
+        fn drop(&mut self) {
+            if let Some(open) = self.open.take() {
+                let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(state) = state.as_mut() {
+                    state.spans.push(Finished {
+                        id: random_hex(1),
+                        name: open.name,
+                        start: open.start,
+                        end: now_nanos(),
+                        attributes: open.attributes,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Enables export to `endpoint`, or to `$OTEL_EXPORTER_OTLP_ENDPOINT` when not given
+    pub fn init(endpoint: Option<&str>) -> Result<()> {
+        let configured = match endpoint {
+            Some(endpoint) => endpoint.to_string(),
+            None => match std::env::var(ENDPOINT_VAR) {
+                Ok(endpoint) if !endpoint.trim().is_empty() => endpoint,
+                _ => return Ok(()),
+            },
+        };
+        let endpoint = parse_endpoint(&configured)?;
+        *STATE.lock().unwrap_or_else(PoisonError::into_inner) = Some(State {
+            endpoint,
+            trace_id: random_hex(2),
+            spans: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Starts a span; a no-op unless `init` enabled export
+    pub fn span(name: &'static str) -> Span {
+        let enabled = STATE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some();
+        Span {
+            open: enabled.then(|| Open {
+                name,
+                start: now_nanos(),
+                attributes: Vec::new(),
+            }),
+        }
+    }
+
+    /// Sends the recorded spans; failures only warn, tracing never fails the command
+    pub fn flush() {
+        let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        if state.spans.is_empty() {
+            return;
+        }
+        let body = render(&state.trace_id, &state.spans);
+        state.spans.clear();
+        if let Err(err) = post(&state.endpoint, &body) {
+            eprintln!("Warning: failed to export trace spans: {:#}", err);
+        }
+    }
+
+    /// Accepts `http://host[:port][/base]`; `/v1/traces` is appended as OTLP/HTTP specifies
+    pub(super) fn parse_endpoint(url: &str) -> Result<Endpoint> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unsupported OTLP endpoint {}: only http:// collectors are supported",
+                url
+            )
+        })?;
+        let (authority, base) = match rest.find('/') {
+            Some(index) => (&rest[..index], rest[index..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .with_context(|| format!("Invalid port in OTLP endpoint {}", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() || host.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(anyhow::anyhow!("Invalid host in OTLP endpoint {}", url));
+        }
+        Ok(Endpoint {
+            host: host.to_string(),
+            port,
+            path: format!("{}/v1/traces", base),
+        })
+    }
+
+    /// OTLP/HTTP JSON encoding of one batch of spans
+    fn render(trace_id: &str, spans: &[Finished]) -> String {
+        let mut out = String::from(
+            "{\"resourceSpans\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\",\"value\":{\"stringValue\":\"local-secrets\"}}]},\"scopeSpans\":[{\"scope\":{\"name\":\"local-secrets\",\"version\":",
+        );
+        json::push_string(&mut out, env!("CARGO_PKG_VERSION"));
+        out.push_str("},\"spans\":[");
+        for (index, span) in spans.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "{{\"traceId\":\"{}\",\"spanId\":\"{}\",\"name\":",
+                trace_id, span.id
+            ));
+            json::push_string(&mut out, span.name);
+            out.push_str(&format!(
+                ",\"kind\":1,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[",
+                span.start, span.end
+            ));
+            for (index, (key, value)) in span.attributes.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"key\":");
+                json::push_string(&mut out, key);
+                match value {
+                    Value::Str(value) => {
+                        out.push_str(",\"value\":{\"stringValue\":");
+                        json::push_string(&mut out, value);
+                        out.push_str("}}");
+                    }
+                    // OTLP JSON carries 64-bit integers as strings
+                    Value::Int(value) => {
+                        out.push_str(&format!(",\"value\":{{\"intValue\":\"{}\"}}}}", value))
+                    }
+                }
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}]}]}");
+        out
+    }
+
+    fn post(endpoint: &Endpoint, body: &str) -> Result<()> {
+        let address = (endpoint.host.as_str(), endpoint.port)
+            .to_socket_addrs()
+            .context("Failed to resolve the OTLP endpoint")?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("OTLP endpoint resolved to no address"))?;
+        let mut stream = TcpStream::connect_timeout(&address, EXPORT_TIMEOUT)
+            .context("Failed to connect to the OTLP endpoint")?;
+        stream.set_read_timeout(Some(EXPORT_TIMEOUT))?;
+        stream.set_write_timeout(Some(EXPORT_TIMEOUT))?;
+
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            endpoint.path,
+            endpoint.host,
+            endpoint.port,
+            body.len(),
+            body
+        );
+        stream
+            .write_all(request.as_bytes())
+            .context("Failed to send spans")?;
+
+        let mut status = [0u8; 12];
+        stream
+            .read_exact(&mut status)
+            .context("No response from the OTLP endpoint")?;
+        // "HTTP/1.1 200"
+        if status[9] != b'2' {
+            return Err(anyhow::anyhow!(
+                "OTLP endpoint answered {}",
+                String::from_utf8_lossy(&status[9..])
+            ));
+        }
+        Ok(())
+    }
+
+    fn now_nanos() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos())
+    }
+
+    /// `words` random 64-bit words as lowercase hex (span ids: 1, trace ids: 2)
+    fn random_hex(words: usize) -> String {
+        (0..words)
+            .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::exporter::{parse_endpoint, Endpoint};
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            parse_endpoint("http://localhost:4318").unwrap(),
+            Endpoint {
+                host: "localhost".to_string(),
+                port: 4318,
+                path: "/v1/traces".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_endpoint("http://collector/otlp/").unwrap().path,
+            "/otlp/v1/traces"
+        );
+        assert!(parse_endpoint("https://collector:4318").is_err());
+        assert!(parse_endpoint("http://:4318").is_err());
+        assert!(parse_endpoint("http://host:port").is_err());
+    }
+}
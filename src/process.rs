@@ -0,0 +1,1258 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Interpreter command line for script types Windows cannot execute directly.
+///
+/// `.bat`/`.cmd` are deliberately absent: std already launches them through
+/// `cmd.exe` with escaping that guards against argument injection, and wrapping
+/// them in our own `cmd /C` would bypass it.
+fn script_interpreter(program: &str) -> Option<&'static [&'static str]> {
+    let extension = std::path::Path::new(program).extension()?.to_str()?;
+    if extension.eq_ignore_ascii_case("ps1") {
+        // -File passes the remaining arguments as literal strings, no interpolation
+        return Some(&["powershell", "-NoProfile", "-NonInteractive", "-File"]);
+    }
+    None
+}
+
+/// Command line running `script` through a shell for `run --shell`: `$SHELL -c` (else
+/// `/bin/sh`) on Unix, `powershell -Command` on Windows, whose argument quoting
+/// round-trips where cmd.exe's does not
+pub fn shell_command(script: &str) -> Vec<String> {
+    if cfg!(windows) {
+        return ["powershell", "-NoProfile", "-Command", script]
+            .map(String::from)
+            .to_vec();
+    }
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    vec![shell, "-c".to_string(), script.to_string()]
+}
+
+/// Builds the child command, routing Windows scripts through their interpreter
+/// when `windows_script_auto` is enabled
+pub fn build_command(command_args: &[String], windows_script_auto: bool) -> Command {
+    let program = &command_args[0];
+    let interpreter = script_interpreter(program).filter(|_| windows_script_auto && cfg!(windows));
+
+    match interpreter {
+        Some(interpreter) => {
+            let mut cmd = Command::new(interpreter[0]);
+            cmd.args(&interpreter[1..])
+                .arg(program)
+                .args(&command_args[1..]);
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new(program);
+            cmd.args(&command_args[1..]);
+            cmd
+        }
+    }
+}
+
+/// Validates a `--user`/`--group` value before it is resolved against the system databases
+#[cfg(unix)]
+fn validate_principal(kind: &str, value: &str) -> Result<()> {
+    if value.trim().is_empty() {
+        return Err(anyhow::anyhow!("{} name cannot be empty", kind));
+    }
+    if value.len() > 256 {
+        return Err(anyhow::anyhow!(
+            "{} name too long (max 256 characters)",
+            kind
+        ));
+    }
+    if value.contains('\0') || value.chars().any(|c| c.is_control()) {
+        return Err(anyhow::anyhow!("{} name contains control characters", kind));
+    }
+    Ok(())
+}
+
+/// Resolved identity the child process should run as
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct Privileges {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub groups: Vec<libc::gid_t>,
+}
+
+#[cfg(unix)]
+impl Privileges {
+    /// Whether the current process already runs as this identity, so that
+    /// switching to it would be a no-op (and, without root, would fail)
+    pub fn is_current(&self) -> bool {
+        // SAFETY: getuid/getgid have no preconditions and cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let Ok(current) = unix::current_groups(gid) else {
+            return false;
+        };
+        let mut groups = self.groups.clone();
+        groups.sort_unstable();
+        groups.dedup();
+        self.uid == uid && self.gid == gid && groups == current
+    }
+}
+
+/// Resolves `--user`/`--group` into numeric ids and supplementary groups.
+///
+/// Returns `None` when neither flag was given. Fails when the names cannot be
+/// resolved or when the current process lacks the privileges to switch to them.
+#[cfg(unix)]
+pub fn resolve_privileges(user: Option<&str>, group: Option<&str>) -> Result<Option<Privileges>> {
+    if user.is_none() && group.is_none() {
+        return Ok(None);
+    }
+
+    let account = match user {
+        Some(user) => {
+            validate_principal("User", user)?;
+            Some(unix::lookup_user(user)?)
+        }
+        None => None,
+    };
+    let explicit_gid = match group {
+        Some(group) => {
+            validate_principal("Group", group)?;
+            Some(unix::lookup_group(group)?)
+        }
+        None => None,
+    };
+
+    // SAFETY: getuid/getgid/geteuid have no preconditions and cannot fail
+    let (current_uid, current_gid, euid) =
+        unsafe { (libc::getuid(), libc::getgid(), libc::geteuid()) };
+
+    let (uid, gid, mut groups) = match account {
+        Some(account) => {
+            let gid = explicit_gid.unwrap_or(account.gid);
+            let groups = unix::supplementary_groups(&account.name, gid)?;
+            (account.uid, gid, groups)
+        }
+        None => {
+            let gid = explicit_gid.unwrap_or(current_gid);
+            (current_uid, gid, vec![gid])
+        }
+    };
+
+    // Without root the supplementary groups cannot change either; naming our own
+    // identity keeps the ones we have, and the child is spawned without switching
+    if euid != 0 && uid == current_uid && gid == current_gid {
+        groups = unix::current_groups(gid)?;
+    }
+
+    if euid != 0 && (uid != current_uid || gid != current_gid) {
+        return Err(anyhow::anyhow!(
+            "--user/--group require root privileges to switch identity (running as uid {})",
+            euid
+        ));
+    }
+
+    Ok(Some(Privileges { uid, gid, groups }))
+}
+
+/// Arranges for the child to drop to `privileges` right before exec.
+///
+/// `CommandExt::uid`/`gid` are not used: std applies them before running
+/// `pre_exec` hooks, after which `setgroups` is no longer permitted. Doing all
+/// three calls here keeps the required order (groups, gid, then uid).
+#[cfg(unix)]
+pub fn apply_privileges(cmd: &mut Command, privileges: &Privileges) {
+    use std::os::unix::process::CommandExt;
+
+    let uid = privileges.uid;
+    let gid = privileges.gid;
+    let groups = privileges.groups.clone();
+
+    // SAFETY: the closure only performs async-signal-safe syscalls on data
+    // that was fully prepared before fork
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::setgid(gid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if libc::setuid(uid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Parses an octal umask such as `077`, `0077` or `0o077`
+pub fn parse_umask(value: &str) -> Result<u32> {
+    let trimmed = value.trim();
+    let digits = trimmed.strip_prefix("0o").unwrap_or(trimmed);
+
+    if digits.is_empty() || digits.len() > 4 || !digits.chars().all(|c| ('0'..='7').contains(&c)) {
+        return Err(anyhow::anyhow!(
+            "Invalid umask '{}' (expected octal digits, e.g. 077)",
+            value
+        ));
+    }
+
+    let mask =
+        u32::from_str_radix(digits, 8).map_err(|_| anyhow::anyhow!("Invalid umask '{}'", value))?;
+    if mask > 0o777 {
+        return Err(anyhow::anyhow!("Invalid umask '{}' (max 0777)", value));
+    }
+    Ok(mask)
+}
+
+/// Applies `--umask` to the child, or warns about a permissive inherited umask.
+///
+/// The warning only makes sense while secrets are being injected, so callers
+/// pass `warn = false` otherwise.
+#[cfg(unix)]
+pub fn configure_umask(cmd: &mut Command, umask: Option<u32>, warn: bool) -> Result<()> {
+    match umask {
+        Some(mask) => apply_umask(cmd, mask),
+        None => {
+            let inherited = current_umask();
+            if warn && inherited & 0o022 != 0o022 {
+                eprintln!(
+                    "Warning: inherited umask {:04o} is more permissive than 0022; files the child writes may expose injected secrets (consider --umask 077)",
+                    inherited
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn configure_umask(_cmd: &mut Command, umask: Option<u32>, _warn: bool) -> Result<()> {
+    if umask.is_some() {
+        return Err(anyhow::anyhow!(
+            "--umask is only supported on Unix platforms"
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the umask inherited by this process
+#[cfg(unix)]
+fn current_umask() -> u32 {
+    // SAFETY: umask cannot fail; the original value is restored immediately and
+    // no other threads create files at this point
+    unsafe {
+        let mask = libc::umask(0o077);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+/// Arranges for the child to start with the given umask
+#[cfg(unix)]
+fn apply_umask(cmd: &mut Command, mask: u32) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: umask is async-signal-safe and cannot fail
+    unsafe {
+        cmd.pre_exec(move || {
+            libc::umask(mask as libc::mode_t);
+            Ok(())
+        });
+    }
+}
+
+/// Resource limits applied to the child (`--limit-mem`, `--limit-cpu`, `--limit-nofile`)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Limits {
+    /// Address space in bytes
+    pub memory: Option<u64>,
+    /// CPU time in seconds
+    pub cpu: Option<u64>,
+    /// Open file descriptors
+    pub nofile: Option<u64>,
+}
+
+impl Limits {
+    fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpu.is_none() && self.nofile.is_none()
+    }
+}
+
+/// Parses a byte size such as `2G`, `512M`, `64KiB` or `1048576` (binary multiples)
+pub fn parse_size(value: &str) -> Result<u64> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid size '{}' (expected a number with an optional K, M, G or T suffix, e.g. 2G)",
+            value
+        )
+    };
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (digits, unit) = trimmed.split_at(split);
+    let number: u64 = digits.parse().map_err(|_| invalid())?;
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid()),
+    };
+    let bytes = number
+        .checked_shl(shift)
+        .filter(|bytes| bytes >> shift == number);
+    match bytes {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses CPU time such as `300`, `300s`, `5m` or `1h` into seconds
+pub fn parse_cpu_time(value: &str) -> Result<u64> {
+    parse_seconds(value, "CPU time")
+}
+
+/// Parses a duration such as `30s`, `5m` or `2h` for `--timeout` and `--kill-after`
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    parse_seconds(value, "duration").map(Duration::from_secs)
+}
+
+/// Positive whole seconds with an optional `s`, `m` or `h` suffix
+fn parse_seconds(value: &str, what: &str) -> Result<u64> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid {} '{}' (expected seconds with an optional s, m or h suffix, e.g. 300s)",
+            what,
+            value
+        )
+    };
+    let trimmed = value.trim();
+    let (digits, multiplier) = match trimmed.char_indices().last() {
+        Some((index, 's')) => (&trimmed[..index], 1),
+        Some((index, 'm')) => (&trimmed[..index], 60),
+        Some((index, 'h')) => (&trimmed[..index], 3600),
+        _ => (trimmed, 1),
+    };
+    let number: u64 = digits.parse().map_err(|_| invalid())?;
+    match number.checked_mul(multiplier) {
+        Some(seconds) if seconds > 0 => Ok(seconds),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a descriptor count for `--limit-nofile`
+pub fn parse_nofile(value: &str) -> Result<u64> {
+    match value.trim().parse::<u64>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(anyhow::anyhow!(
+            "Invalid descriptor limit '{}' (expected a positive number, e.g. 1024)",
+            value
+        )),
+    }
+}
+
+/// Arranges for the child to start with `limits` via setrlimit.
+///
+/// Limits can only be lowered, so each one is checked against the current
+/// hard limit here rather than failing obscurely inside the child.
+#[cfg(unix)]
+pub fn configure_limits(cmd: &mut Command, limits: &Limits) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    if limits.is_empty() {
+        return Ok(());
+    }
+    let mut planned = Vec::new();
+    for (resource, requested, flag) in [
+        (libc::RLIMIT_AS, limits.memory, "--limit-mem"),
+        (libc::RLIMIT_CPU, limits.cpu, "--limit-cpu"),
+        (libc::RLIMIT_NOFILE, limits.nofile, "--limit-nofile"),
+    ] {
+        let Some(requested) = requested else {
+            continue;
+        };
+        let hard = hard_limit(resource)?;
+        let requested = requested as libc::rlim_t;
+        if hard != libc::RLIM_INFINITY && requested > hard {
+            return Err(anyhow::anyhow!(
+                "{} {} exceeds the current hard limit of {}",
+                flag,
+                requested,
+                hard
+            ));
+        }
+        // A CPU soft limit sends SIGXCPU; the hard limit one second later kills
+        let hard = if resource == libc::RLIMIT_CPU {
+            requested.saturating_add(1).min(hard)
+        } else {
+            requested
+        };
+        planned.push((
+            resource,
+            libc::rlimit {
+                rlim_cur: requested,
+                rlim_max: hard,
+            },
+        ));
+    }
+
+    // SAFETY: setrlimit is async-signal-safe; `planned` is moved into the hook
+    // and only read there
+    unsafe {
+        cmd.pre_exec(move || {
+            for (resource, limit) in &planned {
+                if libc::setrlimit(*resource, limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn configure_limits(_cmd: &mut Command, limits: &Limits) -> Result<()> {
+    if !limits.is_empty() {
+        return Err(anyhow::anyhow!(
+            "--limit-mem, --limit-cpu and --limit-nofile are only supported on Unix platforms"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn hard_limit(resource: RlimitResource) -> Result<libc::rlim_t> {
+    let mut current = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: current is a valid rlimit for getrlimit to fill in
+    if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to read resource limits");
+    }
+    Ok(current.rlim_max)
+}
+
+/// glibc types the resource argument differently from other libcs
+#[cfg(all(unix, target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+/// Explains a child death that matches one of its `limits`, for stderr and `--stats`
+#[cfg(unix)]
+pub fn limit_exceeded(status: &ExitStatus, limits: &Limits) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    if let Some(cpu) = limits.cpu {
+        if signal == libc::SIGXCPU || signal == libc::SIGKILL {
+            return Some(format!(
+                "Child exceeded its CPU time limit (--limit-cpu {}s)",
+                cpu
+            ));
+        }
+    }
+    // An exhausted address space shows up as a failed allocation, which most
+    // runtimes turn into an abort
+    if let Some(memory) = limits.memory {
+        if matches!(
+            signal,
+            libc::SIGABRT | libc::SIGSEGV | libc::SIGBUS | libc::SIGKILL
+        ) {
+            return Some(format!(
+                "Child was killed by signal {} and may have exceeded its memory limit (--limit-mem {} bytes)",
+                signal, memory
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(not(unix))]
+pub fn limit_exceeded(_status: &ExitStatus, _limits: &Limits) -> Option<String> {
+    None
+}
+
+/// Shell-style `128 + N` exit code and a note for a child killed by signal `N`
+#[cfg(unix)]
+pub fn signal_exit(status: &ExitStatus) -> Option<(i32, String)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    let note = match signal_name(signal) {
+        Some(name) => format!("child terminated by signal {} ({})", signal, name),
+        None => format!("child terminated by signal {}", signal),
+    };
+    Some((128 + signal, note))
+}
+
+/// Windows has no signals; the raw exit code is reported as is
+#[cfg(not(unix))]
+pub fn signal_exit(_status: &ExitStatus) -> Option<(i32, String)> {
+    None
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return None,
+    })
+}
+
+/// Marker printed by the login shell right before its environment dump, so that
+/// anything profile scripts write to stdout is ignored
+#[cfg(unix)]
+const LOGIN_ENV_MARKER: &[u8] = b"\0__LOCAL_SECRETS_LOGIN_ENV__\0";
+
+/// Variables describing the login shell itself rather than the user's environment
+#[cfg(unix)]
+const LOGIN_ENV_SKIPPED: &[&str] = &["_", "SHLVL", "PWD", "OLDPWD"];
+
+/// Captures the environment of the user's login shell (`$SHELL -lc 'env -0'`).
+///
+/// Used to recover the PATH and friends when local-secrets is launched from an
+/// IDE task or launchd agent with a minimal environment.
+#[cfg(unix)]
+pub fn login_environment() -> Result<Vec<(std::ffi::OsString, std::ffi::OsString)>> {
+    use anyhow::Context;
+    use std::process::Stdio;
+
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|shell| shell.starts_with('/'))
+        .unwrap_or_else(|| "/bin/sh".to_string());
+
+    let output = Command::new(&shell)
+        .arg("-lc")
+        .arg("printf '\\000__LOCAL_SECRETS_LOGIN_ENV__\\000'; exec env -0")
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to start login shell {}", shell))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Login shell {} failed to report its environment: {}",
+            shell,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_login_environment(&output.stdout)
+        .with_context(|| format!("Failed to parse environment from login shell {}", shell))
+}
+
+#[cfg(not(unix))]
+pub fn login_environment() -> Result<Vec<(std::ffi::OsString, std::ffi::OsString)>> {
+    Err(anyhow::anyhow!(
+        "--login-env is only supported on Unix platforms"
+    ))
+}
+
+/// Parses the NUL-separated `KEY=VALUE` records that follow the marker
+#[cfg(unix)]
+fn parse_login_environment(output: &[u8]) -> Result<Vec<(std::ffi::OsString, std::ffi::OsString)>> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let start = output
+        .windows(LOGIN_ENV_MARKER.len())
+        .position(|window| window == LOGIN_ENV_MARKER)
+        .ok_or_else(|| anyhow::anyhow!("environment marker not found in shell output"))?;
+
+    let mut vars = Vec::new();
+    for record in output[start + LOGIN_ENV_MARKER.len()..].split(|&b| b == 0) {
+        let Some(eq) = record.iter().position(|&b| b == b'=') else {
+            continue;
+        };
+        let (key, value) = (&record[..eq], &record[eq + 1..]);
+        if key.is_empty() || LOGIN_ENV_SKIPPED.iter().any(|skip| skip.as_bytes() == key) {
+            continue;
+        }
+        vars.push((
+            OsString::from_vec(key.to_vec()),
+            OsString::from_vec(value.to_vec()),
+        ));
+    }
+    Ok(vars)
+}
+
+#[cfg(unix)]
+mod unix {
+    use crate::security::display_name;
+    use anyhow::Result;
+    use std::ffi::{CStr, CString};
+
+    pub struct Account {
+        pub name: CString,
+        pub uid: libc::uid_t,
+        pub gid: libc::gid_t,
+    }
+
+    fn buffer_size() -> usize {
+        // SAFETY: sysconf has no preconditions
+        let size = unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) };
+        if size > 0 {
+            size as usize
+        } else {
+            16 * 1024
+        }
+    }
+
+    /// Looks up a user by name, falling back to a numeric uid
+    pub fn lookup_user(user: &str) -> Result<Account> {
+        let c_name =
+            CString::new(user).map_err(|_| anyhow::anyhow!("User name contains null byte"))?;
+        let mut buf = vec![0 as libc::c_char; buffer_size()];
+
+        loop {
+            // SAFETY: passwd is plain old data and fully written by getpwnam_r on success
+            let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result: *mut libc::passwd = std::ptr::null_mut();
+            // SAFETY: all pointers reference live buffers of the advertised sizes
+            let rc = unsafe {
+                libc::getpwnam_r(
+                    c_name.as_ptr(),
+                    &mut pwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+
+            if rc == libc::ERANGE && buf.len() < 1024 * 1024 {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if rc != 0 {
+                return Err(anyhow::anyhow!(
+                    "Failed to look up user '{}': {}",
+                    user,
+                    std::io::Error::from_raw_os_error(rc)
+                ));
+            }
+            if result.is_null() {
+                return lookup_user_by_id(user);
+            }
+
+            // SAFETY: on success pw_name points into `buf` and is NUL-terminated
+            let name = unsafe { CStr::from_ptr(pwd.pw_name) }.to_owned();
+            return Ok(Account {
+                name,
+                uid: pwd.pw_uid,
+                gid: pwd.pw_gid,
+            });
+        }
+    }
+
+    fn lookup_user_by_id(user: &str) -> Result<Account> {
+        let uid: libc::uid_t = user
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Unknown user '{}'", display_name(user)))?;
+        let mut buf = vec![0 as libc::c_char; buffer_size()];
+
+        loop {
+            // SAFETY: see lookup_user
+            let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result: *mut libc::passwd = std::ptr::null_mut();
+            // SAFETY: all pointers reference live buffers of the advertised sizes
+            let rc = unsafe {
+                libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result)
+            };
+
+            if rc == libc::ERANGE && buf.len() < 1024 * 1024 {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if rc != 0 || result.is_null() {
+                return Err(anyhow::anyhow!("Unknown user '{}'", display_name(user)));
+            }
+
+            // SAFETY: on success pw_name points into `buf` and is NUL-terminated
+            let name = unsafe { CStr::from_ptr(pwd.pw_name) }.to_owned();
+            return Ok(Account {
+                name,
+                uid: pwd.pw_uid,
+                gid: pwd.pw_gid,
+            });
+        }
+    }
+
+    /// Looks up a group by name, falling back to a numeric gid
+    pub fn lookup_group(group: &str) -> Result<libc::gid_t> {
+        let c_name =
+            CString::new(group).map_err(|_| anyhow::anyhow!("Group name contains null byte"))?;
+        let mut buf = vec![0 as libc::c_char; buffer_size()];
+
+        loop {
+            // SAFETY: group is plain old data and fully written by getgrnam_r on success
+            let mut grp: libc::group = unsafe { std::mem::zeroed() };
+            let mut result: *mut libc::group = std::ptr::null_mut();
+            // SAFETY: all pointers reference live buffers of the advertised sizes
+            let rc = unsafe {
+                libc::getgrnam_r(
+                    c_name.as_ptr(),
+                    &mut grp,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            };
+
+            if rc == libc::ERANGE && buf.len() < 1024 * 1024 {
+                buf.resize(buf.len() * 2, 0);
+                continue;
+            }
+            if rc != 0 {
+                return Err(anyhow::anyhow!(
+                    "Failed to look up group '{}': {}",
+                    group,
+                    std::io::Error::from_raw_os_error(rc)
+                ));
+            }
+            if result.is_null() {
+                return group
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Unknown group '{}'", display_name(group)));
+            }
+
+            return Ok(grp.gr_gid);
+        }
+    }
+
+    /// The current process's supplementary groups plus `gid`, sorted and deduplicated
+    pub fn current_groups(gid: libc::gid_t) -> Result<Vec<libc::gid_t>> {
+        // SAFETY: a zero size only asks for the number of groups
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut groups = vec![0 as libc::gid_t; count as usize];
+        // SAFETY: `groups` holds `count` entries as advertised to getgroups
+        let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        groups.truncate(count as usize);
+        groups.push(gid);
+        groups.sort_unstable();
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Collects the supplementary groups of `name`, always including `gid`
+    pub fn supplementary_groups(name: &CStr, gid: libc::gid_t) -> Result<Vec<libc::gid_t>> {
+        let mut count: libc::c_int = 64;
+
+        loop {
+            let mut groups = vec![0 as libc::gid_t; count as usize];
+            // SAFETY: `groups` holds `count` entries as advertised to getgrouplist
+            #[cfg(target_vendor = "apple")]
+            let rc = unsafe {
+                libc::getgrouplist(
+                    name.as_ptr(),
+                    gid as libc::c_int,
+                    groups.as_mut_ptr() as *mut libc::c_int,
+                    &mut count,
+                )
+            };
+            // SAFETY: `groups` holds `count` entries as advertised to getgrouplist
+            #[cfg(not(target_vendor = "apple"))]
+            let rc =
+                unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+
+            if rc >= 0 {
+                groups.truncate(count.max(0) as usize);
+                if !groups.contains(&gid) {
+                    groups.insert(0, gid);
+                }
+                return Ok(groups);
+            }
+            if count as usize <= groups.len() {
+                // Some platforms don't report the required size; grow manually
+                count = (groups.len() * 2) as libc::c_int;
+            }
+            if count > 65_536 {
+                return Err(anyhow::anyhow!("Too many supplementary groups for user"));
+            }
+        }
+    }
+}
+
+/// Splits a `--probe` command line into argv using POSIX shell quoting rules
+pub fn parse_probe(probe: &str) -> Result<Vec<String>> {
+    parse_command_line(probe, "--probe")
+}
+
+/// Splits the command line given to `flag` into argv using POSIX shell quoting rules
+pub fn parse_command_line(line: &str, flag: &str) -> Result<Vec<String>> {
+    let args = shell_words::split(line).with_context(|| format!("Invalid {} command", flag))?;
+    if args.is_empty() {
+        return Err(anyhow::anyhow!("{} command cannot be empty", flag));
+    }
+    Ok(args)
+}
+
+/// Replaces every `{{NAME}}` in `arg` with `value_of(NAME)`; `\{\{` stands for a literal `{{`.
+///
+/// Fails on a placeholder without its closing `}}` or when `value_of` fails. Errors
+/// name `place` and a position, never the text itself, which may hold secrets.
+pub fn expand_placeholders<'v>(
+    arg: &str,
+    place: &str,
+    mut value_of: impl FnMut(&str) -> Result<&'v str>,
+) -> Result<Zeroizing<String>> {
+    let mut expanded = Zeroizing::new(String::with_capacity(arg.len()));
+    let mut rest = arg;
+    loop {
+        let escape = rest.find("\\{\\{");
+        let open = rest.find("{{");
+        match (escape, open) {
+            (Some(at), open) if !matches!(open, Some(open) if open < at) => {
+                expanded.push_str(&rest[..at]);
+                expanded.push_str("{{");
+                rest = &rest[at + 4..];
+            }
+            (_, Some(at)) => {
+                expanded.push_str(&rest[..at]);
+                let inner = &rest[at + 2..];
+                let end = inner.find("}}").ok_or_else(|| {
+                    let (line, column) = position(arg, arg.len() - rest.len() + at);
+                    anyhow::anyhow!(
+                        "Unterminated {{{{ placeholder in {} at line {}, column {}",
+                        place,
+                        line,
+                        column
+                    )
+                })?;
+                expanded.push_str(value_of(&inner[..end])?);
+                rest = &inner[end + 2..];
+            }
+            _ => {
+                expanded.push_str(rest);
+                return Ok(expanded);
+            }
+        }
+    }
+}
+
+/// 1-based line and column (in characters) of byte `offset` in `text`
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |at| at + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Checks a `--cwd` directory, resolving a relative path against our own working directory
+pub fn working_dir(dir: &Path) -> Result<PathBuf> {
+    let absolute = if dir.is_absolute() {
+        dir.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .context("Failed to read the current directory")?
+            .join(dir)
+    };
+    match std::fs::metadata(&absolute) {
+        Ok(metadata) if metadata.is_dir() => Ok(absolute),
+        Ok(_) => Err(anyhow::anyhow!(
+            "--cwd {} is not a directory",
+            absolute.display()
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+            "--cwd {} does not exist",
+            absolute.display()
+        )),
+        Err(err) => Err(err).with_context(|| format!("Cannot access --cwd {}", absolute.display())),
+    }
+}
+
+/// A child that can be polled, killed and reaped; run mode wraps [`Child`] so that
+/// its process tree is stopped before the leader is reaped
+pub trait Waitable {
+    fn id(&self) -> u32;
+    fn kill(&mut self) -> std::io::Result<()>;
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>>;
+    fn wait(&mut self) -> std::io::Result<ExitStatus>;
+}
+
+impl Waitable for Child {
+    fn id(&self) -> u32 {
+        Child::id(self)
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        Child::kill(self)
+    }
+
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        Child::try_wait(self)
+    }
+
+    fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        Child::wait(self)
+    }
+}
+
+/// Waits for `child` until `deadline`; `None` means it is still running
+pub fn wait_until(
+    child: &mut impl Waitable,
+    deadline: std::time::Instant,
+) -> Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .context("Failed to wait for child process")?
+        {
+            return Ok(Some(status));
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+}
+
+/// Stops `child` with SIGTERM and kills it if it is still running after
+/// `grace`; on non-Unix platforms it is killed immediately
+pub fn terminate(child: &mut impl Waitable, grace: Duration) -> Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: kill has no memory-safety preconditions; the pid belongs to
+        // our own child, which cannot be reused until we reap it
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+
+        let deadline = std::time::Instant::now() + grace;
+        while std::time::Instant::now() < deadline {
+            if let Some(status) = child
+                .try_wait()
+                .context("Failed to wait for child process")?
+            {
+                return Ok(status);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = grace;
+
+    // Fails harmlessly if the child exited in the meantime; wait reports either way
+    let _ = child.kill();
+    child.wait().context("Failed to wait for child process")
+}
+
+/// Rejects `--user`/`--group` on platforms without Unix credentials
+#[cfg(not(unix))]
+pub fn reject_privileges(user: Option<&str>, group: Option<&str>) -> Result<()> {
+    if user.is_some() || group.is_some() {
+        return Err(anyhow::anyhow!(
+            "--user/--group are only supported on Unix platforms"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_honours_quoting() {
+        let args = parse_probe(r#"curl -fsS -H 'Authorization: Bearer {{TOKEN}}' "https://x/a b""#)
+            .unwrap();
+        assert_eq!(
+            args,
+            [
+                "curl",
+                "-fsS",
+                "-H",
+                "Authorization: Bearer {{TOKEN}}",
+                "https://x/a b"
+            ]
+        );
+        assert!(parse_probe("").is_err());
+        assert!(parse_probe("curl 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_expand_placeholders_with_escapes() {
+        let values = |name: &str| match name {
+            "TOKEN" => Ok("abc"),
+            other => Err(anyhow::anyhow!("unknown {}", other)),
+        };
+        assert_eq!(
+            *expand_placeholders("--password={{TOKEN}}:{{TOKEN}}", "the command", values).unwrap(),
+            "--password=abc:abc"
+        );
+        assert_eq!(
+            *expand_placeholders(r"\{\{TOKEN}} {{TOKEN}}", "the command", values).unwrap(),
+            "{{TOKEN}} abc"
+        );
+        assert_eq!(
+            *expand_placeholders("no braces }}", "the command", values).unwrap(),
+            "no braces }}"
+        );
+        assert!(expand_placeholders("{{OTHER}}", "the command", values).is_err());
+        assert!(expand_placeholders("{{TOKEN", "the command", values).is_err());
+    }
+
+    #[test]
+    fn test_unterminated_placeholder_error_hides_the_text() {
+        let template = "user: admin\npassword: hunter2 {{TOKEN\n";
+        let err = expand_placeholders(template, "template.txt", |_| Ok("")).unwrap_err();
+        let message = err.to_string();
+        assert_eq!(
+            message,
+            "Unterminated {{ placeholder in template.txt at line 2, column 19"
+        );
+        assert!(!message.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_script_interpreter_detects_powershell_only() {
+        assert!(script_interpreter("deploy.ps1").is_some());
+        assert!(script_interpreter(r"C:\scripts\Deploy.PS1").is_some());
+        assert!(script_interpreter("build.bat").is_none());
+        assert!(script_interpreter("build.cmd").is_none());
+        assert!(script_interpreter("tool.exe").is_none());
+        assert!(script_interpreter("ps1").is_none());
+    }
+
+    #[test]
+    fn test_build_command_keeps_arguments_separate() {
+        let args = vec![
+            "deploy.ps1".to_string(),
+            "arg with spaces".to_string(),
+            "$(not-expanded)".to_string(),
+        ];
+        let cmd = build_command(&args, true);
+        let actual: Vec<String> = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+
+        if cfg!(windows) {
+            assert_eq!(cmd.get_program(), "powershell");
+            assert_eq!(
+                actual,
+                [
+                    "-NoProfile",
+                    "-NonInteractive",
+                    "-File",
+                    "deploy.ps1",
+                    "arg with spaces",
+                    "$(not-expanded)"
+                ]
+            );
+        } else {
+            assert_eq!(cmd.get_program(), "deploy.ps1");
+            assert_eq!(actual, ["arg with spaces", "$(not-expanded)"]);
+        }
+
+        let cmd = build_command(&args, false);
+        assert_eq!(cmd.get_program(), "deploy.ps1");
+    }
+
+    #[test]
+    fn test_parse_umask_accepts_octal_forms() {
+        assert_eq!(parse_umask("077").unwrap(), 0o077);
+        assert_eq!(parse_umask("0077").unwrap(), 0o077);
+        assert_eq!(parse_umask("77").unwrap(), 0o077);
+        assert_eq!(parse_umask("0o027").unwrap(), 0o027);
+        assert_eq!(parse_umask("0").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_size("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_size("512m").unwrap(), 512 << 20);
+        assert_eq!(parse_size("64KiB").unwrap(), 64 << 10);
+        assert_eq!(parse_size("1048576").unwrap(), 1 << 20);
+        assert_eq!(parse_size("3MB").unwrap(), 3 << 20);
+        for invalid in ["", "0", "2X", "G", "-1G", "1.5G", "99999999T"] {
+            assert!(parse_size(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(parse_cpu_time("300").unwrap(), 300);
+        assert_eq!(parse_cpu_time("300s").unwrap(), 300);
+        assert_eq!(parse_cpu_time("5m").unwrap(), 300);
+        assert_eq!(parse_cpu_time("1h").unwrap(), 3600);
+        for invalid in ["", "0s", "s", "5d", "-5"] {
+            assert!(parse_cpu_time(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("1.5m")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid duration '1.5m'"));
+
+        assert_eq!(parse_nofile("1024").unwrap(), 1024);
+        assert!(parse_nofile("0").is_err());
+        assert!(parse_nofile("many").is_err());
+    }
+
+    #[test]
+    fn test_parse_umask_rejects_invalid_values() {
+        assert!(parse_umask("").is_err());
+        assert!(parse_umask("088").is_err());
+        assert!(parse_umask("abc").is_err());
+        assert!(parse_umask("-77").is_err());
+        assert!(parse_umask("1777").is_err());
+        assert!(parse_umask("00777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_login_environment_skips_profile_noise() {
+        let mut output = b"Welcome!\nLast login: today\n".to_vec();
+        output.extend_from_slice(LOGIN_ENV_MARKER);
+        output.extend_from_slice(b"PATH=/usr/local/bin:/usr/bin\0MULTI=line1\nline2\0");
+        output.extend_from_slice(b"SHLVL=2\0_=/usr/bin/env\0EMPTY=\0garbage\0");
+
+        let vars = parse_login_environment(&output).unwrap();
+        let vars: Vec<(String, String)> = vars
+            .into_iter()
+            .map(|(k, v)| (k.into_string().unwrap(), v.into_string().unwrap()))
+            .collect();
+
+        assert_eq!(
+            vars,
+            vec![
+                ("PATH".to_string(), "/usr/local/bin:/usr/bin".to_string()),
+                ("MULTI".to_string(), "line1\nline2".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_login_environment_requires_marker() {
+        assert!(parse_login_environment(b"PATH=/usr/bin\0").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_privileges_none_without_flags() {
+        assert!(resolve_privileges(None, None).unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_privileges_rejects_unknown_names() {
+        let err = resolve_privileges(Some("no-such-user-local-secrets"), None).unwrap_err();
+        assert!(err.to_string().contains("Unknown user"));
+
+        let err = resolve_privileges(None, Some("no-such-group-local-secrets")).unwrap_err();
+        assert!(err.to_string().contains("Unknown group"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_privileges_validates_names() {
+        assert!(resolve_privileges(Some(""), None).is_err());
+        assert!(resolve_privileges(Some("bad\nname"), None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_privileges_current_identity_is_allowed() {
+        // SAFETY: getuid/getgid have no preconditions
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let privileges = resolve_privileges(Some(&uid.to_string()), Some(&gid.to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(privileges.uid, uid);
+        assert_eq!(privileges.gid, gid);
+        assert!(privileges.groups.contains(&gid));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_privileges_current_identity_needs_no_switch() {
+        // SAFETY: getuid/getgid have no preconditions
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let current = Privileges {
+            uid,
+            gid,
+            groups: unix::current_groups(gid).unwrap(),
+        };
+        assert!(current.is_current());
+
+        let other = Privileges {
+            uid: uid.wrapping_add(1),
+            ..current
+        };
+        assert!(!other.is_current());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_terminate_escalates_to_kill_after_grace() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut polite = Command::new("sleep").arg("30").spawn().unwrap();
+        let status = terminate(&mut polite, Duration::from_secs(5)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+
+        let mut stubborn = Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 30"])
+            .spawn()
+            .unwrap();
+        // Give the shell time to install its trap before signalling it
+        std::thread::sleep(Duration::from_millis(200));
+        let status = terminate(&mut stubborn, Duration::from_millis(200)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_exit_uses_shell_convention() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let killed = ExitStatus::from_raw(libc::SIGKILL);
+        assert_eq!(
+            signal_exit(&killed),
+            Some((137, "child terminated by signal 9 (SIGKILL)".to_string()))
+        );
+        let (code, note) = signal_exit(&ExitStatus::from_raw(libc::SIGSEGV)).unwrap();
+        assert_eq!(code, 128 + libc::SIGSEGV);
+        assert!(note.ends_with("(SIGSEGV)"));
+        // A normal exit (code 3 in the high byte) is not a signal
+        assert_eq!(signal_exit(&ExitStatus::from_raw(3 << 8)), None);
+    }
+}
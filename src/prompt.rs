@@ -0,0 +1,545 @@
+use anyhow::{Context, Result};
+#[cfg(unix)]
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::errors::{self, ErrorCode};
+
+/// How typing at a secret prompt is shown (`--prompt-style`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PromptStyle {
+    /// Nothing is echoed
+    #[default]
+    Hidden,
+    /// One `*` per character typed
+    Asterisks,
+    /// A running character count, e.g. `(12 chars)`
+    Length,
+}
+
+/// Reads one line from the terminal without echoing it, giving up after `timeout`
+pub fn read_secret(style: PromptStyle, timeout: Option<Duration>) -> Result<Zeroizing<String>> {
+    match (style, timeout) {
+        (PromptStyle::Hidden, None) => Ok(Zeroizing::new(
+            rpassword::read_password().context("Failed to read password")?,
+        )),
+        (PromptStyle::Hidden, Some(timeout)) => read_hidden_with_timeout(timeout),
+        (style, timeout) => read_with_feedback(style, timeout),
+    }
+}
+
+fn timed_out(timeout: Duration) -> anyhow::Error {
+    errors::coded(
+        ErrorCode::PromptTimeout,
+        None,
+        format!(
+            "Prompt timed out after {} seconds (--prompt-timeout)",
+            timeout.as_secs()
+        ),
+    )
+}
+
+#[cfg(unix)]
+fn read_hidden_with_timeout(timeout: Duration) -> Result<Zeroizing<String>> {
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("Failed to open the terminal for the prompt")?;
+    let result = read_line_from_tty(&tty, timeout);
+    if result.is_err() {
+        // The typed newline never came, so move past the prompt ourselves
+        eprintln!();
+    }
+    result
+}
+
+/// Reads a line from a terminal in canonical mode with echo off.
+///
+/// poll(2) only reports the terminal readable once a whole line is buffered, so
+/// on timeout any partial input is still in the kernel and is flushed there.
+#[cfg(unix)]
+fn read_line_from_tty(tty: &std::fs::File, timeout: Duration) -> Result<Zeroizing<String>> {
+    use std::io::Read;
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let fd = tty.as_raw_fd();
+    let _echo = TtyMode::new(fd, |mode| {
+        mode.c_lflag &= !libc::ECHO;
+        mode.c_lflag |= libc::ECHONL | libc::ICANON;
+    })?;
+    let deadline = Instant::now() + timeout;
+    let mut line = Zeroizing::new(Vec::new());
+    let mut buf = Zeroizing::new([0u8; 256]);
+    loop {
+        if !wait_readable(fd, Some(deadline))? {
+            // SAFETY: fd is an open terminal for the lifetime of `tty`
+            unsafe { libc::tcflush(fd, libc::TCIFLUSH) };
+            return Err(timed_out(timeout));
+        }
+
+        let read = match (&*tty).read(&mut buf[..]) {
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err).context("Failed to read password"),
+        };
+        let chunk = &buf[..read];
+        let end = chunk.iter().position(|b| *b == b'\n');
+        line.extend_from_slice(&chunk[..end.unwrap_or(read)]);
+        buf.zeroize();
+        if read == 0 || end.is_some() {
+            break;
+        }
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    into_string(&mut line)
+}
+
+#[cfg(unix)]
+fn read_with_feedback(style: PromptStyle, timeout: Option<Duration>) -> Result<Zeroizing<String>> {
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("Failed to open the terminal for the prompt")?;
+    read_edited_from_tty(&tty, style, timeout)
+}
+
+/// Reads a line byte by byte in raw mode and echoes only `style` feedback.
+///
+/// Feedback is redrawn relative to the cursor with backspaces, never by column,
+/// so a terminal resized mid-prompt keeps working. Signal keys arrive as bytes:
+/// Ctrl-C cancels the prompt after the terminal is restored.
+#[cfg(unix)]
+fn read_edited_from_tty(
+    tty: &std::fs::File,
+    style: PromptStyle,
+    timeout: Option<Duration>,
+) -> Result<Zeroizing<String>> {
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let fd = tty.as_raw_fd();
+    let raw = TtyMode::new(fd, |mode| {
+        mode.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        mode.c_cc[libc::VMIN] = 1;
+        mode.c_cc[libc::VTIME] = 0;
+    })?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut editor = Editor::new(style);
+    let mut buf = Zeroizing::new([0u8; 256]);
+    let outcome = loop {
+        match wait_readable(fd, deadline) {
+            Ok(true) => {}
+            Ok(false) => break Err(timed_out(timeout.unwrap_or_default())),
+            Err(err) => break Err(err),
+        }
+        let read = match (&*tty).read(&mut buf[..]) {
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => break Err(anyhow::Error::new(err).context("Failed to read password")),
+        };
+        // A pasted chunk is edited as a whole and redrawn once
+        let mut step = if read == 0 { Step::Done } else { Step::More };
+        for byte in &buf[..read] {
+            step = editor.feed(*byte);
+            if step != Step::More {
+                break;
+            }
+        }
+        buf.zeroize();
+        // Only `*`, digits and cursor movement are written; never the input
+        let _ = (&*tty).write_all(editor.redraw().as_bytes());
+        match step {
+            Step::More => {}
+            Step::Done => break Ok(()),
+            Step::Cancel => break Err(anyhow::anyhow!("Prompt cancelled")),
+        }
+    };
+    let _ = (&*tty).write_all(b"\n");
+    drop(raw);
+    outcome?;
+    into_string(&mut editor.line)
+}
+
+/// Console fallback: styled feedback needs a Unix terminal, so the input stays hidden
+#[cfg(not(unix))]
+fn read_with_feedback(_style: PromptStyle, timeout: Option<Duration>) -> Result<Zeroizing<String>> {
+    read_secret(PromptStyle::Hidden, timeout)
+}
+
+/// What the styled prompt does after a byte of input
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    More,
+    Done,
+    Cancel,
+}
+
+/// Line editing for the styled prompt; only the character count is ever displayed
+struct Editor {
+    style: PromptStyle,
+    line: Zeroizing<Vec<u8>>,
+    /// Feedback currently on screen after the prompt
+    shown: String,
+}
+
+impl Editor {
+    fn new(style: PromptStyle) -> Self {
+        Self {
+            style,
+            line: Zeroizing::new(Vec::new()),
+            shown: String::new(),
+        }
+    }
+
+    fn feed(&mut self, byte: u8) -> Step {
+        match byte {
+            b'\r' | b'\n' => return Step::Done,
+            // Ctrl-C
+            0x03 => return Step::Cancel,
+            // Ctrl-D on an empty line, as in canonical mode
+            0x04 if self.line.is_empty() => return Step::Done,
+            // Backspace and DEL remove one whole UTF-8 character
+            0x08 | 0x7f => {
+                while self.line.last().is_some_and(|b| b & 0xc0 == 0x80) {
+                    self.line.pop();
+                }
+                self.line.pop();
+            }
+            // Ctrl-U clears the line
+            0x15 => self.line.zeroize(),
+            byte => self.line.push(byte),
+        }
+        Step::More
+    }
+
+    fn chars(&self) -> usize {
+        self.line.iter().filter(|b| *b & 0xc0 != 0x80).count()
+    }
+
+    /// Terminal output turning the feedback on screen into the current one
+    fn redraw(&mut self) -> String {
+        let chars = self.chars();
+        let feedback = match self.style {
+            PromptStyle::Hidden => String::new(),
+            PromptStyle::Asterisks => "*".repeat(chars),
+            PromptStyle::Length if chars == 0 => String::new(),
+            PromptStyle::Length => format!("({} char{})", chars, if chars == 1 { "" } else { "s" }),
+        };
+        let common = self
+            .shown
+            .bytes()
+            .zip(feedback.bytes())
+            .take_while(|(shown, new)| shown == new)
+            .count();
+        let erased = self.shown.len() - common;
+        let mut out = "\x08".repeat(erased);
+        out.push_str(&feedback[common..]);
+        if feedback.len() < self.shown.len() {
+            let blank = self.shown.len() - feedback.len();
+            out.push_str(&" ".repeat(blank));
+            out.push_str(&"\x08".repeat(blank));
+        }
+        self.shown = feedback;
+        out
+    }
+}
+
+fn into_string(line: &mut Zeroizing<Vec<u8>>) -> Result<Zeroizing<String>> {
+    match String::from_utf8(std::mem::take(&mut **line)) {
+        Ok(text) => Ok(Zeroizing::new(text)),
+        Err(err) => {
+            let mut bytes = err.into_bytes();
+            bytes.zeroize();
+            Err(anyhow::anyhow!("Entered secret is not valid UTF-8"))
+        }
+    }
+}
+
+/// Waits until `fd` has input; `false` once `deadline` passes (never without one)
+#[cfg(unix)]
+fn wait_readable(fd: std::os::fd::RawFd, deadline: Option<std::time::Instant>) -> Result<bool> {
+    loop {
+        let millis = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    return Ok(false);
+                }
+                i32::try_from(remaining.as_millis())
+                    .unwrap_or(i32::MAX)
+                    .max(1)
+            }
+            None => -1,
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: pollfd points to exactly one initialised entry
+        let ready = unsafe { libc::poll(&mut pollfd, 1, millis) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err).context("Failed to wait for terminal input");
+        }
+        if ready > 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Terminal settings to put back if local-secrets is interrupted mid-prompt
+#[cfg(unix)]
+static SAVED: Mutex<Option<(std::os::fd::RawFd, libc::termios)>> = Mutex::new(None);
+
+/// Puts the terminal back as it was before a prompt changed it.
+///
+/// For the Ctrl-C/SIGTERM handler and the panic hook, which skip destructors.
+pub fn restore_terminal() {
+    #[cfg(unix)]
+    if let Ok(mut saved) = SAVED.try_lock() {
+        if let Some((fd, original)) = saved.take() {
+            // SAFETY: restores settings read by tcgetattr on the same descriptor
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+        }
+    }
+}
+
+/// Applies `change` to the terminal settings until dropped
+#[cfg(unix)]
+struct TtyMode {
+    fd: std::os::fd::RawFd,
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl TtyMode {
+    fn new(fd: std::os::fd::RawFd, change: impl FnOnce(&mut libc::termios)) -> Result<Self> {
+        // SAFETY: termios is plain data and fully written by tcgetattr on success
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: fd is an open descriptor and original is a valid termios
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to read terminal settings");
+        }
+        let mut changed = original;
+        change(&mut changed);
+        *SAVED.lock().unwrap_or_else(PoisonError::into_inner) = Some((fd, original));
+        // SAFETY: as above; changed is a modified copy of the current settings
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &changed) } != 0 {
+            SAVED.lock().unwrap_or_else(PoisonError::into_inner).take();
+            return Err(std::io::Error::last_os_error()).context("Failed to turn off echo");
+        }
+        Ok(Self { fd, original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for TtyMode {
+    fn drop(&mut self) {
+        SAVED.lock().unwrap_or_else(PoisonError::into_inner).take();
+        // SAFETY: restores the settings read in new() on the same descriptor
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Console fallback: the blocking read runs on a worker thread that is abandoned
+/// on timeout; the process exits with the error right after, taking its input along
+#[cfg(not(unix))]
+fn read_hidden_with_timeout(timeout: Duration) -> Result<Zeroizing<String>> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(rpassword::read_password().map(Zeroizing::new));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(result.context("Failed to read password")?),
+        Err(_) => {
+            eprintln!();
+            Err(timed_out(timeout))
+        }
+    }
+}
+
+#[cfg(test)]
+mod editor_tests {
+    use super::*;
+
+    fn typed(style: PromptStyle, input: &[u8]) -> (Editor, Step, String) {
+        let mut editor = Editor::new(style);
+        let mut step = Step::More;
+        let mut screen = String::new();
+        for byte in input {
+            step = editor.feed(*byte);
+            screen.push_str(&editor.redraw());
+            if step != Step::More {
+                break;
+            }
+        }
+        (editor, step, screen)
+    }
+
+    #[test]
+    fn test_backspace_removes_whole_characters() {
+        let (editor, step, screen) = typed(PromptStyle::Asterisks, "ab\u{7f}cé\u{8}d\r".as_bytes());
+        assert_eq!(step, Step::Done);
+        assert_eq!(&editor.line[..], b"acd");
+        assert_eq!(screen, "**\x08 \x08**\x08 \x08*");
+    }
+
+    #[test]
+    fn test_length_shows_only_a_count() {
+        let mut editor = Editor::new(PromptStyle::Length);
+        for byte in b"s3cret" {
+            editor.feed(*byte);
+        }
+        // A paste is redrawn once
+        assert_eq!(editor.redraw(), "(6 chars)");
+        editor.feed(0x7f);
+        assert_eq!(editor.redraw(), format!("{}5 chars)", "\x08".repeat(8)));
+        editor.feed(0x15);
+        assert_eq!(
+            editor.redraw(),
+            format!("{0}{1}{0}", "\x08".repeat(9), " ".repeat(9))
+        );
+        assert!(editor.line.is_empty());
+    }
+
+    #[test]
+    fn test_ctrl_c_cancels_and_ctrl_d_ends_an_empty_line() {
+        assert_eq!(typed(PromptStyle::Asterisks, b"ab\x03").1, Step::Cancel);
+        assert_eq!(typed(PromptStyle::Length, b"\x04").1, Step::Done);
+        let (editor, step, _) = typed(PromptStyle::Length, b"a\x04");
+        assert_eq!((step, &editor.line[..]), (Step::More, &b"a\x04"[..]));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    /// Opens a pseudo-terminal pair: (controller, terminal)
+    fn pty() -> (std::fs::File, std::fs::File) {
+        let mut controller = 0;
+        let mut terminal = 0;
+        let rc = unsafe {
+            libc::openpty(
+                &mut controller,
+                &mut terminal,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(rc, 0, "openpty failed");
+        unsafe {
+            (
+                std::fs::File::from_raw_fd(controller),
+                std::fs::File::from_raw_fd(terminal),
+            )
+        }
+    }
+
+    #[test]
+    fn test_reads_a_line_before_the_timeout() {
+        let (mut controller, terminal) = pty();
+        controller.write_all(b"s3cret value\r\n").unwrap();
+        let line = read_line_from_tty(&terminal, Duration::from_secs(5)).unwrap();
+        assert_eq!(line.as_str(), "s3cret value");
+    }
+
+    #[test]
+    fn test_partial_input_times_out_and_is_discarded() {
+        let (mut controller, terminal) = pty();
+        controller.write_all(b"half-typed").unwrap();
+        let err = read_line_from_tty(&terminal, Duration::from_millis(200)).unwrap_err();
+        assert_eq!(errors::classify(&err).0, ErrorCode::PromptTimeout);
+
+        // The abandoned input must not leak into the next prompt
+        controller.write_all(b"fresh\n").unwrap();
+        let line = read_line_from_tty(&terminal, Duration::from_secs(5)).unwrap();
+        assert_eq!(line.as_str(), "fresh");
+    }
+
+    fn lflag(file: &std::fs::File) -> libc::tcflag_t {
+        let mut mode: libc::termios = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::tcgetattr(file.as_raw_fd(), &mut mode) }, 0);
+        mode.c_lflag
+    }
+
+    /// Input written before the prompt switches modes would otherwise be echoed by the
+    /// pty, and a Ctrl-C byte turned into a signal
+    fn no_echo(terminal: &std::fs::File) {
+        let mut mode: libc::termios = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::tcgetattr(terminal.as_raw_fd(), &mut mode) },
+            0
+        );
+        mode.c_lflag &= !(libc::ECHO | libc::ISIG);
+        assert_eq!(
+            unsafe { libc::tcsetattr(terminal.as_raw_fd(), libc::TCSANOW, &mode) },
+            0
+        );
+    }
+
+    fn echoed(controller: &mut std::fs::File) -> String {
+        let mut buf = [0u8; 1024];
+        let read = controller.read(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..read]).into_owned()
+    }
+
+    #[test]
+    fn test_asterisks_echo_stars_and_restore_the_terminal() {
+        let (mut controller, terminal) = pty();
+        no_echo(&terminal);
+        let before = lflag(&terminal);
+        controller.write_all(b"tok\x7fken\r").unwrap();
+        let line = read_edited_from_tty(
+            &terminal,
+            PromptStyle::Asterisks,
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap();
+        assert_eq!(line.as_str(), "token");
+        assert_eq!(lflag(&terminal), before);
+
+        let screen = echoed(&mut controller);
+        assert_eq!(screen, "*****\r\n");
+        assert!(!screen.contains("tok"));
+    }
+
+    #[test]
+    fn test_length_style_times_out_and_ctrl_c_cancels() {
+        let (mut controller, terminal) = pty();
+        no_echo(&terminal);
+        controller.write_all(b"half").unwrap();
+        let err = read_edited_from_tty(
+            &terminal,
+            PromptStyle::Length,
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap_err();
+        assert_eq!(errors::classify(&err).0, ErrorCode::PromptTimeout);
+        let screen = echoed(&mut controller);
+        assert!(screen.starts_with("(4 chars)"), "{:?}", screen);
+
+        controller.write_all(b"abc\x03").unwrap();
+        let err =
+            read_edited_from_tty(&terminal, PromptStyle::Length, Some(Duration::from_secs(5)))
+                .unwrap_err();
+        assert_eq!(format!("{:#}", err), "Prompt cancelled");
+        assert_ne!(lflag(&terminal) & libc::ICANON, 0);
+    }
+}
@@ -1,4 +1,7 @@
 use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::path::Path;
+use zeroize::Zeroizing;
 
 /// Security validation functions for input sanitization and attack prevention
 /// Based on vulnerability research from similar tools and security best practices.
@@ -29,6 +32,16 @@ pub fn validate_env_var_name(name: &str) -> Result<()> {
         ));
     }
 
+    // `%NAME%`, `$NAME` and friends: the user meant the name, not its expansion
+    if let Some((shell, inner)) = unexpanded_reference(name) {
+        return Err(anyhow::anyhow!(
+            "It looks like your shell did not expand {} ({} syntax); pass the variable name {} without expansion syntax",
+            display_name(name),
+            shell,
+            inner
+        ));
+    }
+
     // Environment variable names must not start with a number
     if name.chars().next().is_some_and(|c| c.is_ascii_digit()) {
         return Err(anyhow::anyhow!(
@@ -66,8 +79,22 @@ pub fn validate_env_var_name(name: &str) -> Result<()> {
         }
     }
 
-    // Check for critical system variables that shouldn't be overridden
-    let critical_vars = [
+    // Check for suspicious patterns that might indicate attacks
+    if name.starts_with('/') || name.starts_with('\\') || name.contains("://") {
+        return Err(anyhow::anyhow!(
+            "Environment variable name looks like a file path or URL"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The system variable `name` would override, if it is one that shouldn't be.
+///
+/// Validation does not warn itself: only commands that inject or store the
+/// name do, through `errors::warn`.
+pub fn critical_variable(name: &str) -> Option<&'static str> {
+    const CRITICAL_VARS: [&str; 19] = [
         "PATH",
         "LD_LIBRARY_PATH",
         "DYLD_LIBRARY_PATH",
@@ -90,30 +117,32 @@ pub fn validate_env_var_name(name: &str) -> Result<()> {
         "APPDATA",
     ];
 
-    for critical in &critical_vars {
-        if name.eq_ignore_ascii_case(critical) {
-            eprintln!(
-                "Warning: Overriding critical system variable '{}' - this may cause unexpected behavior", 
-                critical
-            );
-        }
-    }
+    CRITICAL_VARS
+        .into_iter()
+        .find(|critical| name.eq_ignore_ascii_case(critical))
+}
 
-    // Check for suspicious patterns that might indicate attacks
-    if name.starts_with('/') || name.starts_with('\\') || name.contains("://") {
-        return Err(anyhow::anyhow!(
-            "Environment variable name looks like a file path or URL"
-        ));
+/// Warns when an injected or stored name overrides a critical system variable
+pub fn warn_if_critical(name: &str) {
+    if let Some(critical) = critical_variable(name) {
+        crate::errors::warn(
+            "critical_variable",
+            Some(name),
+            format!(
+                "Overriding critical system variable '{}' - this may cause unexpected behavior",
+                critical
+            ),
+        );
     }
-
-    Ok(())
 }
 
+/// Largest secret value accepted, in bytes (1MB)
+pub const MAX_SECRET_BYTES: usize = 1_048_576;
+
 /// Validates secret values to prevent various injection attacks
 pub fn validate_secret_value(value: &str) -> Result<()> {
     // Check length limit to prevent resource exhaustion
-    if value.len() > 1_048_576 {
-        // 1MB limit
+    if value.len() > MAX_SECRET_BYTES {
         return Err(anyhow::anyhow!("Secret value too long (max 1MB)"));
     }
 
@@ -129,28 +158,124 @@ pub fn validate_secret_value(value: &str) -> Result<()> {
     Ok(())
 }
 
-/// Validates command arguments to prevent injection attacks
+/// Removes terminal escape sequences from a typed or pasted secret.
+///
+/// Some terminals leak bracketed-paste markers (`ESC[200~` ... `ESC[201~`) into
+/// password prompts, which silently corrupts the secret. CSI, OSC and two-byte
+/// ESC sequences are removed; returns `None` when the input had none.
+pub fn strip_terminal_escapes(input: &str) -> Option<Zeroizing<String>> {
+    if !input.contains('\u{1b}') {
+        return None;
+    }
+
+    let mut out = Zeroizing::new(String::with_capacity(input.len()));
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates, then one final byte (`~` for paste markers)
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\u{40}'..='\u{7e}').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Two-byte sequences such as ESC c; anything else keeps the following character
+            Some(c) if ('\u{30}'..='\u{7e}').contains(&c) => {}
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    Some(out)
+}
+
+/// Why a stored value looks like a placeholder rather than a real secret.
+///
+/// Such values usually come from broken dotenv files and cause confusing
+/// authentication failures downstream. The reason never includes the value.
+pub fn blank_value_reason(value: &str) -> Option<&'static str> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Some("empty after trimming whitespace");
+    }
+    if ["null", "undefined", "none"]
+        .iter()
+        .any(|literal| trimmed.eq_ignore_ascii_case(literal))
+    {
+        return Some("literal null/undefined/None");
+    }
+    if trimmed.chars().all(|c| matches!(c, '"' | '\'' | '`')) {
+        return Some("only quote characters");
+    }
+    None
+}
+
+/// Validates command arguments before spawning the child.
+///
+/// No shell is involved in spawning, so shell metacharacters are not special in
+/// either the program name or its arguments (`foo&bar.exe` is a legitimate
+/// program). Instead argv[0] is treated as a program/path token: it must not
+/// contain NUL bytes, newlines or other control characters, and relative paths
+/// trigger a warning when the current directory is world-writable, since any
+/// local user could have planted the binary there.
 pub fn validate_command_args(args: &[String]) -> Result<()> {
+    validate_command_args_in(args, None)
+}
+
+/// [`validate_command_args`] for a program that will run in `dir` (`run --cwd`);
+/// `None` means our own current directory
+pub fn validate_command_args_in(args: &[String], dir: Option<&Path>) -> Result<()> {
     if args.is_empty() {
         return Err(anyhow::anyhow!("No command specified"));
     }
 
     let command = &args[0];
 
-    // Check for empty or suspicious command
+    // Check for empty command
     if command.trim().is_empty() {
         return Err(anyhow::anyhow!("Empty command specified"));
     }
 
-    // Check for obvious shell injection patterns in command
-    let dangerous_command_patterns = [";", "&", "|", "`", "$(", "&&", "||", ">>", "<<"];
+    // The program token must be a single, printable path or name
+    if command.contains('\0') {
+        return Err(anyhow::anyhow!("Command contains null byte"));
+    }
+    if command.contains('\n') || command.contains('\r') {
+        return Err(anyhow::anyhow!("Command contains a newline"));
+    }
+    if command.chars().any(|c| c.is_control()) {
+        return Err(anyhow::anyhow!("Command contains control characters"));
+    }
 
-    for pattern in &dangerous_command_patterns {
-        if command.contains(pattern) {
-            return Err(anyhow::anyhow!(
-                "Command contains dangerous pattern: {}",
-                pattern
-            ));
+    if is_relative_path_program(command) {
+        let cwd = match dir {
+            Some(dir) => Ok(dir.to_path_buf()),
+            None => std::env::current_dir(),
+        };
+        if let Ok(cwd) = cwd {
+            if is_world_writable(&cwd) {
+                eprintln!(
+                    "Warning: running relative program '{}' from world-writable directory {} - other users could have replaced it",
+                    command,
+                    cwd.display()
+                );
+            }
         }
     }
 
@@ -171,17 +296,229 @@ pub fn validate_command_args(args: &[String]) -> Result<()> {
     Ok(())
 }
 
-/// Validates the overall CLI arguments for security issues
-pub fn validate_cli_security(env_vars: &[String], command_args: &[String]) -> Result<()> {
-    // Validate environment variable names
+/// Validates a `run --shell` script.
+///
+/// The script is meant to hold pipes, quotes, `$VAR` references and newlines, so
+/// only what no shell can take is refused: NUL bytes and oversized input.
+pub fn validate_shell_script(script: &str) -> Result<()> {
+    if script.trim().is_empty() {
+        return Err(anyhow::anyhow!("--shell script is empty"));
+    }
+    if script.contains('\0') {
+        return Err(anyhow::anyhow!("--shell script contains a null byte"));
+    }
+    if script.len() > 32_768 {
+        return Err(anyhow::anyhow!("--shell script too long (max 32KB)"));
+    }
+    Ok(())
+}
+
+/// True when the program is a relative path (`./tool`, `bin/tool`) resolved
+/// against the current directory rather than looked up on PATH
+fn is_relative_path_program(program: &str) -> bool {
+    let has_separator = program.contains('/') || (cfg!(windows) && program.contains('\\'));
+    has_separator && std::path::Path::new(program).is_relative()
+}
+
+/// True when any user may create files in `dir`
+#[cfg(unix)]
+pub fn is_world_writable(dir: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(dir)
+        .map(|meta| meta.permissions().mode() & 0o002 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_world_writable(_dir: &std::path::Path) -> bool {
+    // Windows ACLs cannot be summarised as a single "world" bit
+    false
+}
+
+/// True for characters that reorder or hide text without being visible
+fn is_invisible_or_bidi(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}'                    // Arabic letter mark
+            | '\u{200B}'..='\u{200F}' // Zero-width space/joiners, LRM, RLM
+            | '\u{202A}'..='\u{202E}' // Bidi embeddings and overrides
+            | '\u{2060}'..='\u{2064}' // Word joiner and invisible operators
+            | '\u{2066}'..='\u{2069}' // Bidi isolates
+            | '\u{FEFF}'               // Zero-width no-break space
+    )
+}
+
+/// Renders a user-supplied name for prompts, logs and error messages.
+///
+/// Invisible, bidi and control characters are shown as `<U+XXXX>` so they
+/// cannot spoof the surrounding text, and any other non-ASCII characters are
+/// listed after the name because they may be confusables (Cyrillic `А` vs `A`).
+/// The stored key is never changed; this is for display only.
+pub fn display_name(name: &str) -> Cow<'_, str> {
+    if name.is_ascii() && !name.chars().any(|c| c.is_ascii_control()) {
+        return Cow::Borrowed(name);
+    }
+
+    let mut shown = String::with_capacity(name.len());
+    let mut non_ascii = Vec::new();
+    for c in name.chars() {
+        if c.is_control() || is_invisible_or_bidi(c) {
+            shown.push_str(&format!("<U+{:04X}>", c as u32));
+        } else {
+            if !c.is_ascii() {
+                non_ascii.push(format!("U+{:04X}", c as u32));
+            }
+            shown.push(c);
+        }
+    }
+    if !non_ascii.is_empty() {
+        shown.push_str(&format!(" ({})", non_ascii.join(" ")));
+    }
+    Cow::Owned(shown)
+}
+
+/// Validates a keyring namespace, which follows the variable-name character rules
+pub fn validate_namespace(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Namespace cannot be empty"));
+    }
+
+    if name.len() > 64 {
+        return Err(anyhow::anyhow!("Namespace too long (max 64 characters)"));
+    }
+
+    if name.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+        return Err(anyhow::anyhow!("Namespace cannot start with a number"));
+    }
+
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow::anyhow!(
+            "Namespace contains invalid characters (only A-Z, a-z, 0-9, _ allowed)"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Splits an `--env` argument of the form `NAME` or `NAME@namespace`
+pub fn split_env_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once('@') {
+        Some((name, namespace)) => (name, Some(namespace)),
+        None => (spec, None),
+    }
+}
+
+/// Recognises an unexpanded variable reference and returns the shell it belongs to and
+/// the name inside: `%NAME%` (cmd.exe), `$env:NAME` / `${env:NAME}` (PowerShell) and
+/// `$NAME` / `${NAME}` (POSIX shells)
+pub fn unexpanded_reference(arg: &str) -> Option<(&'static str, &str)> {
+    let (shell, inner) = if let Some(inner) = arg
+        .strip_prefix('%')
+        .and_then(|rest| rest.strip_suffix('%'))
+    {
+        ("cmd.exe", inner)
+    } else if let Some(inner) = arg
+        .strip_prefix("${env:")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        ("PowerShell", inner)
+    } else if let Some(inner) = arg.strip_prefix("$env:") {
+        ("PowerShell", inner)
+    } else if let Some(inner) = arg
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        ("POSIX shell", inner)
+    } else if let Some(inner) = arg.strip_prefix('$') {
+        ("POSIX shell", inner)
+    } else {
+        return None;
+    };
+
+    let plausible = inner.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && inner.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    plausible.then_some((shell, inner))
+}
+
+/// Splits the trailing `!` that marks an `--env` argument as never saved when prompted for
+pub fn split_no_save(spec: &str) -> (&str, bool) {
+    match spec.strip_suffix('!') {
+        Some(spec) => (spec, true),
+        None => (spec, false),
+    }
+}
+
+/// Splits an `--env TARGET=SOURCE` alias; derive arguments are not aliases
+pub fn split_alias(spec: &str) -> Option<(&str, &str)> {
+    if crate::derive::parse_spec(spec).is_some() {
+        return None;
+    }
+    spec.split_once('=')
+}
+
+/// The `--env` reference naming the stored secret behind an argument
+pub fn source_spec(spec: &str) -> &str {
+    if let Some(derive) = crate::derive::parse_spec(spec) {
+        return derive.master;
+    }
+    split_alias(spec).map_or(spec, |(_, source)| source)
+}
+
+/// The variable an `--env` argument is injected as
+pub fn injected_name(spec: &str) -> &str {
+    if let Some(derive) = crate::derive::parse_spec(spec) {
+        return derive.target;
+    }
+    match split_alias(spec) {
+        Some((target, _)) => target,
+        None => split_env_spec(spec).0,
+    }
+}
+
+/// Validates the overall CLI arguments for security issues; `cwd` is where the
+/// command will run, if not in our own current directory
+pub fn validate_cli_security(
+    env_vars: &[String],
+    command_args: &[String],
+    cwd: Option<&Path>,
+) -> Result<()> {
+    // Validate environment variable names and optional namespaces
     for env_var in env_vars {
-        validate_env_var_name(env_var)
-            .with_context(|| format!("Invalid environment variable name: {}", env_var))?;
+        if let Some(spec) = crate::derive::parse_spec(env_var) {
+            crate::derive::validate_spec(&spec)
+                .with_context(|| format!("Invalid --env {}", display_name(env_var)))?;
+            continue;
+        }
+
+        // `TARGET=SOURCE` injects the secret stored as SOURCE under the name TARGET
+        let env_var_source = match split_alias(env_var) {
+            Some((target, source)) => {
+                validate_env_var_name(target).with_context(|| {
+                    format!(
+                        "Invalid environment variable name: {}",
+                        display_name(env_var)
+                    )
+                })?;
+                source
+            }
+            None => env_var.as_str(),
+        };
+        let (name, namespace) = split_env_spec(env_var_source);
+        validate_env_var_name(name).with_context(|| {
+            format!(
+                "Invalid environment variable name: {}",
+                display_name(env_var)
+            )
+        })?;
+        if let Some(namespace) = namespace {
+            validate_namespace(namespace)
+                .with_context(|| format!("Invalid namespace in --env {}", display_name(env_var)))?;
+        }
     }
 
     // Validate command arguments if provided
     if !command_args.is_empty() {
-        validate_command_args(command_args).context("Invalid command arguments")?;
+        validate_command_args_in(command_args, cwd).context("Invalid command arguments")?;
     }
 
     // Check for suspicious combinations
@@ -227,12 +564,219 @@ mod tests {
         assert!(validate_secret_value(&long_secret).is_err());
     }
 
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
     #[test]
     fn test_validate_command_args() {
-        assert!(validate_command_args(&["echo".to_string(), "hello".to_string()]).is_ok());
+        assert!(validate_command_args(&args(&["echo", "hello"])).is_ok());
         assert!(validate_command_args(&[]).is_err());
-        assert!(validate_command_args(&["".to_string()]).is_err());
-        assert!(validate_command_args(&["echo; rm -rf /".to_string()]).is_err());
-        assert!(validate_command_args(&["echo $(whoami)".to_string()]).is_err());
+        assert!(validate_command_args(&args(&[""])).is_err());
+        assert!(validate_command_args(&args(&["   "])).is_err());
+    }
+
+    #[test]
+    fn test_validate_command_args_allows_former_false_positives() {
+        // No shell is involved, so metacharacters are ordinary characters
+        assert!(validate_command_args(&args(&["foo&bar.exe"])).is_ok());
+        assert!(validate_command_args(&args(&["C:\\Tools\\a&b\\tool.exe", "--flag"])).is_ok());
+        assert!(validate_command_args(&args(&["report|v2"])).is_ok());
+        assert!(validate_command_args(&args(&["echo; rm -rf /"])).is_ok());
+        assert!(validate_command_args(&args(&["echo $(whoami)"])).is_ok());
+        assert!(validate_command_args(&args(&["sh", "-c", "curl -H \"$TOKEN\" | jq ."])).is_ok());
+    }
+
+    #[test]
+    fn test_validate_command_args_rejects_former_false_negatives() {
+        assert!(validate_command_args(&args(&["tool\nrm -rf /"])).is_err());
+        assert!(validate_command_args(&args(&["tool\r"])).is_err());
+        assert!(validate_command_args(&args(&["tool\x1b[2J"])).is_err());
+        assert!(validate_command_args(&args(&["tool\0"])).is_err());
+        assert!(validate_command_args(&args(&["tool", "arg\0"])).is_err());
+        assert!(validate_command_args(&args(&["tool", &"x".repeat(40_000)])).is_err());
+    }
+
+    #[test]
+    fn test_validate_shell_script() {
+        assert!(
+            validate_shell_script("curl -H \"Authorization: $TOKEN\" https://x | jq .").is_ok()
+        );
+        assert!(validate_shell_script("set -e\nmake && ./deploy.sh").is_ok());
+        assert!(validate_shell_script("  ").is_err());
+        assert!(validate_shell_script("echo \0").is_err());
+        assert!(validate_shell_script(&"x".repeat(40_000)).is_err());
+    }
+
+    #[test]
+    fn test_env_spec_with_namespace() {
+        assert_eq!(split_env_spec("NPM_TOKEN"), ("NPM_TOKEN", None));
+        assert_eq!(split_env_spec("NPM_TOKEN@org"), ("NPM_TOKEN", Some("org")));
+
+        assert!(validate_cli_security(&["NPM_TOKEN@org".to_string()], &[], None).is_ok());
+        assert!(validate_cli_security(&["NPM_TOKEN@".to_string()], &[], None).is_err());
+        assert!(validate_cli_security(&["NPM_TOKEN@a@b".to_string()], &[], None).is_err());
+        assert!(validate_cli_security(&["NPM_TOKEN@../x".to_string()], &[], None).is_err());
+        assert!(validate_cli_security(&["@org".to_string()], &[], None).is_err());
+        assert!(validate_namespace(&"a".repeat(65)).is_err());
+        assert!(validate_namespace("1org").is_err());
+    }
+
+    #[test]
+    fn test_unexpanded_references_get_a_suggestion() {
+        for (arg, shell) in [
+            ("%GITHUB_TOKEN%", "cmd.exe"),
+            ("$env:GITHUB_TOKEN", "PowerShell"),
+            ("${env:GITHUB_TOKEN}", "PowerShell"),
+            ("${GITHUB_TOKEN}", "POSIX shell"),
+            ("$GITHUB_TOKEN", "POSIX shell"),
+        ] {
+            assert_eq!(
+                unexpanded_reference(arg),
+                Some((shell, "GITHUB_TOKEN")),
+                "{}",
+                arg
+            );
+            let message = validate_env_var_name(arg).unwrap_err().to_string();
+            assert!(
+                message.contains("pass the variable name GITHUB_TOKEN without expansion syntax"),
+                "{}",
+                message
+            );
+        }
+
+        for arg in ["GITHUB_TOKEN", "%%", "$(whoami)", "${1X}", "%A B%", "$"] {
+            assert_eq!(unexpanded_reference(arg), None, "{}", arg);
+        }
+    }
+
+    #[test]
+    fn test_split_no_save() {
+        assert_eq!(split_no_save("ONE_TIME_OTP!"), ("ONE_TIME_OTP", true));
+        assert_eq!(split_no_save("OTP=SOURCE@team!"), ("OTP=SOURCE@team", true));
+        assert_eq!(split_no_save("DB_PASSWORD"), ("DB_PASSWORD", false));
+    }
+
+    #[test]
+    fn test_strip_terminal_escapes() {
+        assert!(strip_terminal_escapes("plain-secret").is_none());
+        assert_eq!(
+            strip_terminal_escapes("\u{1b}[200~ghp_token\u{1b}[201~")
+                .unwrap()
+                .as_str(),
+            "ghp_token"
+        );
+        // Only the start marker (the prompt ended before the end marker was read)
+        assert_eq!(
+            strip_terminal_escapes("\u{1b}[200~abc").unwrap().as_str(),
+            "abc"
+        );
+        // Mixed content: colour codes, a title OSC and a two-byte sequence around real text
+        assert_eq!(
+            strip_terminal_escapes(
+                "a\u{1b}[1;31mb\u{1b}]0;title\u{7}c\u{1b}]8;;x\u{1b}\\d\u{1b}ce"
+            )
+            .unwrap()
+            .as_str(),
+            "abcde"
+        );
+        assert_eq!(strip_terminal_escapes("x\u{1b}").unwrap().as_str(), "x");
+    }
+
+    #[test]
+    fn test_blank_value_reasons() {
+        assert_eq!(
+            blank_value_reason(" \t"),
+            Some("empty after trimming whitespace")
+        );
+        assert_eq!(
+            blank_value_reason("NULL"),
+            Some("literal null/undefined/None")
+        );
+        assert_eq!(
+            blank_value_reason(" undefined\n"),
+            Some("literal null/undefined/None")
+        );
+        assert_eq!(
+            blank_value_reason("None"),
+            Some("literal null/undefined/None")
+        );
+        assert_eq!(blank_value_reason("\"\""), Some("only quote characters"));
+        assert_eq!(blank_value_reason("''"), Some("only quote characters"));
+        assert_eq!(blank_value_reason("nullable-token"), None);
+        assert_eq!(blank_value_reason(" a "), None);
+    }
+
+    #[test]
+    fn test_env_spec_aliases() {
+        assert_eq!(
+            split_alias("GH_TOKEN=GITHUB_PAT"),
+            Some(("GH_TOKEN", "GITHUB_PAT"))
+        );
+        assert_eq!(split_alias("KEY=derive:MASTER:a"), None);
+        assert_eq!(source_spec("GH_TOKEN=GITHUB_PAT@org"), "GITHUB_PAT@org");
+        assert_eq!(source_spec("KEY=derive:MASTER:a"), "MASTER");
+        assert_eq!(source_spec("NPM_TOKEN@org"), "NPM_TOKEN@org");
+        assert_eq!(injected_name("GH_TOKEN=GITHUB_PAT@org"), "GH_TOKEN");
+        assert_eq!(injected_name("KEY=derive:MASTER:a"), "KEY");
+        assert_eq!(injected_name("NPM_TOKEN@org"), "NPM_TOKEN");
+
+        assert!(validate_cli_security(&["GH_TOKEN=GITHUB_PAT@org".to_string()], &[], None).is_ok());
+        assert!(validate_cli_security(&["1GH=GITHUB_PAT".to_string()], &[], None).is_err());
+        assert!(validate_cli_security(&["GH_TOKEN=".to_string()], &[], None).is_err());
+        assert!(validate_cli_security(&["GH_TOKEN=A=B".to_string()], &[], None).is_err());
+    }
+
+    #[test]
+    fn test_display_name_neutralises_spoofing_characters() {
+        // Same attack strings as tests/security_validation_tests.rs
+        assert_eq!(display_name("VAR\u{202E}KCATTA"), "VAR<U+202E>KCATTA");
+        assert_eq!(display_name("VAR\u{FEFF}HIDDEN"), "VAR<U+FEFF>HIDDEN");
+        assert_eq!(display_name("VAR\u{200B}HIDDEN"), "VAR<U+200B>HIDDEN");
+        assert_eq!(display_name("VAR\u{2066}X\u{2069}"), "VAR<U+2066>X<U+2069>");
+        assert_eq!(display_name("VAR\x1b[2J"), "VAR<U+001B>[2J");
+    }
+
+    #[test]
+    fn test_display_name_annotates_confusables_and_keeps_ascii() {
+        assert!(matches!(
+            display_name("GITHUB_PAT"),
+            Cow::Borrowed("GITHUB_PAT")
+        ));
+        // Cyrillic capital A looks identical to Latin A
+        assert_eq!(display_name("\u{0410}PI_KEY"), "\u{0410}PI_KEY (U+0410)");
+    }
+
+    #[test]
+    fn test_relative_program_detection() {
+        assert!(is_relative_path_program("./deploy.sh"));
+        assert!(is_relative_path_program("bin/tool"));
+        assert!(!is_relative_path_program("deploy.sh")); // PATH lookup
+        assert!(!is_relative_path_program("/usr/bin/env"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_world_writable_detection() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("local-secrets-ww-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(is_world_writable(&dir));
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(!is_world_writable(&dir));
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_critical_variable_is_reported_not_printed() {
+        assert_eq!(critical_variable("path"), Some("PATH"));
+        assert_eq!(critical_variable("SystemRoot"), Some("SYSTEMROOT"));
+        assert_eq!(critical_variable("API_KEY"), None);
+        // Still a valid name; whether to warn is up to the command
+        assert!(validate_env_var_name("PATH").is_ok());
     }
 }
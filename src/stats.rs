@@ -0,0 +1,232 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Timing and resource figures collected for `--stats`; never holds secret data
+#[derive(Default)]
+pub struct RunStats {
+    backends: Vec<BackendTiming>,
+    prompts: usize,
+    child_wall: Option<Duration>,
+    child_usage: Option<ChildUsage>,
+    limits: Vec<String>,
+}
+
+struct BackendTiming {
+    backend: &'static str,
+    lookups: usize,
+    missing: usize,
+    elapsed: Duration,
+}
+
+/// CPU time and peak memory of waited-for children
+#[derive(Clone, Copy)]
+pub struct ChildUsage {
+    pub user: Duration,
+    pub system: Duration,
+    /// Peak resident set size in KiB (maximum over all children so far)
+    pub max_rss_kib: u64,
+}
+
+impl RunStats {
+    /// Records one `retrieve` call against `backend`
+    pub fn record_lookup(&mut self, backend: &'static str, elapsed: Duration, found: bool) {
+        let index = match self.backends.iter().position(|t| t.backend == backend) {
+            Some(index) => index,
+            None => {
+                self.backends.push(BackendTiming {
+                    backend,
+                    lookups: 0,
+                    missing: 0,
+                    elapsed: Duration::ZERO,
+                });
+                self.backends.len() - 1
+            }
+        };
+        let timing = &mut self.backends[index];
+        timing.lookups += 1;
+        timing.elapsed += elapsed;
+        if !found {
+            timing.missing += 1;
+        }
+    }
+
+    pub fn record_prompt(&mut self) {
+        self.prompts += 1;
+    }
+
+    /// Records the child's wall-clock time and its resource usage when available
+    pub fn record_child(&mut self, wall: Duration, usage: Option<ChildUsage>) {
+        self.child_wall = Some(wall);
+        self.child_usage = usage;
+    }
+
+    /// Records that a child hit one of its `--limit-*` resource limits
+    pub fn record_limit(&mut self, note: String) {
+        self.limits.push(note);
+    }
+
+    /// Renders the stderr footer block
+    pub fn footer(&self) -> String {
+        let mut out = String::from("--- local-secrets stats ---\n");
+        for timing in &self.backends {
+            let _ = writeln!(
+                out,
+                "resolve {}: {} lookup(s), {} missing, {}",
+                timing.backend,
+                timing.lookups,
+                timing.missing,
+                format_duration(timing.elapsed)
+            );
+        }
+        let _ = writeln!(out, "prompts: {}", self.prompts);
+        if let Some(wall) = self.child_wall {
+            let _ = writeln!(out, "child wall: {}", format_duration(wall));
+        }
+        match self.child_usage {
+            Some(usage) => {
+                let _ = writeln!(
+                    out,
+                    "child cpu: user {} sys {}",
+                    format_duration(usage.user),
+                    format_duration(usage.system)
+                );
+                let _ = writeln!(out, "child peak rss: {} KiB", usage.max_rss_kib);
+            }
+            None => out.push_str("child cpu: n/a\nchild peak rss: n/a\n"),
+        }
+        for note in &self.limits {
+            let _ = writeln!(out, "limit: {}", note);
+        }
+        out
+    }
+}
+
+/// The `--summary` line. Its format is a stable interface for CI log scanners:
+/// fields may be appended, but existing ones are never renamed or reordered.
+pub fn summary_line(
+    injected: usize,
+    missing: usize,
+    backend: &str,
+    exit: i32,
+    duration: Duration,
+) -> String {
+    format!(
+        "local-secrets: injected={} missing={} backend={} exit={} duration={:.1}s",
+        injected,
+        missing,
+        backend,
+        exit,
+        duration.as_secs_f64()
+    )
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.3}s", duration.as_secs_f64())
+    }
+}
+
+/// Cumulative usage of all waited-for children of this process
+#[cfg(unix)]
+pub fn children_usage() -> Option<ChildUsage> {
+    // SAFETY: getrusage only writes into the zero-initialised struct we pass
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return None;
+    }
+
+    let timeval = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec.max(0) as u64)
+            + Duration::from_micros(tv.tv_usec.max(0) as u64)
+    };
+    // macOS reports ru_maxrss in bytes, other Unix systems in KiB
+    let max_rss = usage.ru_maxrss.max(0) as u64;
+    let max_rss_kib = if cfg!(target_os = "macos") {
+        max_rss / 1024
+    } else {
+        max_rss
+    };
+
+    Some(ChildUsage {
+        user: timeval(usage.ru_utime),
+        system: timeval(usage.ru_stime),
+        max_rss_kib,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn children_usage() -> Option<ChildUsage> {
+    None
+}
+
+/// Usage attributable to children reaped since `before` was taken
+pub fn usage_since(before: Option<ChildUsage>, after: Option<ChildUsage>) -> Option<ChildUsage> {
+    let after = after?;
+    Some(match before {
+        Some(before) => ChildUsage {
+            user: after.user.saturating_sub(before.user),
+            system: after.system.saturating_sub(before.system),
+            max_rss_kib: after.max_rss_kib,
+        },
+        None => after,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_footer_aggregates_lookups_per_backend() {
+        let mut stats = RunStats::default();
+        stats.record_lookup("keyring", Duration::from_millis(2), true);
+        stats.record_lookup("keyring", Duration::from_millis(3), false);
+        stats.record_prompt();
+        stats.record_child(Duration::from_millis(1500), None);
+
+        let footer = stats.footer();
+        assert!(footer.contains("resolve keyring: 2 lookup(s), 1 missing, 5.0ms\n"));
+        assert!(footer.contains("prompts: 1\n"));
+        assert!(footer.contains("child wall: 1.500s\n"));
+        assert!(footer.contains("child cpu: n/a\n"));
+        assert!(!footer.contains("limit:"));
+
+        stats.record_limit("Child exceeded its CPU time limit (--limit-cpu 1s)".to_string());
+        assert!(stats
+            .footer()
+            .ends_with("limit: Child exceeded its CPU time limit (--limit-cpu 1s)\n"));
+    }
+
+    #[test]
+    fn test_summary_line_format_is_stable() {
+        assert_eq!(
+            summary_line(4, 0, "keyring", 0, Duration::from_millis(12_345)),
+            "local-secrets: injected=4 missing=0 backend=keyring exit=0 duration=12.3s"
+        );
+        assert_eq!(
+            summary_line(1, 2, "keyring", 137, Duration::ZERO),
+            "local-secrets: injected=1 missing=2 backend=keyring exit=137 duration=0.0s"
+        );
+    }
+
+    #[test]
+    fn test_usage_since_subtracts_earlier_children() {
+        let before = ChildUsage {
+            user: Duration::from_millis(10),
+            system: Duration::from_millis(5),
+            max_rss_kib: 100,
+        };
+        let after = ChildUsage {
+            user: Duration::from_millis(30),
+            system: Duration::from_millis(6),
+            max_rss_kib: 200,
+        };
+        let usage = usage_since(Some(before), Some(after)).unwrap();
+        assert_eq!(usage.user, Duration::from_millis(20));
+        assert_eq!(usage.system, Duration::from_millis(1));
+        assert_eq!(usage.max_rss_kib, 200);
+        assert!(usage_since(Some(before), None).is_none());
+    }
+}
@@ -0,0 +1,257 @@
+//! Keeps the child's whole process tree from outliving local-secrets (`run --kill-tree`).
+//!
+//! Grandchildren inherit the injected secrets, so they are stopped together with
+//! the child: on Windows through a job object that is killed when its last handle
+//! closes, on Unix by signalling the child's own process group.
+
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(unix)]
+use std::sync::{Mutex, MutexGuard};
+
+/// Set once the signal handler has started stopping the trees
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Process groups that are still running, for the signal handler
+#[cfg(unix)]
+static GROUPS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// Puts the child of `cmd` in a process group of its own; call before spawning.
+///
+/// Returns false when the child should stay in ours instead: under a controlling
+/// terminal, job control already delivers Ctrl-C and hangups to the whole tree,
+/// and a child moved out of the foreground group could no longer read the terminal.
+#[cfg(unix)]
+pub fn prepare(cmd: &mut Command) -> bool {
+    use std::os::unix::process::CommandExt;
+
+    if std::fs::File::open("/dev/tty").is_ok() {
+        return false;
+    }
+    cmd.process_group(0);
+    true
+}
+
+#[cfg(windows)]
+pub fn prepare(_cmd: &mut Command) -> bool {
+    true
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn prepare(_cmd: &mut Command) -> bool {
+    false
+}
+
+/// The tree rooted at one child; whatever is left of it is stopped when dropped
+pub struct ProcessTree {
+    #[cfg(unix)]
+    group: libc::pid_t,
+    #[cfg(windows)]
+    job: windows_sys::Win32::Foundation::HANDLE,
+}
+
+impl ProcessTree {
+    /// Tracks the tree of a child spawned from a command passed to [`prepare`]
+    #[cfg(unix)]
+    pub fn adopt(child: &Child) -> Option<Self> {
+        let group = libc::pid_t::try_from(child.id()).ok()?;
+        groups().push(group);
+        Some(Self { group })
+    }
+
+    /// Tracks the tree of a child spawned from a command passed to [`prepare`].
+    ///
+    /// Processes the child starts from now on join its job. A failure only warns,
+    /// since the child is already running.
+    #[cfg(windows)]
+    pub fn adopt(child: &Child) -> Option<Self> {
+        match job::create(child) {
+            Ok(job) => Some(Self { job }),
+            Err(err) => {
+                eprintln!("Warning: --kill-tree is unavailable: {}", err);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub fn adopt(_child: &Child) -> Option<Self> {
+        None
+    }
+}
+
+impl Drop for ProcessTree {
+    #[cfg(unix)]
+    fn drop(&mut self) {
+        let tracked = {
+            let mut groups = groups();
+            groups
+                .iter()
+                .position(|group| *group == self.group)
+                .map(|index| groups.swap_remove(index))
+        };
+        if let Some(group) = tracked {
+            stop_group(group);
+        }
+    }
+
+    #[cfg(windows)]
+    fn drop(&mut self) {
+        // Closing the last handle kills every process still in the job
+        // SAFETY: the handle came from CreateJobObjectW and is closed only here
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(self.job) };
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn drop(&mut self) {}
+}
+
+/// Whether `child` has exited, without reaping it; waits for it when `block` is set.
+///
+/// Until the leader is reaped its pid stays reserved, so the process group it led
+/// cannot be reused by an unrelated one. Drop the [`ProcessTree`] in that window.
+#[cfg(unix)]
+pub fn leader_exited(child: &Child, block: bool) -> std::io::Result<bool> {
+    let flags = libc::WEXITED | libc::WNOWAIT | if block { 0 } else { libc::WNOHANG };
+    loop {
+        // SAFETY: all zeroes is a valid siginfo_t, which waitid fills in on success
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `info` outlives the call; WNOWAIT leaves the child to be reaped later
+        let result = unsafe { libc::waitid(libc::P_PID, child.id(), &mut info, flags) };
+        if result == 0 {
+            // SAFETY: waitid succeeded, so si_pid is set, or left zero under WNOHANG
+            return Ok(unsafe { info.si_pid() } != 0);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Stops every tracked tree; called when local-secrets is interrupted.
+///
+/// On Windows there is nothing to do: the system closes the job handles when
+/// local-secrets exits, which kills the jobs.
+pub fn stop_all() {
+    STOPPING.store(true, Ordering::SeqCst);
+    #[cfg(unix)]
+    for group in std::mem::take(&mut *groups()) {
+        stop_group(group);
+    }
+}
+
+/// Blocks the calling thread for good if [`stop_all`] has run.
+///
+/// A child that died because the handler stopped it must not be reported as a
+/// failure of its own; the handler is about to exit with its interrupt code.
+pub fn defer_to_interrupt() {
+    while STOPPING.load(Ordering::SeqCst) {
+        std::thread::park();
+    }
+}
+
+#[cfg(unix)]
+fn stop_group(group: libc::pid_t) {
+    // SAFETY: killpg has no memory-safety preconditions; it fails harmlessly
+    // once every member of the group has exited
+    unsafe { libc::killpg(group, libc::SIGTERM) };
+}
+
+#[cfg(unix)]
+fn groups() -> MutexGuard<'static, Vec<libc::pid_t>> {
+    // A poisoned lock still holds a valid list; cleanup must not give up on it
+    GROUPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(windows)]
+mod job {
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// Creates a kill-on-close job object holding `child`
+    pub fn create(child: &Child) -> std::io::Result<HANDLE> {
+        // SAFETY: null attributes and name are allowed and create an anonymous job
+        let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if job.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: the structure is plain data for which all zeroes is valid
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        // SAFETY: `limits` outlives the call and the size matches the information class;
+        // the child's handle stays open for as long as `child` exists
+        let assigned = unsafe {
+            SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                std::ptr::addr_of!(limits).cast(),
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ) != 0
+                && AssignProcessToJobObject(job, child.as_raw_handle() as HANDLE) != 0
+        };
+        if !assigned {
+            let err = std::io::Error::last_os_error();
+            // SAFETY: `job` is a valid handle we own and no longer need
+            unsafe { CloseHandle(job) };
+            return Err(err);
+        }
+        Ok(job)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Whether `pid` still runs; a zombie nobody has reaped yet counts as gone
+    fn alive(pid: u32) -> bool {
+        Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid.to_string()])
+            .output()
+            .map(|out| {
+                out.status.success()
+                    && !String::from_utf8_lossy(&out.stdout).trim().starts_with('Z')
+            })
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn dropping_the_tree_stops_grandchildren() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 30 >/dev/null & echo $!"])
+            .stdout(std::process::Stdio::piped());
+        // Under a terminal the child deliberately stays in our group
+        if !prepare(&mut cmd) {
+            return;
+        }
+        let mut child = cmd.spawn().unwrap();
+        let tree = ProcessTree::adopt(&child).unwrap();
+        let group = tree.group;
+        let mut stdout = String::new();
+        std::io::Read::read_to_string(&mut child.stdout.take().unwrap(), &mut stdout).unwrap();
+        let grandchild: u32 = stdout.trim().parse().unwrap();
+        assert!(leader_exited(&child, true).unwrap());
+        assert!(alive(grandchild));
+
+        drop(tree);
+        child.wait().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while alive(grandchild) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!alive(grandchild), "grandchild {} survived", grandchild);
+        assert!(!groups().contains(&group));
+    }
+}
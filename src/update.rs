@@ -0,0 +1,232 @@
+//! `self-update --check`: compares the running version with the latest GitHub release.
+//!
+//! Check and notify only; nothing is downloaded or executed. The request goes
+//! through `curl`, which honours `HTTPS_PROXY`/`ALL_PROXY`, so no TLS stack is
+//! compiled in. Built only with the `self-update` feature (on by default).
+
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/DK26/local-secrets/releases/latest";
+const RELEASES_PAGE: &str = "https://github.com/DK26/local-secrets/releases/latest";
+
+/// The whole check gives up after this many seconds
+const CHECK_TIMEOUT_SECS: &str = "5";
+
+/// Prints whether a newer release exists and how to upgrade this installation.
+///
+/// Being offline is not an error: the check says so in one line and exits 0.
+pub fn check() -> Result<ExitCode> {
+    let current = env!("CARGO_PKG_VERSION");
+    let tag = match latest_tag() {
+        Ok(tag) => tag,
+        Err(err) => {
+            eprintln!("Could not check for updates: {:#}", err);
+            return Ok(ExitCode::SUCCESS);
+        }
+    };
+    let latest = tag.strip_prefix('v').unwrap_or(&tag);
+
+    match (Version::parse(latest), Version::parse(current)) {
+        (Some(newer), Some(running)) if newer > running => {
+            println!(
+                "local-secrets {} is available (you have {}).",
+                latest, current
+            );
+            let exe = std::env::current_exe().unwrap_or_default();
+            println!("Upgrade with: {}", upgrade_hint(&exe));
+        }
+        (Some(_), Some(_)) => println!("local-secrets {} is up to date.", current),
+        _ => eprintln!(
+            "Could not check for updates: unrecognised release tag {}",
+            crate::security::display_name(&tag)
+        ),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn latest_tag() -> Result<String> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--proto",
+            "=https",
+            "--max-time",
+            CHECK_TIMEOUT_SECS,
+            "--max-filesize",
+            "1048576",
+            "--header",
+            "Accept: application/vnd.github+json",
+            "--user-agent",
+            concat!("local-secrets/", env!("CARGO_PKG_VERSION")),
+            LATEST_RELEASE_URL,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!("curl not found on PATH"),
+            _ => anyhow::anyhow!("Failed to run curl: {}", err),
+        })?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let body = String::from_utf8(output.stdout).context("Release response is not UTF-8")?;
+    tag_name(&body).ok_or_else(|| anyhow::anyhow!("Release response has no tag_name"))
+}
+
+/// Pulls `"tag_name": "..."` out of a GitHub release object
+fn tag_name(body: &str) -> Option<String> {
+    let rest = &body[body.find("\"tag_name\"")? + "\"tag_name\"".len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let tag = &rest[..rest.find('"')?];
+    (!tag.is_empty()).then(|| tag.to_string())
+}
+
+/// The upgrade command matching how this binary was installed
+fn upgrade_hint(exe: &Path) -> String {
+    let path = exe
+        .to_string_lossy()
+        .replace('\\', "/")
+        .to_ascii_lowercase();
+    if path.contains("/cellar/") || path.contains("/homebrew/") || path.contains("/linuxbrew/") {
+        "brew upgrade local-secrets".to_string()
+    } else if path.contains("/winget/") {
+        "winget upgrade local-secrets".to_string()
+    } else if path.contains("/.cargo/bin/") {
+        "cargo install --git https://github.com/DK26/local-secrets --force".to_string()
+    } else {
+        format!(
+            "download the release for your platform from {}",
+            RELEASES_PAGE
+        )
+    }
+}
+
+/// Semantic version; build metadata is ignored as semver precedence requires
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    core: [u64; 3],
+    pre: Vec<String>,
+}
+
+impl Version {
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.split('+').next()?;
+        let (core, pre) = match text.split_once('-') {
+            Some((core, pre)) => (core, pre.split('.').map(str::to_string).collect()),
+            None => (text, Vec::new()),
+        };
+        let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+        let core = [parts.next()??, parts.next()??, parts.next()??];
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { core, pre })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.core.cmp(&other.core).then_with(|| {
+            // A release sorts after its pre-releases
+            match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => compare_pre(&self.pre, &other.pre),
+            }
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Numeric identifiers compare as numbers and before alphanumeric ones
+fn compare_pre(left: &[String], right: &[String]) -> Ordering {
+    for (left, right) in left.iter().zip(right) {
+        let ordering = match (left.parse::<u64>(), right.parse::<u64>()) {
+            (Ok(left), Ok(right)) => left.cmp(&right),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => left.cmp(right),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(text: &str) -> Version {
+        Version::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_semver_precedence() {
+        let ordered = [
+            "0.1.0-alpha",
+            "0.1.0-alpha.1",
+            "0.1.0-alpha.beta",
+            "0.1.0-beta.2",
+            "0.1.0-beta.11",
+            "0.1.0-rc.1",
+            "0.1.0",
+            "0.1.1",
+            "0.10.0",
+            "1.0.0+build.5",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(v("1.0.0+a").cmp(&v("1.0.0")), Ordering::Equal);
+        assert!(Version::parse("1.0").is_none());
+        assert!(Version::parse("1.0.0.0").is_none());
+        assert!(Version::parse("v1.0.0").is_none());
+    }
+
+    #[test]
+    fn test_tag_name_from_release_json() {
+        assert_eq!(
+            tag_name("{\"url\":\"x\",\n  \"tag_name\" : \"v0.2.0\",\"name\":\"0.2.0\"}").as_deref(),
+            Some("v0.2.0")
+        );
+        assert_eq!(tag_name("{\"message\":\"Not Found\"}"), None);
+        assert_eq!(tag_name("{\"tag_name\":\"\"}"), None);
+    }
+
+    #[test]
+    fn test_upgrade_hint_follows_the_install_location() {
+        assert_eq!(
+            upgrade_hint(Path::new(
+                "/opt/homebrew/Cellar/local-secrets/0.1.0/bin/local-secrets"
+            )),
+            "brew upgrade local-secrets"
+        );
+        assert_eq!(
+            upgrade_hint(Path::new(
+                r"C:\Users\me\AppData\Local\Microsoft\WinGet\Packages\local-secrets\local-secrets.exe"
+            )),
+            "winget upgrade local-secrets"
+        );
+        assert!(upgrade_hint(Path::new("/home/me/.cargo/bin/local-secrets"))
+            .starts_with("cargo install"));
+        assert!(upgrade_hint(Path::new("/usr/local/bin/local-secrets")).contains(RELEASES_PAGE));
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::process::ExitStatus;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::backend::SecretBackend;
+use crate::process::Waitable;
+use crate::security::{source_spec, split_env_spec};
+
+/// How long a child gets to exit after SIGTERM before it is killed on restart
+pub const STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How often the child's exit status is checked between backend polls
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Why `wait_for_exit_or_change` returned
+pub enum WatchEvent {
+    /// The child exited on its own
+    Exited(ExitStatus),
+    /// The backend now holds a different value for this `--env` argument
+    Changed(String),
+}
+
+/// In-memory fingerprint used to notice changed values without keeping copies.
+///
+/// The hasher is randomly keyed per process, so fingerprints mean nothing
+/// outside of it.
+pub fn fingerprint(secret: &SecretString) -> u64 {
+    static KEYS: OnceLock<RandomState> = OnceLock::new();
+    let mut hasher = KEYS.get_or_init(RandomState::new).build_hasher();
+    hasher.write(secret.expose_secret().as_bytes());
+    hasher.finish()
+}
+
+/// Fingerprint of what the backend currently holds for an `--env` argument
+fn backend_state(backend: &dyn SecretBackend, spec: &str) -> Result<Option<u64>> {
+    let (var, namespace) = split_env_spec(source_spec(spec));
+    let secret = match namespace {
+        Some(namespace) => backend.namespaced(namespace).retrieve(var)?,
+        None => backend.retrieve(var)?,
+    };
+    Ok(secret.as_ref().map(fingerprint))
+}
+
+/// Waits for the child to exit, checking the backend every `interval` for
+/// values that differ from `snapshot` (one entry per `env_vars` item)
+pub fn wait_for_exit_or_change(
+    child: &mut impl Waitable,
+    backend: &dyn SecretBackend,
+    env_vars: &[String],
+    snapshot: &[Option<u64>],
+    interval: Duration,
+) -> Result<WatchEvent> {
+    let mut next_poll = Instant::now() + interval;
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .context("Failed to wait for child process")?
+        {
+            return Ok(WatchEvent::Exited(status));
+        }
+
+        if Instant::now() >= next_poll {
+            for (spec, expected) in env_vars.iter().zip(snapshot) {
+                match backend_state(backend, spec) {
+                    Ok(state) if state != *expected => {
+                        return Ok(WatchEvent::Changed(spec.clone()));
+                    }
+                    Ok(_) => {}
+                    // A flaky keyring must not take the running child down
+                    Err(err) => {
+                        eprintln!("Warning: could not check {} for changes: {:#}", spec, err)
+                    }
+                }
+            }
+            next_poll = Instant::now() + interval;
+        }
+
+        std::thread::sleep(EXIT_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_distinguishes_values() {
+        let a = SecretString::new("value-a".into());
+        let a_again = SecretString::new("value-a".into());
+        let b = SecretString::new("value-b".into());
+        assert_eq!(fingerprint(&a), fingerprint(&a_again));
+        assert_ne!(fingerprint(&a), fingerprint(&b));
+    }
+}
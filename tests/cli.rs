@@ -1,9 +1,9 @@
 use assert_cmd::Command as AssertCommand;
 use predicates::prelude::*;
 use std::error::Error;
+use std::ffi::OsString;
 use std::path::PathBuf;
 use std::process::Command as StdCommand;
-use std::sync::OnceLock;
 
 const BACKEND_ENV: &str = "LOCAL_SECRETS_BACKEND";
 const TEST_MODE_ENV: &str = "LOCAL_SECRETS_TEST_MODE";
@@ -15,49 +15,15 @@ fn target_dir() -> PathBuf {
         .unwrap_or_else(|_| PathBuf::from("target"))
 }
 
-fn env_probe() -> PathBuf {
-    static BIN: OnceLock<PathBuf> = OnceLock::new();
-    BIN.get_or_init(|| {
-        let bin_dir = target_dir().join("test-bin");
-        std::fs::create_dir_all(&bin_dir).expect("create test-bin directory");
-        let bin_path = bin_dir.join(format!("print-env{}", std::env::consts::EXE_SUFFIX));
-        compile_env_probe(&bin_path).expect("compile env probe helper");
-        bin_path
-    })
-    .clone()
-}
-
-fn compile_env_probe(bin_path: &PathBuf) -> Result<(), Box<dyn Error>> {
-    let source = r#"
-        use std::{env, process};
-
-        fn main() {
-            let var = env::args().nth(1).expect("variable name required");
-            match env::var(&var) {
-                Ok(value) => print!("{}", value),
-                Err(_) => {
-                    eprintln!("missing env: {}", var);
-                    process::exit(2);
-                }
-            }
-        }
-    "#;
-
-    let source_path = bin_path.with_extension("rs");
-    std::fs::write(&source_path, source)?;
-
-    let status = StdCommand::new("rustc")
-        .arg("--edition=2021")
-        .arg(&source_path)
-        .arg("-o")
-        .arg(bin_path)
-        .status()?;
-
-    if !status.success() {
-        panic!("failed to compile env probe helper");
-    }
-
-    Ok(())
+/// Command line for the built-in `__selftest echo-env` child, which prints the
+/// given variable and exits 2 when it is missing
+fn env_probe(var: &str) -> Vec<OsString> {
+    vec![
+        assert_cmd::cargo::cargo_bin("local-secrets").into_os_string(),
+        "__selftest".into(),
+        "echo-env".into(),
+        var.into(),
+    ]
 }
 
 fn local_secrets_cmd() -> Result<AssertCommand, Box<dyn Error>> {
@@ -68,8 +34,6 @@ fn local_secrets_cmd() -> Result<AssertCommand, Box<dyn Error>> {
 
 #[test]
 fn store_then_run_injects_secret_from_keyring_backend() -> Result<(), Box<dyn Error>> {
-    let helper = env_probe();
-
     // Use unique variable name to avoid conflicts with keyring
     let test_var = format!(
         "CLI_TEST_GITHUB_PAT_{}",
@@ -98,8 +62,7 @@ fn store_then_run_injects_secret_from_keyring_backend() -> Result<(), Box<dyn Er
     run.env_remove(BACKEND_ENV) // Use default keyring backend
         .env_remove(TEST_SECRET_ENV)
         .args(["--env", &test_var, "--"])
-        .arg(&helper)
-        .arg(&test_var);
+        .args(env_probe(&test_var));
 
     let stderr_pred = predicate::str::contains(format!("Injecting env vars: [\"{}\"]", test_var))
         .and(predicate::str::contains("Enter secret").not());
@@ -119,8 +82,6 @@ fn store_then_run_injects_secret_from_keyring_backend() -> Result<(), Box<dyn Er
 
 #[test]
 fn no_save_missing_requires_secret_each_time() -> Result<(), Box<dyn Error>> {
-    let helper = env_probe();
-
     // Use unique variable name
     let test_var = format!(
         "CLI_TEST_API_KEY_{}",
@@ -135,8 +96,7 @@ fn no_save_missing_requires_secret_each_time() -> Result<(), Box<dyn Error>> {
         .env_remove(BACKEND_ENV) // Use default keyring backend
         .env(TEST_SECRET_ENV, "transient-1")
         .args(["--env", &test_var, "--no-save-missing", "--"])
-        .arg(&helper)
-        .arg(&test_var);
+        .args(env_probe(&test_var));
 
     first
         .assert()
@@ -152,8 +112,7 @@ fn no_save_missing_requires_secret_each_time() -> Result<(), Box<dyn Error>> {
         .env_remove(BACKEND_ENV) // Use default keyring backend
         .env(TEST_SECRET_ENV, "transient-2")
         .args(["--env", &test_var, "--no-save-missing", "--"])
-        .arg(&helper)
-        .arg(&test_var);
+        .args(env_probe(&test_var));
 
     let stderr_pred = predicate::str::contains(format!("Enter secret for missing {}", test_var))
         .and(predicate::str::contains(format!("Stored secret for {}", test_var)).not());
@@ -169,8 +128,6 @@ fn no_save_missing_requires_secret_each_time() -> Result<(), Box<dyn Error>> {
 
 #[test]
 fn delete_removes_secret_from_keyring_backend() -> Result<(), Box<dyn Error>> {
-    let helper = env_probe();
-
     // Use unique variable name
     let test_var = format!(
         "CLI_TEST_CI_PAT_{}",
@@ -209,8 +166,7 @@ fn delete_removes_secret_from_keyring_backend() -> Result<(), Box<dyn Error>> {
     run.env_remove(BACKEND_ENV) // Use default keyring backend
         .env_remove(TEST_SECRET_ENV)
         .args(["--env", &test_var, "--"])
-        .arg(&helper)
-        .arg(&test_var);
+        .args(env_probe(&test_var));
 
     run.assert()
         .failure()
@@ -221,3 +177,2786 @@ fn delete_removes_secret_from_keyring_backend() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn systemd_creds_backend_reads_credentials_directory() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join(format!("test-credentials-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("CLI_TEST_CRED"), "from-systemd\n")?;
+    std::fs::write(dir.join("CLI_TEST_CRED_CRLF"), "spaced value \r\n")?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env("CREDENTIALS_DIRECTORY", &dir)
+        .env_remove(TEST_SECRET_ENV)
+        .args(["--backend", "systemd-creds", "--env", "CLI_TEST_CRED", "--"])
+        .args(env_probe("CLI_TEST_CRED"));
+    run.assert().success().stdout("from-systemd");
+
+    let mut run = local_secrets_cmd()?;
+    run.env("CREDENTIALS_DIRECTORY", &dir)
+        .args([
+            "--backend",
+            "systemd-creds",
+            "--env",
+            "CLI_TEST_CRED_CRLF",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_CRED_CRLF"));
+    run.assert().success().stdout("spaced value ");
+
+    // A credential the unit did not load is simply missing
+    let mut run = local_secrets_cmd()?;
+    run.env("CREDENTIALS_DIRECTORY", &dir)
+        .env_remove(TEST_SECRET_ENV)
+        .args([
+            "--backend",
+            "systemd-creds",
+            "--env",
+            "CLI_TEST_CRED_ABSENT",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_CRED_ABSENT"));
+    run.assert().failure().stderr(predicate::str::contains(
+        "Secret CLI_TEST_CRED_ABSENT not found",
+    ));
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env("CREDENTIALS_DIRECTORY", &dir)
+        .env(TEST_SECRET_ENV, "new-value")
+        .args(["--backend", "systemd-creds", "store", "CLI_TEST_CRED"]);
+    store.assert().failure().stderr(predicate::str::contains(
+        "systemd-creds backend is read-only",
+    ));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("CLI_TEST_CRED"))?,
+        "from-systemd\n"
+    );
+
+    let mut run = local_secrets_cmd()?;
+    run.env_remove("CREDENTIALS_DIRECTORY")
+        .args(["--backend", "systemd-creds", "--env", "CLI_TEST_CRED", "--"])
+        .args(env_probe("CLI_TEST_CRED"));
+    run.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("CREDENTIALS_DIRECTORY is not set"));
+
+    let mut run = local_secrets_cmd()?;
+    run.env("CREDENTIALS_DIRECTORY", dir.join("missing"))
+        .args(["--backend", "systemd-creds", "--env", "CLI_TEST_CRED", "--"])
+        .args(env_probe("CLI_TEST_CRED"));
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("does not exist"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn get_prints_the_raw_value_and_nothing_else() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join(format!("test-get-credentials-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("CLI_TEST_GET"), "get-secret-value")?;
+
+    let mut get = local_secrets_cmd()?;
+    get.env("CREDENTIALS_DIRECTORY", &dir).args([
+        "--backend",
+        "systemd-creds",
+        "get",
+        "CLI_TEST_GET",
+    ]);
+    get.assert().success().stdout("get-secret-value").stderr("");
+
+    let mut get = local_secrets_cmd()?;
+    get.env("CREDENTIALS_DIRECTORY", &dir).args([
+        "--backend",
+        "systemd-creds",
+        "get",
+        "CLI_TEST_GET",
+        "--newline",
+    ]);
+    get.assert().success().stdout("get-secret-value\n");
+
+    let mut get = local_secrets_cmd()?;
+    get.env("CREDENTIALS_DIRECTORY", &dir).args([
+        "--backend",
+        "systemd-creds",
+        "get",
+        "CLI_TEST_GET_MISSING",
+    ]);
+    get.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_GET_MISSING not found",
+        ));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn info_describes_a_secret_without_printing_it() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join(format!("test-info-credentials-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("CLI_TEST_INFO"), "abc")?;
+
+    let mut info = local_secrets_cmd()?;
+    info.env("CREDENTIALS_DIRECTORY", &dir).args([
+        "--backend",
+        "systemd-creds",
+        "info",
+        "CLI_TEST_INFO",
+    ]);
+    info.assert().success().stdout(
+        "variable:    CLI_TEST_INFO\n\
+         backend:     systemd-creds\n\
+         length:      3 characters\n\
+         fingerprint: ba7816bf (SHA-256 prefix)\n",
+    );
+
+    let mut info = local_secrets_cmd()?;
+    info.env("CREDENTIALS_DIRECTORY", &dir).args([
+        "--backend",
+        "systemd-creds",
+        "info",
+        "CLI_TEST_INFO_MISSING",
+    ]);
+    info.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_INFO_MISSING not found",
+        ));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn update_requires_an_existing_secret() -> Result<(), Box<dyn Error>> {
+    let mut update = local_secrets_cmd()?;
+    update
+        .args(["update", "CLI_TEST_UPDATE_MISSING"])
+        .env(TEST_SECRET_ENV, "new-value");
+    update
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_UPDATE_MISSING does not exist; use `store` to create it",
+        ));
+
+    let mut update = local_secrets_cmd()?;
+    update
+        .args(["update", "1BAD"])
+        .env(TEST_SECRET_ENV, "new-value");
+    update
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot start with a number"));
+
+    Ok(())
+}
+
+#[test]
+fn store_if_missing_leaves_existing_secret_alone() -> Result<(), Box<dyn Error>> {
+    // The systemd-creds backend is read-only, so reaching the store would fail
+    let dir = target_dir().join(format!(
+        "test-if-missing-credentials-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("CLI_TEST_IF_MISSING"), "existing-value")?;
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env("CREDENTIALS_DIRECTORY", &dir)
+        .env(TEST_SECRET_ENV, "new-value")
+        .args([
+            "--backend",
+            "systemd-creds",
+            "store",
+            "CLI_TEST_IF_MISSING",
+            "--if-missing",
+        ]);
+    store.assert().success().stdout(predicate::str::contains(
+        "CLI_TEST_IF_MISSING is already stored; left unchanged.",
+    ));
+
+    let mut store = local_secrets_cmd()?;
+    store.env(TEST_SECRET_ENV, "new-value").args([
+        "store",
+        "CLI_TEST_IF_MISSING_NEW",
+        "--if-missing",
+    ]);
+    store.assert().success().stdout(predicate::str::contains(
+        "Stored secret for CLI_TEST_IF_MISSING_NEW.",
+    ));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn empty_test_secret_fails_early_in_store_and_run_mode() -> Result<(), Box<dyn Error>> {
+    let from_env = "Refusing to use an empty secret from LOCAL_SECRETS_TEST_SECRET";
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env(TEST_SECRET_ENV, "")
+        .args(["store", "CLI_TEST_EMPTY_TOKEN"]);
+    store
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(from_env));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "")
+        .args(["--env", "CLI_TEST_EMPTY_TOKEN", "--"])
+        .args(env_probe("CLI_TEST_EMPTY_TOKEN"));
+    run.assert().code(1).stdout("").stderr(
+        predicate::str::contains(from_env).and(predicate::str::contains("Enter secret").not()),
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "test-secret-param")]
+#[test]
+fn test_secret_flag_takes_precedence_over_the_environment() -> Result<(), Box<dyn Error>> {
+    let from_flag = "Refusing to use an empty secret from --test-secret";
+
+    for env in [None, Some(""), Some("env-value")] {
+        let mut store = local_secrets_cmd()?;
+        store.args(["store", "CLI_TEST_PRECEDENCE_TOKEN", "--test-secret", ""]);
+        let mut run = local_secrets_cmd()?;
+        run.args([
+            "--test-secret",
+            "",
+            "--env",
+            "CLI_TEST_PRECEDENCE_TOKEN",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_PRECEDENCE_TOKEN"));
+        for cmd in [&mut store, &mut run] {
+            match env {
+                Some(value) => cmd.env(TEST_SECRET_ENV, value),
+                None => cmd.env_remove(TEST_SECRET_ENV),
+            };
+            cmd.assert()
+                .code(1)
+                .stdout("")
+                .stderr(predicate::str::contains(from_flag));
+        }
+    }
+
+    // A non-empty flag wins even over an empty environment value
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "")
+        .args([
+            "--test-secret",
+            "flag-value",
+            "--env",
+            "CLI_TEST_PRECEDENCE_TOKEN",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_PRECEDENCE_TOKEN"));
+    run.assert().success().stdout("flag-value");
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "env-value")
+        .args([
+            "--test-secret",
+            "flag-value",
+            "--env",
+            "CLI_TEST_PRECEDENCE_TOKEN",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_PRECEDENCE_TOKEN"));
+    run.assert().success().stdout("flag-value");
+
+    let mut store = local_secrets_cmd()?;
+    store.env(TEST_SECRET_ENV, "").args([
+        "store",
+        "CLI_TEST_PRECEDENCE_TOKEN",
+        "--test-secret",
+        "flag-value",
+    ]);
+    store.assert().success();
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn store_from_clipboard_reports_missing_clipboard_on_headless_systems() -> Result<(), Box<dyn Error>>
+{
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove(TEST_SECRET_ENV)
+        .args(["store", "CLI_TEST_CLIPBOARD_TOKEN", "--from-clipboard"]);
+
+    store
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No clipboard available"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn current_uid() -> Result<String, Box<dyn Error>> {
+    let output = StdCommand::new("id").arg("-u").output()?;
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_with_user_drops_privileges_or_explains_why_not() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--user", "nobody", "--", "id", "-u"]);
+
+    if current_uid()? == "0" {
+        let nobody = StdCommand::new("id").args(["-u", "nobody"]).output()?;
+        let nobody_uid = String::from_utf8(nobody.stdout)?.trim().to_string();
+
+        run.assert()
+            .success()
+            .stdout(predicate::str::contains(nobody_uid))
+            .stderr(predicate::str::contains("Dropping privileges for child"));
+    } else {
+        run.assert()
+            .failure()
+            .stderr(predicate::str::contains("require root privileges"));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_with_user_naming_ourselves_runs_without_root() -> Result<(), Box<dyn Error>> {
+    let uid = current_uid()?;
+    let mut run = local_secrets_cmd()?;
+    run.args(["--user", &uid, "--", "id", "-u"]);
+
+    run.assert()
+        .success()
+        .stdout(predicate::str::contains(uid.as_str()));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_with_unknown_user_fails_before_spawning() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "--user",
+        "no-such-user-local-secrets",
+        "--",
+        "echo",
+        "should_not_run",
+    ]);
+
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("should_not_run").not())
+        .stderr(predicate::str::contains(
+            "Unknown user 'no-such-user-local-secrets'",
+        ));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_with_umask_restricts_files_created_by_child() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = target_dir().join("test-umask");
+    std::fs::create_dir_all(&dir)?;
+    let file = dir.join(format!("created-{}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--umask", "077", "--", "touch"]).arg(&file);
+    run.assert().success();
+
+    let mode = std::fs::metadata(&file)?.permissions().mode() & 0o777;
+    std::fs::remove_file(&file)?;
+    assert_eq!(mode, 0o600, "child created file with mode {:o}", mode);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_reports_signal_deaths_as_128_plus_signal() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--", "sh", "-c", "kill -9 $$"]);
+    run.assert().code(137).stderr(predicate::str::contains(
+        "child terminated by signal 9 (SIGKILL)",
+    ));
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--", "sh", "-c", "kill -SEGV $$"]);
+    run.assert()
+        .code(139)
+        .stderr(predicate::str::contains("(SIGSEGV)"));
+
+    // Ordinary exit codes are passed through untouched
+    let mut run = local_secrets_cmd()?;
+    run.args(["--", "sh", "-c", "exit 42"]);
+    run.assert()
+        .code(42)
+        .stderr(predicate::str::contains("terminated by signal").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_timeout_stops_the_child_with_exit_code_124() -> Result<(), Box<dyn Error>> {
+    let started = std::time::Instant::now();
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--timeout", "1s", "--"])
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args(["__selftest", "sleep", "30"]);
+    run.timeout(std::time::Duration::from_secs(20))
+        .assert()
+        .code(124)
+        .stderr(predicate::str::contains(
+            "Timed out after 1s; stopping the child",
+        ));
+    assert!(started.elapsed() < std::time::Duration::from_secs(15));
+
+    // A child that finishes in time keeps its own exit code
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--timeout", "5m", "--"])
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args(["__selftest", "exit", "42"]);
+    run.assert()
+        .code(42)
+        .stderr(predicate::str::contains("Timed out").not());
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--timeout", "1.5m", "--", "true"]);
+    run.assert().code(2).stdout("");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_timeout_kills_a_child_ignoring_sigterm() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "run",
+        "--timeout",
+        "1s",
+        "--kill-after",
+        "1s",
+        "--",
+        "sh",
+        "-c",
+        "trap '' TERM; sleep 30",
+    ]);
+    run.timeout(std::time::Duration::from_secs(20))
+        .assert()
+        .code(124);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_stdin_template_feeds_the_rendered_document() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-stdin-template");
+    std::fs::create_dir_all(&dir)?;
+    let template = dir.join("pgpass.tmpl");
+    std::fs::write(
+        &template,
+        "# pg settings\r\nuser=app\npassword='{{CLI_TEST_TMPL_PASS}}'\n\\{\\{CLI_TEST_TMPL_PASS}} ünïcode \t$HOME `x`\n",
+    )?;
+
+    // The child reads the document up to EOF and never sees our terminal
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, r#"p@ss "w0rd" $x \n"#)
+        .args([
+            "run",
+            "--env",
+            "CLI_TEST_TMPL_PASS",
+            "--no-save-missing",
+            "--stdin-template",
+        ])
+        .arg(&template)
+        .args(["--", "sh", "-c", "cat; echo '<EOF>'"]);
+    run.assert().success().stdout(
+        "# pg settings\r\nuser=app\npassword='p@ss \"w0rd\" $x \\n'\n{{CLI_TEST_TMPL_PASS}} ünïcode \t$HOME `x`\n<EOF>\n",
+    );
+
+    // A child that ignores its stdin is not held up
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "unused")
+        .args([
+            "run",
+            "--env",
+            "CLI_TEST_TMPL_PASS",
+            "--no-save-missing",
+            "--stdin-template",
+        ])
+        .arg(&template)
+        .args(["--", "true"]);
+    run.assert().success();
+
+    let unknown = dir.join("unknown.tmpl");
+    std::fs::write(&unknown, "token={{CLI_TEST_TMPL_OTHER}}\n")?;
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "unused")
+        .args(["run", "--env", "CLI_TEST_TMPL_PASS", "--stdin-template"])
+        .arg(&unknown)
+        .args(["--", "cat"]);
+    run.assert().code(1).stdout("").stderr(
+        predicate::str::contains("{{CLI_TEST_TMPL_OTHER}} in --stdin-template")
+            .and(predicate::str::contains("does not name an --env variable"))
+            .and(predicate::str::contains("Enter secret").not()),
+    );
+
+    // An unterminated placeholder is located, not quoted: the template may hold secrets
+    let unterminated = dir.join("unterminated.tmpl");
+    std::fs::write(
+        &unterminated,
+        "api_key=sk-literal-key\npassword={{CLI_TEST_TMPL_PASS\n",
+    )?;
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "unused")
+        .args(["run", "--env", "CLI_TEST_TMPL_PASS", "--stdin-template"])
+        .arg(&unterminated)
+        .args(["--", "cat"]);
+    run.assert().code(1).stdout("").stderr(
+        predicate::str::contains("unterminated.tmpl at line 2, column 10")
+            .and(predicate::str::contains("sk-literal-key").not()),
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_substitute_args_replaces_placeholders_on_request() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "flag-secret").args([
+        "run",
+        "--env",
+        "CLI_TEST_SUBST_TOKEN",
+        "--no-save-missing",
+        "--substitute-args",
+        "--",
+        "printf",
+        "%s|%s",
+        "--password={{CLI_TEST_SUBST_TOKEN}}",
+        r"\{\{CLI_TEST_SUBST_TOKEN}}",
+    ]);
+    run.assert()
+        .success()
+        .stdout("--password=flag-secret|{{CLI_TEST_SUBST_TOKEN}}")
+        .stderr(predicate::str::contains(
+            "Warning: --substitute-args puts CLI_TEST_SUBST_TOKEN in the command line",
+        ));
+
+    // Without the opt-in, placeholders are ordinary text
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "flag-secret").args([
+        "run",
+        "--env",
+        "CLI_TEST_SUBST_TOKEN",
+        "--no-save-missing",
+        "--",
+        "printf",
+        "%s",
+        "{{CLI_TEST_SUBST_TOKEN}}",
+    ]);
+    run.assert()
+        .success()
+        .stdout("{{CLI_TEST_SUBST_TOKEN}}")
+        .stderr(predicate::str::contains("--substitute-args").not());
+
+    // Only variables listed with --env may be referenced, checked before any lookup
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "flag-secret").args([
+        "run",
+        "--env",
+        "CLI_TEST_SUBST_TOKEN",
+        "--substitute-args",
+        "--",
+        "printf",
+        "%s",
+        "{{CLI_TEST_SUBST_OTHER}}",
+    ]);
+    run.assert().code(1).stdout("").stderr(
+        predicate::str::contains(
+            "{{CLI_TEST_SUBST_OTHER}} in the command does not name an --env variable",
+        )
+        .and(predicate::str::contains("Enter secret").not()),
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_shell_runs_a_script_with_secrets_in_the_environment() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env("SHELL", "/bin/sh")
+        .env(TEST_SECRET_ENV, "shell-secret")
+        .args([
+            "run",
+            "--env",
+            "CLI_TEST_SHELL_TOKEN",
+            "--no-save-missing",
+            "--shell",
+            r#"printf '%s' "$CLI_TEST_SHELL_TOKEN" | tr a-z A-Z; echo " $(echo piped)""#,
+        ]);
+    run.assert().success().stdout("SHELL-SECRET piped\n");
+
+    // The script reaches the shell verbatim: a quoted reference stays a reference
+    let mut run = local_secrets_cmd()?;
+    run.env("SHELL", "/bin/sh")
+        .env(TEST_SECRET_ENV, "shell-secret")
+        .args([
+            "run",
+            "--env",
+            "CLI_TEST_SHELL_TOKEN",
+            "--no-save-missing",
+            "--shell",
+            "printf '%s' '$CLI_TEST_SHELL_TOKEN'",
+        ]);
+    run.assert().success().stdout("$CLI_TEST_SHELL_TOKEN");
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--shell", "true", "--", "true"]);
+    run.assert().code(2).stdout("");
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["run", "--shell"]).arg("x".repeat(40_000));
+    run.assert()
+        .code(1)
+        .stderr(predicate::str::contains("--shell script too long"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_cwd_sets_the_child_working_directory() -> Result<(), Box<dyn Error>> {
+    let base = std::env::current_dir()?.join(target_dir()).join("test-cwd");
+    let dir = base.join("repo root");
+    std::fs::create_dir_all(&dir)?;
+    let script = dir.join("where.sh");
+    std::fs::write(&script, "#!/bin/sh\npwd -P\n")?;
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+    let expected = format!("{}\n", std::fs::canonicalize(&dir)?.display());
+
+    let mut run = local_secrets_cmd()?;
+    run.arg("run")
+        .arg("--cwd")
+        .arg(&dir)
+        .args(["--", "sh", "-c", "pwd -P"]);
+    run.assert().success().stdout(expected.clone());
+
+    // Relative --cwd starts from the invoking directory, and so does $PWD;
+    // a relative program is looked up in the new directory
+    let mut run = local_secrets_cmd()?;
+    run.current_dir(&base).args([
+        "run",
+        "--cwd",
+        "repo root",
+        "--",
+        "sh",
+        "-c",
+        "printf %s \"$PWD\"",
+    ]);
+    run.assert()
+        .success()
+        .stdout(base.join("repo root").display().to_string());
+
+    let mut run = local_secrets_cmd()?;
+    run.current_dir(&base)
+        .args(["run", "--cwd", "repo root", "--", "./where.sh"]);
+    run.assert().success().stdout(expected);
+
+    let missing = base.join("missing");
+    let mut run = local_secrets_cmd()?;
+    run.arg("run")
+        .arg("--cwd")
+        .arg(&missing)
+        .args(["--", "true"]);
+    run.assert()
+        .code(1)
+        .stderr(predicate::str::contains(format!(
+            "--cwd {} does not exist",
+            missing.display()
+        )));
+
+    let mut run = local_secrets_cmd()?;
+    run.arg("run")
+        .arg("--cwd")
+        .arg(&script)
+        .args(["--", "true"]);
+    run.assert()
+        .code(1)
+        .stderr(predicate::str::contains("is not a directory"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_cwd_warns_about_a_world_writable_child_directory() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let base = std::env::current_dir()?
+        .join(target_dir())
+        .join("test-cwd-writable");
+    let shared = base.join("shared");
+    std::fs::create_dir_all(&shared)?;
+    std::fs::set_permissions(&base, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o777))?;
+    let script = shared.join("tool.sh");
+    std::fs::write(&script, "#!/bin/sh\necho ran\n")?;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+
+    // The relative program is found in --cwd, so that is the directory checked
+    let mut run = local_secrets_cmd()?;
+    run.current_dir(&base)
+        .args(["run", "--cwd", "shared", "--", "./tool.sh"]);
+    run.assert()
+        .success()
+        .stdout("ran\n")
+        .stderr(predicate::str::contains(format!(
+            "from world-writable directory {}",
+            shared.display()
+        )));
+
+    // ...and not the directory local-secrets was started from
+    let mut run = local_secrets_cmd()?;
+    run.current_dir(&shared)
+        .arg("run")
+        .arg("--cwd")
+        .arg(&base)
+        .args(["--", "./shared/tool.sh"]);
+    run.assert()
+        .success()
+        .stderr(predicate::str::contains("world-writable").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_applies_resource_limits_to_child() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--limit-nofile", "16", "--", "sh", "-c", "ulimit -n"]);
+    run.assert().success().stdout("16\n");
+
+    // The soft CPU limit delivers SIGXCPU, which the footer attributes to --limit-cpu
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "--limit-cpu",
+        "1s",
+        "--stats",
+        "--",
+        "sh",
+        "-c",
+        "while :; do :; done",
+    ]);
+    run.timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "limit: Child exceeded its CPU time limit (--limit-cpu 1s)",
+        ));
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--limit-mem", "2X", "--", "true"]);
+    run.assert().code(2).stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn verify_child_sha256_pins_the_executable() -> Result<(), Box<dyn Error>> {
+    let zeros = "0".repeat(64);
+    let probe = env_probe("CLI_TEST_PINNED");
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "pinned-secret")
+        .args([
+            "--verify-child-sha256",
+            &zeros,
+            "--env",
+            "CLI_TEST_PINNED",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(&probe);
+    let output = run.output()?;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("does not match --verify-child-sha256")
+            && stderr.contains(&format!("expected: {}", zeros)),
+        "unexpected stderr: {stderr}"
+    );
+    // A mismatch is detected before any secret is resolved
+    assert!(
+        !stderr.contains("Enter secret"),
+        "unexpected stderr: {stderr}"
+    );
+    let actual = stderr
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("actual:"))
+        .map(str::trim)
+        .ok_or("no actual hash reported")?
+        .to_string();
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "pinned-secret")
+        .args([
+            "--verify-child-sha256",
+            &actual.to_uppercase(),
+            "--env",
+            "CLI_TEST_PINNED",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(&probe);
+    run.assert().success().stdout("pinned-secret");
+
+    // Interpreters run a script that the hash would not cover
+    #[cfg(unix)]
+    {
+        let mut run = local_secrets_cmd()?;
+        run.args(["--verify-child-sha256", &zeros, "--", "sh", "-c", "exit 0"]);
+        run.assert().success().stderr(predicate::str::contains(
+            "--verify-child-sha256 skipped: sh is an interpreter",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_warns_about_permissive_umask_when_injecting() -> Result<(), Box<dyn Error>> {
+    // The wrapper itself must inherit the permissive umask, so launch it through a shell
+    let output = StdCommand::new("sh")
+        .arg("-c")
+        .arg(r#"umask 002; exec "$@""#)
+        .arg("sh")
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args([
+            "--env",
+            "CLI_TEST_UMASK_TOKEN",
+            "--no-save-missing",
+            "--",
+            "true",
+        ])
+        .env(TEST_MODE_ENV, "1")
+        .env(TEST_SECRET_ENV, "umask-secret")
+        .output()?;
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("inherited umask 0002 is more permissive than 0022"),
+        "unexpected stderr: {stderr}"
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn login_env_merges_profile_beneath_injected_secrets() -> Result<(), Box<dyn Error>> {
+    let home = target_dir().join("test-login-home");
+    std::fs::create_dir_all(&home)?;
+    std::fs::write(
+        home.join(".profile"),
+        "echo profile noise\nexport LOGIN_ONLY_VAR=from-profile\nexport CLI_TEST_LOGIN_TOKEN=from-profile\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env("HOME", &home)
+        .env("SHELL", "/bin/sh")
+        .env_remove("LOGIN_ONLY_VAR")
+        .env(TEST_SECRET_ENV, "injected-value")
+        .args([
+            "--login-env",
+            "--env",
+            "CLI_TEST_LOGIN_TOKEN",
+            "--no-save-missing",
+            "--",
+            "sh",
+            "-c",
+            "echo \"$LOGIN_ONLY_VAR/$CLI_TEST_LOGIN_TOKEN\"",
+        ]);
+
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("from-profile/injected-value\n"));
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn run_windows_script(file_name: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-scripts");
+    std::fs::create_dir_all(&dir)?;
+    let script = dir.join(file_name);
+    std::fs::write(&script, body)?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "script-secret")
+        .args(["--env", "CLI_TEST_SCRIPT_TOKEN", "--no-save-missing", "--"])
+        .arg(&script)
+        .arg("first arg");
+
+    run.assert()
+        .success()
+        .stdout(predicate::str::contains("script-secret"))
+        .stdout(predicate::str::contains("first arg"));
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[test]
+fn run_executes_powershell_scripts_with_injected_env() -> Result<(), Box<dyn Error>> {
+    run_windows_script(
+        "print-env.ps1",
+        "Write-Output $env:CLI_TEST_SCRIPT_TOKEN\r\nWrite-Output $args[0]\r\n",
+    )
+}
+
+#[cfg(windows)]
+#[test]
+fn run_executes_batch_scripts_with_injected_env() -> Result<(), Box<dyn Error>> {
+    run_windows_script(
+        "print-env.bat",
+        "@echo off\r\necho %CLI_TEST_SCRIPT_TOKEN%\r\necho %~1\r\n",
+    )
+}
+
+#[cfg(windows)]
+#[test]
+fn run_executes_cmd_scripts_with_injected_env() -> Result<(), Box<dyn Error>> {
+    run_windows_script(
+        "print-env.cmd",
+        "@echo off\r\necho %CLI_TEST_SCRIPT_TOKEN%\r\necho %~1\r\n",
+    )
+}
+
+#[test]
+fn selftest_child_reports_exit_codes_through_run_mode() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.arg("--")
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args(["__selftest", "exit", "42"]);
+
+    run.assert().code(42);
+
+    Ok(())
+}
+
+#[test]
+fn run_mask_output_redacts_injected_values() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "mask-me-please")
+        .args(["run", "--mask-output", "--env", "CLI_TEST_MASKED"])
+        .args(["--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_MASKED"));
+    run.assert().success().stdout("***MASKED***");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_mask_output_covers_stderr_and_keeps_exit_code() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "mask-me-please")
+        .args(["run", "--mask-output", "--env", "CLI_TEST_MASKED"])
+        .args(["--no-save-missing", "--", "sh", "-c"])
+        .arg("printf 'config: token=%s\\n' \"$CLI_TEST_MASKED\" >&2; exit 3");
+    run.assert()
+        .code(3)
+        .stderr(predicate::str::contains("config: token=***MASKED***\n"))
+        .stderr(predicate::str::contains("mask-me-please").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_mask_output_passes_a_closed_pipe_on_to_the_child() -> Result<(), Box<dyn Error>> {
+    use std::io::Read;
+
+    let mut child = detached_local_secrets(&[
+        "run",
+        "--mask-output",
+        "--env",
+        "CLI_TEST_MASKED",
+        "--no-save-missing",
+        "--",
+        "yes",
+    ])
+    .env(TEST_SECRET_ENV, "mask-me-please")
+    .spawn()?;
+    let mut stdout = child.stdout.take().ok_or("child stdout was not captured")?;
+    let mut start = [0u8; 4];
+    stdout.read_exact(&mut start)?;
+    assert_eq!(&start, b"y\ny\n");
+
+    // Like `| head -2`: the reader goes away and the child must notice
+    drop(stdout);
+    let exited = exits_soon(&child.id().to_string());
+    if !exited {
+        child.kill()?;
+    }
+    child.wait()?;
+    assert!(exited, "the child kept writing into a closed pipe");
+
+    Ok(())
+}
+
+#[test]
+fn run_subcommand_matches_the_bare_form() -> Result<(), Box<dyn Error>> {
+    for prefix in [&[][..], &["run"][..]] {
+        let mut run = local_secrets_cmd()?;
+        run.args(prefix)
+            .args(["--env", "CLI_TEST_RUN_SPELLING", "--no-save-missing", "--"])
+            .args(env_probe("CLI_TEST_RUN_SPELLING"))
+            .env(TEST_SECRET_ENV, "spelling-value");
+        run.assert().success().stdout("spelling-value");
+
+        let mut run = local_secrets_cmd()?;
+        run.args(prefix)
+            .arg("--")
+            .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+            .args(["__selftest", "exit", "42"]);
+        run.assert().code(42);
+
+        let mut run = local_secrets_cmd()?;
+        run.args(prefix).args(["--env", "CLI_TEST_RUN_SPELLING"]);
+        run.assert().failure().stderr(predicate::str::contains(
+            "No command specified. Provide command arguments after --",
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn store_binary_file_accepts_nul_bytes() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-binary");
+    std::fs::create_dir_all(&dir)?;
+    let key_file = dir.join("key.der");
+    std::fs::write(&key_file, [0x30u8, 0x82, 0x00, 0x00, 0xff, 0x00, 0x7f])?;
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args(["store", "CLI_TEST_BINARY_KEY", "--binary", "--from-file"])
+        .arg(&key_file);
+
+    store.assert().success().stdout(predicate::str::contains(
+        "Stored secret for CLI_TEST_BINARY_KEY",
+    ));
+
+    let mut cleanup = local_secrets_cmd()?;
+    cleanup.args(["delete", "CLI_TEST_BINARY_KEY"]);
+    let _ = cleanup.output(); // Best effort cleanup
+
+    Ok(())
+}
+
+#[test]
+fn store_binary_file_reads_no_further_than_the_limit() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-binary");
+    std::fs::create_dir_all(&dir)?;
+    let huge = dir.join("huge.bin");
+    // Sparse: 10 GB on paper, nothing on disk
+    std::fs::File::create(&huge)?.set_len(10 * 1024 * 1024 * 1024)?;
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .timeout(std::time::Duration::from_secs(30))
+        .args(["store", "CLI_TEST_BINARY_HUGE", "--binary", "--from-file"])
+        .arg(&huge);
+    let result = store.assert();
+    std::fs::remove_file(&huge)?;
+    result
+        .failure()
+        .stderr(predicate::str::contains("Binary secret too large"));
+
+    Ok(())
+}
+
+#[test]
+fn store_from_file_reads_text_and_rejects_empty_files() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join(format!("test-text-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let key_file = dir.join("sa.json");
+    std::fs::write(&key_file, "{\"type\": \"service_account\"}\n")?;
+    let empty = dir.join("empty");
+    std::fs::write(&empty, "\n")?;
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args([
+            "store",
+            "CLI_TEST_TEXT_FILE",
+            "--trim-newline",
+            "--from-file",
+        ])
+        .arg(&key_file);
+    store.assert().success().stdout(predicate::str::contains(
+        "Stored secret for CLI_TEST_TEXT_FILE.",
+    ));
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args([
+            "store",
+            "CLI_TEST_TEXT_FILE",
+            "--trim-newline",
+            "--from-file",
+        ])
+        .arg(&empty);
+    store
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is empty"));
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args(["store", "CLI_TEST_TEXT_FILE", "--from-file"])
+        .arg(dir.join("missing"));
+    store
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to open secret file"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn store_stdin_reads_the_pipe_and_never_prompts() -> Result<(), Box<dyn Error>> {
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args(["store", "CLI_TEST_STDIN", "--stdin"])
+        .write_stdin("piped-secret-value\n");
+    store
+        .assert()
+        .success()
+        .stdout("Stored secret for CLI_TEST_STDIN.\n")
+        .stderr(predicate::str::contains("piped-secret-value").not());
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .args(["store", "CLI_TEST_STDIN", "--stdin"])
+        .write_stdin("\n");
+    store
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Nothing to store: standard input is empty",
+        ))
+        .stderr(predicate::str::contains("Enter secret").not());
+
+    Ok(())
+}
+
+#[test]
+fn store_from_command_captures_stdout_and_aborts_on_failure() -> Result<(), Box<dyn Error>> {
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .env("CLI_TEST_PRODUCED", "produced-token")
+        .args(["store", "CLI_TEST_FROM_COMMAND", "--from-command", "--"])
+        .args(env_probe("CLI_TEST_PRODUCED"));
+    store
+        .assert()
+        .success()
+        .stdout("Stored secret for CLI_TEST_FROM_COMMAND.\n")
+        .stderr(predicate::str::contains("produced-token").not());
+
+    // The producer's own stderr reaches the user before the store is aborted
+    let mut store = local_secrets_cmd()?;
+    store
+        .env_remove(TEST_SECRET_ENV)
+        .env_remove("CLI_TEST_PRODUCED")
+        .args(["store", "CLI_TEST_FROM_COMMAND", "--from-command", "--"])
+        .args(env_probe("CLI_TEST_PRODUCED"));
+    store
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("missing env: CLI_TEST_PRODUCED"))
+        .stderr(predicate::str::contains(
+            "exited with code 2; nothing stored",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn run_injects_binary_envelope_payload_as_base64() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "local-secrets:base64:AAEC/w==")
+        .args(["--env", "CLI_TEST_BINARY_B64", "--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_BINARY_B64"));
+
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("AAEC/w=="));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_decode_to_file_injects_private_path_and_cleans_up() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-binary");
+    std::fs::create_dir_all(&dir)?;
+    let expected = dir.join("expected.bin");
+    std::fs::write(&expected, [0x00u8, 0x01, 0x02, 0xff])?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "local-secrets:base64:AAEC/w==")
+        .env("EXPECTED_FILE", &expected)
+        .args([
+            "--env",
+            "CLI_TEST_BINARY_PATH",
+            "--no-save-missing",
+            "--decode-to-file",
+            "--",
+            "sh",
+            "-c",
+            r#"cmp "$CLI_TEST_BINARY_PATH" "$EXPECTED_FILE" && stat -c %a "$CLI_TEST_BINARY_PATH" 2>/dev/null || stat -f %Lp "$CLI_TEST_BINARY_PATH"; printf '%s' "$CLI_TEST_BINARY_PATH""#,
+        ]);
+
+    let output = run.output()?;
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("600"));
+    let decoded_path = lines.next().ok_or("child did not print the decoded path")?;
+    assert!(
+        !std::path::Path::new(decoded_path).exists(),
+        "decoded file was left behind: {decoded_path}"
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn run_env_file_out_writes_private_file_and_removes_it() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-env-file-out");
+    std::fs::create_dir_all(&dir)?;
+    let env_file = dir.join("child.env");
+    let _ = std::fs::remove_file(&env_file);
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "two words")
+        .arg("--env-file-out")
+        .arg(&env_file)
+        .args([
+            "--env",
+            "CLI_TEST_ENV_FILE",
+            "--no-save-missing",
+            "--",
+            "sh",
+            "-c",
+            r#"cat "$LOCAL_SECRETS_ENV_FILE" && stat -c %a "$LOCAL_SECRETS_ENV_FILE" 2>/dev/null || stat -f %Lp "$LOCAL_SECRETS_ENV_FILE""#,
+        ]);
+
+    let output = run.output()?;
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout, "CLI_TEST_ENV_FILE=\"two words\"\n600\n");
+    assert!(!env_file.exists(), "env file was left behind");
+
+    Ok(())
+}
+
+#[test]
+fn run_env_file_out_supports_json() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-env-file-out");
+    std::fs::create_dir_all(&dir)?;
+    let env_file = dir.join("child.json");
+    let _ = std::fs::remove_file(&env_file);
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "json-value")
+        .arg("--env-file-out")
+        .arg(&env_file)
+        .args([
+            "--env-file-format",
+            "json",
+            "--env",
+            "CLI_TEST_ENV_JSON",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("LOCAL_SECRETS_ENV_FILE"));
+
+    let output = run.output()?;
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        env_file.to_string_lossy()
+    );
+    assert!(!env_file.exists(), "env file was left behind");
+
+    Ok(())
+}
+
+/// Starts local-secrets with `args`, waits for the child to print the path of a
+/// transient file, sends SIGTERM to local-secrets and returns (path, exit code)
+#[cfg(unix)]
+fn terminate_while_child_runs(
+    args: &[OsString],
+    secret: &str,
+) -> Result<(PathBuf, Option<i32>), Box<dyn Error>> {
+    use std::io::BufRead;
+
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .env(TEST_MODE_ENV, "1")
+        .env(TEST_SECRET_ENV, secret)
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    let stdout = child.stdout.take().ok_or("child stdout was not captured")?;
+    let mut line = String::new();
+    std::io::BufReader::new(stdout).read_line(&mut line)?;
+    let path = PathBuf::from(line.trim_end());
+    assert!(path.exists(), "transient file was not created: {line:?}");
+
+    let killed = StdCommand::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()?;
+    assert!(killed.success());
+
+    let status = child.wait()?;
+    Ok((path, status.code()))
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_removes_env_file_out() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-env-file-out");
+    std::fs::create_dir_all(&dir)?;
+    let env_file = dir.join("terminated.env");
+    let _ = std::fs::remove_file(&env_file);
+
+    let mut args: Vec<OsString> = vec!["--env-file-out".into(), env_file.clone().into()];
+    args.extend(
+        [
+            "--env",
+            "CLI_TEST_TERM_ENV_FILE",
+            "--no-save-missing",
+            "--",
+            "sh",
+            "-c",
+            r#"echo "$LOCAL_SECRETS_ENV_FILE"; exec sleep 5"#,
+        ]
+        .map(OsString::from),
+    );
+
+    let (path, code) = terminate_while_child_runs(&args, "term-secret")?;
+    assert_eq!(path, env_file);
+    assert_eq!(code, Some(130));
+    assert!(!env_file.exists(), "env file survived SIGTERM");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_removes_decoded_binary_files() -> Result<(), Box<dyn Error>> {
+    let args = [
+        "--env",
+        "CLI_TEST_TERM_BINARY",
+        "--no-save-missing",
+        "--decode-to-file",
+        "--",
+        "sh",
+        "-c",
+        r#"echo "$CLI_TEST_TERM_BINARY"; exec sleep 5"#,
+    ]
+    .map(OsString::from);
+
+    let (path, code) = terminate_while_child_runs(&args, "local-secrets:base64:AAEC/w==")?;
+    assert_eq!(code, Some(130));
+    assert!(!path.exists(), "decoded file survived SIGTERM");
+
+    Ok(())
+}
+
+/// local-secrets without a controlling terminal, so the child gets a process group
+#[cfg(unix)]
+fn detached_local_secrets(args: &[&str]) -> StdCommand {
+    use std::os::unix::process::CommandExt;
+
+    let mut cmd = StdCommand::new(assert_cmd::cargo::cargo_bin("local-secrets"));
+    cmd.env(TEST_MODE_ENV, "1")
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null());
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    cmd
+}
+
+/// Waits up to five seconds for `pid` to exit; zombies count as exited
+#[cfg(unix)]
+fn exits_soon(pid: &str) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        let running = StdCommand::new("ps")
+            .args(["-o", "stat=", "-p", pid])
+            .output()
+            .map(|out| {
+                out.status.success()
+                    && !String::from_utf8_lossy(&out.stdout).trim().starts_with('Z')
+            })
+            .unwrap_or(false);
+        if !running {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn run_kill_tree_stops_grandchildren() -> Result<(), Box<dyn Error>> {
+    let background = "sleep 30 >/dev/null 2>&1 & echo $!";
+
+    let output = detached_local_secrets(&["run", "--", "sh", "-c", background]).output()?;
+    assert!(output.status.success());
+    let grandchild = String::from_utf8(output.stdout)?.trim().to_string();
+    assert!(
+        exits_soon(&grandchild),
+        "grandchild {} survived",
+        grandchild
+    );
+
+    let output =
+        detached_local_secrets(&["run", "--kill-tree", "false", "--", "sh", "-c", background])
+            .output()?;
+    assert!(output.status.success());
+    let grandchild = String::from_utf8(output.stdout)?.trim().to_string();
+    let survived = !exits_soon(&grandchild);
+    StdCommand::new("kill").arg(&grandchild).status()?;
+    assert!(survived, "--kill-tree false still stopped the grandchild");
+
+    // The bare form keeps its old behavior
+    let output = detached_local_secrets(&["--", "sh", "-c", background]).output()?;
+    assert!(output.status.success());
+    let grandchild = String::from_utf8(output.stdout)?.trim().to_string();
+    let survived = !exits_soon(&grandchild);
+    StdCommand::new("kill").arg(&grandchild).status()?;
+    assert!(survived, "the bare form stopped the grandchild");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_stops_the_whole_child_tree() -> Result<(), Box<dyn Error>> {
+    use std::io::BufRead;
+
+    let mut child = detached_local_secrets(&[
+        "run",
+        "--",
+        "sh",
+        "-c",
+        "sleep 30 >/dev/null 2>&1 & echo $!; wait",
+    ])
+    .spawn()?;
+    let stdout = child.stdout.take().ok_or("child stdout was not captured")?;
+    let mut grandchild = String::new();
+    std::io::BufReader::new(stdout).read_line(&mut grandchild)?;
+    let grandchild = grandchild.trim();
+
+    let killed = StdCommand::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()?;
+    assert!(killed.success());
+    assert_eq!(child.wait()?.code(), Some(130));
+    assert!(
+        exits_soon(grandchild),
+        "grandchild {} survived SIGTERM",
+        grandchild
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_with_stats_prints_footer_without_secrets() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "stats-secret-value")
+        .args([
+            "--stats",
+            "--env",
+            "CLI_TEST_STATS",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_STATS"));
+
+    run.assert()
+        .success()
+        .stdout("stats-secret-value")
+        .stderr(predicate::str::contains("--- local-secrets stats ---"))
+        .stderr(predicate::str::contains(
+            "resolve keyring: 1 lookup(s), 1 missing",
+        ))
+        .stderr(predicate::str::contains("prompts: 1"))
+        .stderr(predicate::str::contains("child wall: "))
+        .stderr(predicate::str::contains("stats-secret-value").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_with_summary_prints_one_greppable_line() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "summary-secret-value")
+        .args([
+            "--summary",
+            "--env",
+            "CLI_TEST_SUMMARY",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_SUMMARY"));
+    run.assert().success().stderr(
+        predicate::str::is_match(
+            r"(?m)^local-secrets: injected=1 missing=0 backend=keyring exit=0 duration=\d+\.\ds$",
+        )?
+        .and(predicate::str::contains("summary-secret-value").not()),
+    );
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--summary", "--"])
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args(["__selftest", "exit", "3"]);
+    run.assert().code(3).stderr(predicate::str::contains(
+        "local-secrets: injected=0 missing=0 backend=keyring exit=3 duration=",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn run_with_missing_cross_namespace_secret_names_variable_and_namespace(
+) -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "must-not-be-used")
+        .args(["--env", "CLI_TEST_SHARED@org", "--"])
+        .args(env_probe("CLI_TEST_SHARED"));
+
+    run.assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_SHARED not found in namespace 'org'",
+        ))
+        .stderr(predicate::str::contains("Enter secret").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_rejects_invalid_namespace_reference() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env", "CLI_TEST_SHARED@bad-name", "--"])
+        .args(env_probe("CLI_TEST_SHARED"));
+
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid namespace in --env"));
+
+    Ok(())
+}
+
+#[test]
+fn run_watch_propagates_exit_code_when_child_exits_on_its_own() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "watched-secret")
+        .args([
+            "--watch",
+            "--watch-interval",
+            "1",
+            "--env",
+            "CLI_TEST_WATCHED",
+            "--no-save-missing",
+            "--",
+        ])
+        .arg(assert_cmd::cargo::cargo_bin("local-secrets"))
+        .args(["__selftest", "exit", "3"]);
+
+    run.assert()
+        .code(3)
+        .stderr(predicate::str::contains("restarting").not());
+
+    Ok(())
+}
+
+/// Directory holding a stand-in `sops` that runs `script` (Unix only)
+#[cfg(unix)]
+fn fake_sops_dir(name: &str, script: &str) -> Result<PathBuf, Box<dyn Error>> {
+    fake_tool_dir(name, "sops", script)
+}
+
+/// Directory holding a stand-in `tool` that runs `script` (Unix only)
+#[cfg(unix)]
+fn fake_tool_dir(name: &str, tool: &str, script: &str) -> Result<PathBuf, Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::current_dir()?.join(target_dir()).join(name);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(tool);
+    std::fs::write(&path, format!("#!/bin/sh\n{script}\n"))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(dir)
+}
+
+#[test]
+fn import_dotenv_file_stores_parsed_entries() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join(format!("test-import-dotenv-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(".env");
+    std::fs::write(
+        &path,
+        "# local settings\n\nexport CLI_TEST_DOTENV_A=alpha\nCLI_TEST_DOTENV_B=\"two words\"\n",
+    )?;
+
+    let mut import = local_secrets_cmd()?;
+    import.arg("import").arg(&path).arg("--dry-run");
+    import
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would store CLI_TEST_DOTENV_A."))
+        .stdout(predicate::str::contains(
+            "Dry run: would import 2 secret(s)",
+        ))
+        .stdout(predicate::str::contains("Stored secret").not())
+        .stdout(predicate::str::contains("alpha").not());
+
+    let mut import = local_secrets_cmd()?;
+    import.arg("import").arg(&path);
+    import
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Stored secret for CLI_TEST_DOTENV_B.",
+        ))
+        .stdout(predicate::str::contains("Imported 2 secret(s)"))
+        .stdout(predicate::str::contains("two words").not());
+
+    std::fs::write(&path, "CLI_TEST_DOTENV_A=alpha\nCLI_TEST_DOTENV_B=\"open\n")?;
+    let mut import = local_secrets_cmd()?;
+    import.arg("import").arg(&path);
+    import
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Stored secret").not())
+        .stderr(predicate::str::contains(
+            "Line 2: CLI_TEST_DOTENV_B has an unterminated quote",
+        ));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn import_sops_stores_every_decrypted_entry() -> Result<(), Box<dyn Error>> {
+    let dir = fake_sops_dir(
+        "test-sops-ok",
+        r#"[ "$1 $2 $3" = "--decrypt --output-type dotenv" ] || exit 9
+printf 'CLI_TEST_SOPS_A=alpha\n# comment\nCLI_TEST_SOPS_B=two words\n'"#,
+    )?;
+
+    let mut import = local_secrets_cmd()?;
+    import
+        .env("PATH", &dir)
+        .args(["import", "--sops", "secrets.enc.yaml"]);
+
+    import
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Stored secret for CLI_TEST_SOPS_A.",
+        ))
+        .stdout(predicate::str::contains(
+            "Stored secret for CLI_TEST_SOPS_B.",
+        ))
+        .stdout(predicate::str::contains("Imported 2 secret(s)"))
+        .stdout(predicate::str::contains("alpha").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn import_sops_rejects_bad_entries_before_storing_anything() -> Result<(), Box<dyn Error>> {
+    let dir = fake_sops_dir(
+        "test-sops-bad",
+        r#"printf 'CLI_TEST_SOPS_OK=fine\nnot-a-valid-name=x\n'"#,
+    )?;
+
+    let mut import = local_secrets_cmd()?;
+    import
+        .env("PATH", &dir)
+        .args(["import", "--sops", "secrets.enc.yaml"]);
+
+    import
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Stored secret").not())
+        .stderr(predicate::str::contains("Line 2"));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn import_sops_stops_reading_endless_output() -> Result<(), Box<dyn Error>> {
+    // Shell builtins only: PATH holds nothing but the fake sops
+    let dir = fake_sops_dir(
+        "test-sops-endless",
+        "while :; do echo CLI_TEST_SOPS_FLOOD=0123456789abcdef0123456789abcdef; done",
+    )?;
+
+    let mut import = local_secrets_cmd()?;
+    import
+        .env("PATH", &dir)
+        .timeout(std::time::Duration::from_secs(60))
+        .args(["import", "--sops", "secrets.enc.yaml"]);
+
+    import
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Stored secret").not())
+        .stderr(predicate::str::contains(
+            "is too large (max 16777216 bytes)",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn import_sops_without_sops_binary_is_actionable() -> Result<(), Box<dyn Error>> {
+    let empty = target_dir().join("test-sops-missing");
+    std::fs::create_dir_all(&empty)?;
+
+    let mut import = local_secrets_cmd()?;
+    import
+        .env("PATH", &empty)
+        .args(["import", "--sops", "secrets.enc.yaml"]);
+
+    import
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("sops not found on PATH"));
+
+    Ok(())
+}
+
+#[test]
+fn verify_reports_missing_secret_without_running_probe() -> Result<(), Box<dyn Error>> {
+    let mut verify = local_secrets_cmd()?;
+    verify.args([
+        "verify",
+        "CLI_TEST_VERIFY_MISSING",
+        "--probe",
+        "definitely-not-a-real-probe-binary",
+    ]);
+
+    verify.assert().failure().stderr(predicate::str::contains(
+        "Secret CLI_TEST_VERIFY_MISSING not found",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_rejects_malformed_probe() -> Result<(), Box<dyn Error>> {
+    let mut verify = local_secrets_cmd()?;
+    verify.args(["verify", "CLI_TEST_VERIFY", "--probe", "curl 'unterminated"]);
+
+    verify
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --probe command"));
+
+    Ok(())
+}
+
+#[test]
+fn errors_json_reports_stable_code_and_exit_code() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--errors-json", "--env", "CLI_TEST_ERRORS_JSON", "--"])
+        .args(env_probe("CLI_TEST_ERRORS_JSON"));
+
+    run.assert().code(3).stderr(predicate::str::ends_with(
+        "{\"code\":\"secret_not_found\",\"message\":\"Secret CLI_TEST_ERRORS_JSON not found\",\
+         \"variable\":\"CLI_TEST_ERRORS_JSON\",\"backend\":\"keyring\",\"exit_code\":3}\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn errors_json_classifies_invalid_input() -> Result<(), Box<dyn Error>> {
+    let mut delete = local_secrets_cmd()?;
+    delete.args(["delete", "BAD-NAME", "--errors-json"]);
+
+    delete
+        .assert()
+        .code(2)
+        .stderr(predicate::str::starts_with("{\"code\":\"invalid_input\""))
+        .stderr(predicate::str::contains("\"variable\":\"BAD-NAME\""));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_names_are_echoed_without_bidi_overrides() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env", "VAR\u{202E}KCATTA", "--"])
+        .args(env_probe("VAR"));
+
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("VAR<U+202E>KCATTA"))
+        .stderr(predicate::str::contains('\u{202E}').not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn isolate_tmp_sets_private_dir_and_removes_it() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "--isolate-tmp",
+        "--",
+        "sh",
+        "-c",
+        "echo scratch > \"$TMPDIR/f\" && [ \"$TEMP\" = \"$TMPDIR\" ] && [ \"$TMP\" = \"$TMPDIR\" ] && echo \"$TMPDIR\"",
+    ]);
+
+    let output = run.assert().success().get_output().stdout.clone();
+    let dir = String::from_utf8(output)?;
+    let dir = std::path::Path::new(dir.trim());
+    assert!(dir.starts_with(std::env::temp_dir()));
+    assert!(!dir.exists(), "{} was left behind", dir.display());
+
+    Ok(())
+}
+
+#[test]
+fn derive_injects_hkdf_key_instead_of_master() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "master-secret")
+        .args([
+            "--env",
+            "CLI_TEST_SERVICE_KEY=derive:CLI_TEST_DERIVE_MASTER:service-a",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_SERVICE_KEY"));
+
+    run.assert().success().stdout(predicate::str::diff(
+        "004deeb3dee1f3131df43ec367325999d398ae0c802b10ded81d2aa59ffaef6b",
+    ));
+
+    // The master itself is never injected
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "master-secret")
+        .args([
+            "--env",
+            "CLI_TEST_SERVICE_KEY=derive:CLI_TEST_DERIVE_MASTER:service-a",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_DERIVE_MASTER"));
+    run.assert().failure();
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn helm_args_resolves_every_secret_before_running_helm() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::current_dir()?
+        .join(target_dir())
+        .join("test-helm-missing");
+    std::fs::create_dir_all(&dir)?;
+    let marker = dir.join("helm-ran");
+    let _ = std::fs::remove_file(&marker);
+    let helm = dir.join("helm");
+    std::fs::write(&helm, format!("#!/bin/sh\ntouch '{}'\n", marker.display()))?;
+    std::fs::set_permissions(&helm, std::fs::Permissions::from_mode(0o755))?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env("PATH", &dir).args([
+        "helm-args",
+        "--map",
+        "db.password=CLI_TEST_HELM_MISSING",
+        "--",
+        "upgrade",
+        "release",
+        "chart",
+    ]);
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("CLI_TEST_HELM_MISSING not found"));
+    assert!(!marker.exists(), "helm ran despite a missing secret");
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["helm-args", "--map", "db[0]=X", "--", "upgrade"]);
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid Helm values path"));
+
+    Ok(())
+}
+
+#[test]
+fn history_check_reports_inline_values_elided() -> Result<(), Box<dyn Error>> {
+    let dir = target_dir().join("test-history");
+    std::fs::create_dir_all(&dir)?;
+    let history = dir.join(".bash_history");
+    std::fs::write(
+        &history,
+        "git status\nlocal-secrets store API_KEY --test-secret hunter2hunter2\n",
+    )?;
+
+    let mut check = local_secrets_cmd()?;
+    check.args(["history-check", "--file"]).arg(&history);
+    check
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            ":2: local-secrets store API_KEY --test-secret ***",
+        ))
+        .stdout(predicate::str::contains("history -d"))
+        .stdout(predicate::str::contains("hunter2").not());
+
+    std::fs::write(&history, "git status\n")?;
+    let mut check = local_secrets_cmd()?;
+    check.args(["history-check", "--file"]).arg(&history);
+    check.assert().success().stdout(predicate::str::contains(
+        "No secrets found in 1 history file(s).",
+    ));
+
+    // Minimal containers: no HOME means nowhere to look, which is not a clean result
+    let mut homeless = local_secrets_cmd()?;
+    for name in ["HISTFILE", "HOME", "USERPROFILE", "APPDATA"] {
+        homeless.env_remove(name);
+    }
+    homeless.arg("history-check");
+    homeless.assert().failure().stderr(predicate::str::contains(
+        "Cannot locate shell history: none of HISTFILE, HOME, USERPROFILE, APPDATA is set; pass --file PATH",
+    ));
+
+    Ok(())
+}
+
+fn write_map_file(name: &str, contents: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = target_dir().join("test-map-files");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(name);
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+#[test]
+fn map_file_injects_aliases_and_skips_missing_optional() -> Result<(), Box<dyn Error>> {
+    let path = write_map_file(
+        "aliases.toml",
+        "optional = [\"CLI_TEST_MAP_OPTIONAL\"]\n\n[map]\nCLI_TEST_MAP_TARGET = \"CLI_TEST_MAP_SOURCE\"\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "aliased-value")
+        .arg("--map-file")
+        .arg(&path)
+        .args(["--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_MAP_TARGET"));
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("aliased-value"));
+
+    let path = write_map_file(
+        "optional.toml",
+        "optional = [\"CLI_TEST_MAP_OPTIONAL\"]\n\n[map]\nCLI_TEST_MAP_OPTIONAL = \"CLI_TEST_MAP_ABSENT\"\n",
+    )?;
+    let mut run = local_secrets_cmd()?;
+    run.arg("--map-file")
+        .arg(&path)
+        .args(["--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_MAP_OPTIONAL"));
+    run.assert().code(2).stderr(predicate::str::contains(
+        "Skipping optional CLI_TEST_MAP_OPTIONAL",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn map_file_dry_run_shows_resolved_table_with_cli_winning() -> Result<(), Box<dyn Error>> {
+    let path = write_map_file(
+        "dry-run.toml",
+        "[map]\nGH_TOKEN = \"GITHUB_PAT\"\nAPI_KEY = \"IGNORED\"\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env", "API_KEY=OTHER_KEY", "--dry-run", "--map-file"])
+        .arg(&path);
+    run.assert().success().stdout(predicate::str::diff(
+        "API_KEY   <- OTHER_KEY  (--env)\nGH_TOKEN  <- GITHUB_PAT  (map file)\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn map_file_lists_every_invalid_entry() -> Result<(), Box<dyn Error>> {
+    let path = write_map_file(
+        "invalid.toml",
+        "[map]\n\"1BAD\" = \"SOURCE\"\nOK = \"SOURCE\"\nNUMBER = 5\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.arg("--map-file").arg(&path).args(["--", "true"]);
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("2 invalid entries"))
+        .stderr(predicate::str::contains("Line 2: Invalid target name 1BAD"))
+        .stderr(predicate::str::contains("Line 4: NUMBER"));
+
+    Ok(())
+}
+
+#[test]
+fn blank_secrets_warn_by_default_and_fail_with_fail_on_empty() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "undefined")
+        .args(["--env", "CLI_TEST_BLANK", "--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_BLANK"));
+    run.assert().success().stderr(predicate::str::contains(
+        "Warning: secret CLI_TEST_BLANK looks blank (literal null/undefined/None)",
+    ));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "  \"\" ")
+        .args([
+            "--fail-on-empty",
+            "--env",
+            "CLI_TEST_BLANK",
+            "--no-save-missing",
+            "--",
+        ])
+        .args(env_probe("CLI_TEST_BLANK"));
+    run.assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_BLANK looks blank (only quote characters)",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn where_reports_location_without_value() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["where", "CLI_TEST_WHERE@team"]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("service:  local-secrets:team"))
+        .stdout(predicate::str::contains("account:  CLI_TEST_WHERE"))
+        .stdout(predicate::str::contains("exists:   no"));
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["where", "--json", "CLI_TEST_WHERE"]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::starts_with(
+            r#"{"backend":"keyring","service":"local-secrets","account":"CLI_TEST_WHERE","store":"#,
+        ))
+        .stdout(predicate::str::ends_with(
+            "\"exists\":false,\"attributes\":{}}\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn namespace_flag_and_env_select_the_keyring_service() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.env_remove("LOCAL_SECRETS_NAMESPACE").args([
+        "--namespace",
+        "projA",
+        "where",
+        "CLI_TEST_NAMESPACED",
+    ]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("service:  local-secrets:projA"));
+
+    // The flag wins over the environment
+    let mut run = local_secrets_cmd()?;
+    run.env("LOCAL_SECRETS_NAMESPACE", "projB")
+        .args(["where", "CLI_TEST_NAMESPACED"]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("service:  local-secrets:projB"));
+    let mut run = local_secrets_cmd()?;
+    run.env("LOCAL_SECRETS_NAMESPACE", "projB").args([
+        "where",
+        "CLI_TEST_NAMESPACED",
+        "--namespace",
+        "projA",
+    ]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("service:  local-secrets:projA"));
+
+    let mut run = local_secrets_cmd()?;
+    run.env("LOCAL_SECRETS_NAMESPACE", "proj-a")
+        .args(["--env", "CLI_TEST_NAMESPACED", "--"])
+        .args(env_probe("CLI_TEST_NAMESPACED"));
+    run.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Namespace contains invalid characters",
+        ));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn env_diff_reports_echoed_and_behaviour_changing_variables() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "env-diff",
+        "--candidate",
+        "CLI_TEST_DIFF_ECHOED",
+        "--candidate",
+        "CLI_TEST_DIFF_UNUSED",
+        "--candidate",
+        "CLI_TEST_DIFF_FLAG",
+        "--",
+        "sh",
+        "-c",
+        "echo \"token=$CLI_TEST_DIFF_ECHOED\"; [ -n \"$CLI_TEST_DIFF_FLAG\" ] && echo verbose; exit 0",
+    ]);
+
+    run.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "CLI_TEST_DIFF_ECHOED  canary echoed in output",
+        ))
+        .stdout(predicate::str::contains(
+            "CLI_TEST_DIFF_FLAG    output changes when set",
+        ))
+        .stdout(predicate::str::contains("CLI_TEST_DIFF_UNUSED").not())
+        .stdout(predicate::str::ends_with(
+            "env = [\"CLI_TEST_DIFF_ECHOED\", \"CLI_TEST_DIFF_FLAG\"]\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn run_env_file_merges_listed_variables_beneath_env() -> Result<(), Box<dyn Error>> {
+    let path = write_map_file(
+        "env-list.txt",
+        "# project secrets\nCLI_TEST_LISTED_A\nCLI_TEST_LISTED_B = CLI_TEST_LISTED_SOURCE\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "run",
+        "--env",
+        "CLI_TEST_LISTED_A",
+        "--dry-run",
+        "--env-file",
+    ])
+    .arg(&path);
+    run.assert().success().stdout(predicate::str::diff(
+        "CLI_TEST_LISTED_A  <- CLI_TEST_LISTED_A  (--env)\nCLI_TEST_LISTED_B  <- CLI_TEST_LISTED_SOURCE  (env file)\n",
+    ));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "listed-value")
+        .args(["run", "--env-file"])
+        .arg(&path)
+        .args(["--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_LISTED_B"));
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("listed-value"));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "listed-value")
+        .args(["run", "--env", "CLI_TEST_LISTED_A"])
+        .args(["--env-file", "does-not-exist.txt", "--"])
+        .args(env_probe("CLI_TEST_LISTED_A"));
+    run.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Failed to open env list file does-not-exist.txt",
+        ))
+        .stderr(predicate::str::contains("Injecting").not());
+
+    Ok(())
+}
+
+#[test]
+fn env_required_from_adds_names_and_reports_bad_lines() -> Result<(), Box<dyn Error>> {
+    let path = write_map_file(
+        "required-secrets.txt",
+        "# every environment provides these\nCLI_TEST_REQUIRED_A\nCLI_TEST_REQUIRED_B # ci\n",
+    )?;
+
+    let mut run = local_secrets_cmd()?;
+    run.args([
+        "--env",
+        "CLI_TEST_REQUIRED_A",
+        "--dry-run",
+        "--env-required-from",
+    ])
+    .arg(&path);
+    run.assert().success().stdout(predicate::str::diff(
+        "CLI_TEST_REQUIRED_A  <- CLI_TEST_REQUIRED_A  (--env)\nCLI_TEST_REQUIRED_B  <- CLI_TEST_REQUIRED_B  (required file)\n",
+    ));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "required-value")
+        .arg("--env-required-from")
+        .arg(&path)
+        .args(["--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_REQUIRED_B"));
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("required-value"));
+
+    let bad = write_map_file("required-bad.txt", "GOOD\n\n$(oops)\n")?;
+    let mut run = local_secrets_cmd()?;
+    run.arg("--env-required-from")
+        .arg(&bad)
+        .args(["--", "true"]);
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("Line 3:"));
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env-required-from", "does-not-exist.txt", "--", "true"]);
+    run.assert().failure().stderr(predicate::str::contains(
+        "Failed to open required secrets file does-not-exist.txt",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn bang_suffix_skips_saving_only_that_variable() -> Result<(), Box<dyn Error>> {
+    let suffix = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let keep = format!("CLI_TEST_KEEP_{}", suffix);
+    let once = format!("CLI_TEST_ONCE_{}", suffix);
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "prompted-value")
+        .args(["--env", &keep, "--env", &format!("{}!", once), "--"])
+        .args(env_probe(&once));
+    run.assert()
+        .success()
+        .stdout(predicate::str::diff("prompted-value"))
+        .stderr(predicate::str::contains(format!(
+            "Stored secret for {}.",
+            keep
+        )))
+        .stderr(predicate::str::contains(format!("Stored secret for {}", once)).not())
+        .stderr(predicate::str::contains(format!(
+            "Not saved (marked no-save): {}",
+            once
+        )));
+
+    // Leave no test entry behind in a real keyring
+    let mut delete = local_secrets_cmd()?;
+    delete.arg("delete").arg(&keep);
+    let _ = delete.output();
+
+    Ok(())
+}
+
+#[test]
+fn debug_entry_requires_acknowledgment_and_reports_each_step() -> Result<(), Box<dyn Error>> {
+    let mut refused = local_secrets_cmd()?;
+    refused.args(["debug", "entry", "CLI_TEST_DEBUG_ENTRY"]);
+    refused
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass --debug to acknowledge"));
+
+    let mut run = local_secrets_cmd()?;
+    run.env_remove(BACKEND_ENV)
+        .args(["debug", "entry", "CLI_TEST_DEBUG_ENTRY@debugns", "--debug"]);
+    run.assert()
+        .failure()
+        .stdout(predicate::str::contains("service:  local-secrets:debugns"))
+        .stdout(predicate::str::contains("account:  CLI_TEST_DEBUG_ENTRY"))
+        .stdout(predicate::str::contains("exists           ok"))
+        .stdout(predicate::str::contains("retrieve-length  error"))
+        .stdout(predicate::str::contains("NoEntry"));
+
+    Ok(())
+}
+
+#[test]
+fn commands_from_stdin_run_in_order_and_stop_on_failure() -> Result<(), Box<dyn Error>> {
+    let bin = assert_cmd::cargo::cargo_bin("local-secrets");
+    let bin = bin.to_string_lossy();
+    let list = format!(
+        "# generated\n'{bin}' __selftest echo-env CLI_TEST_BATCH_TOKEN\n\n'{bin}' __selftest exit 3\n'{bin}' __selftest echo-env CLI_TEST_BATCH_TOKEN\n"
+    );
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "batch-value")
+        .args([
+            "--env",
+            "CLI_TEST_BATCH_TOKEN",
+            "--no-save-missing",
+            "--commands",
+            "-",
+        ])
+        .write_stdin(list.clone());
+    run.assert()
+        .code(3)
+        .stdout(predicate::str::diff("batch-value"))
+        .stderr(predicate::str::contains(
+            "Command on line 4 failed with exit code 3",
+        ));
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "batch-value")
+        .args([
+            "--env",
+            "CLI_TEST_BATCH_TOKEN",
+            "--no-save-missing",
+            "--commands",
+            "-",
+            "--keep-going",
+        ])
+        .write_stdin(list);
+    run.assert()
+        .code(3)
+        .stdout(predicate::str::diff("batch-valuebatch-value"));
+
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env", "CLI_TEST_BATCH_TOKEN", "--commands", "-"])
+        .write_stdin("ok\n'unterminated\n");
+    run.assert()
+        .failure()
+        .stderr(predicate::str::contains("Line 2: Invalid quoting"));
+
+    Ok(())
+}
+
+#[cfg(feature = "otel")]
+#[test]
+fn otel_spans_reach_an_in_process_collector_without_values() -> Result<(), Box<dyn Error>> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let endpoint = format!("http://{}", listener.local_addr()?);
+    let collector = std::thread::spawn(move || -> std::io::Result<String> {
+        let (mut stream, _) = listener.accept()?;
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        // The exporter closes its side only after reading the response, so stop at the body end
+        loop {
+            let read = stream.read(&mut buf)?;
+            request.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if read == 0 || text.ends_with("]}]}]}") {
+                break;
+            }
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")?;
+        Ok(String::from_utf8_lossy(&request).into_owned())
+    });
+
+    let mut run = local_secrets_cmd()?;
+    run.env(TEST_SECRET_ENV, "otel-secret-value")
+        .args(["--otel-endpoint", &endpoint])
+        .args(["--env", "CLI_TEST_OTEL_TOKEN", "--no-save-missing", "--"])
+        .args(env_probe("CLI_TEST_OTEL_TOKEN"));
+    run.assert().success();
+
+    let request = collector.join().map_err(|_| "collector panicked")??;
+    assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+    for expected in [
+        "\"name\":\"validate\"",
+        "\"name\":\"resolve\"",
+        "{\"key\":\"variable\",\"value\":{\"stringValue\":\"CLI_TEST_OTEL_TOKEN\"}}",
+        "\"name\":\"child.spawn\"",
+        "\"name\":\"child.wait\"",
+        "{\"key\":\"exit_code\",\"value\":{\"intValue\":\"0\"}}",
+    ] {
+        assert!(
+            request.contains(expected),
+            "{} missing from {}",
+            expected,
+            request
+        );
+    }
+    assert!(!request.contains("otel-secret-value"));
+
+    Ok(())
+}
+
+#[test]
+fn unexpanded_shell_references_suggest_the_plain_name() -> Result<(), Box<dyn Error>> {
+    let mut run = local_secrets_cmd()?;
+    run.args(["--env", "%GITHUB_TOKEN%", "--", "true"]);
+    run.assert().failure().stderr(predicate::str::contains(
+        "did not expand %GITHUB_TOKEN% (cmd.exe syntax); pass the variable name GITHUB_TOKEN",
+    ));
+
+    let mut store = local_secrets_cmd()?;
+    store.args(["store", "${GITHUB_TOKEN}"]);
+    store.assert().failure().stderr(predicate::str::contains(
+        "(POSIX shell syntax); pass the variable name GITHUB_TOKEN",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn ide_resolve_requires_acknowledgment_before_reading_secrets() -> Result<(), Box<dyn Error>> {
+    let mut refused = local_secrets_cmd()?;
+    refused.args([
+        "ide-resolve",
+        "CLI_TEST_IDE_TOKEN",
+        "--format",
+        "vscode-env",
+    ]);
+    refused
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "pass --unsafe-plain-output to acknowledge",
+        ));
+
+    let mut missing = local_secrets_cmd()?;
+    missing.env_remove(BACKEND_ENV).args([
+        "ide-resolve",
+        "CLI_TEST_IDE_TOKEN",
+        "--format",
+        "jetbrains-xml",
+        "--unsafe-plain-output",
+    ]);
+    missing
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_IDE_TOKEN not found",
+        ));
+
+    let path = write_map_file(
+        "ide.toml",
+        "optional = [\"CLI_TEST_IDE_OPTIONAL\"]\n\n[map]\nCLI_TEST_IDE_OPTIONAL = \"CLI_TEST_IDE_SOURCE\"\n",
+    )?;
+    let mut optional = local_secrets_cmd()?;
+    optional.env_remove(BACKEND_ENV).args([
+        "ide-resolve",
+        "--map-file",
+        path.to_str().ok_or("non-UTF-8 path")?,
+        "--format",
+        "vscode-env",
+        "--unsafe-plain-output",
+    ]);
+    optional
+        .assert()
+        .success()
+        .stdout("{}\n")
+        .stderr(predicate::str::contains(
+            "printed 0 secret values in plain text",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn critical_variable_warning_follows_the_output_mode_and_skips_delete() -> Result<(), Box<dyn Error>>
+{
+    let mut json = local_secrets_cmd()?;
+    json.env(TEST_SECRET_ENV, "/opt/tools/bin")
+        .args(["--errors-json", "--env", "PATH", "--no-save-missing", "--"])
+        .args(env_probe("PATH"));
+    json.assert()
+        .success()
+        .stdout("/opt/tools/bin")
+        .stderr(predicate::str::contains(
+            r#"{"warning":"critical_variable","message":"Overriding critical system variable 'PATH' - this may cause unexpected behavior","variable":"PATH"}"#,
+        ))
+        .stderr(predicate::str::contains("Warning:").not());
+
+    let mut text = local_secrets_cmd()?;
+    text.env(TEST_SECRET_ENV, "/opt/tools/bin")
+        .args(["--env", "PATH", "--no-save-missing", "--"])
+        .args(env_probe("PATH"));
+    text.assert().success().stderr(predicate::str::contains(
+        "Warning: Overriding critical system variable 'PATH'",
+    ));
+
+    let mut delete = local_secrets_cmd()?;
+    delete.args(["delete", "PATH"]);
+    delete
+        .assert()
+        .stderr(predicate::str::contains("critical system variable").not());
+
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "self-update"))]
+#[test]
+fn self_update_check_reports_newer_release_and_stays_quiet_offline() -> Result<(), Box<dyn Error>> {
+    let newer = fake_tool_dir(
+        "test-curl-newer",
+        "curl",
+        r#"case "$*" in *api.github.com/repos/DK26/local-secrets/releases/latest*) ;; *) exit 9 ;; esac
+printf '{"tag_name": "v99.0.0", "name": "99.0.0"}'"#,
+    )?;
+    let mut check = local_secrets_cmd()?;
+    check.env("PATH", &newer).args(["self-update", "--check"]);
+    check
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "local-secrets 99.0.0 is available (you have",
+        ))
+        .stdout(predicate::str::contains("Upgrade with: "));
+
+    let offline = fake_tool_dir(
+        "test-curl-offline",
+        "curl",
+        "echo 'curl: (6) Could not resolve host: api.github.com' >&2; exit 6",
+    )?;
+    let mut check = local_secrets_cmd()?;
+    check.env("PATH", &offline).args(["self-update", "--check"]);
+    check
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr("Could not check for updates: curl: (6) Could not resolve host: api.github.com\n");
+
+    let mut bare = local_secrets_cmd()?;
+    bare.arg("self-update");
+    bare.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn rotate_fails_for_missing_secret_and_keeps_store_untouched() -> Result<(), Box<dyn Error>> {
+    let mut rotate = local_secrets_cmd()?;
+    rotate
+        .args(["rotate", "CLI_TEST_ROTATE_MISSING"])
+        .env(TEST_SECRET_ENV, "new-value");
+
+    rotate
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_ROTATE_MISSING not found",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn rotate_rejects_malformed_exec() -> Result<(), Box<dyn Error>> {
+    let mut rotate = local_secrets_cmd()?;
+    rotate
+        .args(["rotate", "CLI_TEST_ROTATE", "--exec", "curl 'unterminated"])
+        .env(TEST_SECRET_ENV, "new-value");
+
+    rotate
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --exec command"));
+
+    Ok(())
+}
+
+#[test]
+fn store_accepts_several_variables() -> Result<(), Box<dyn Error>> {
+    let mut store = local_secrets_cmd()?;
+    store
+        .args(["store", "CLI_TEST_MANY_A", "CLI_TEST_MANY_B"])
+        .env(TEST_SECRET_ENV, "shared-value");
+    store.assert().success().stdout(predicate::str::contains(
+        "Stored secrets for CLI_TEST_MANY_A, CLI_TEST_MANY_B.",
+    ));
+
+    let mut store = local_secrets_cmd()?;
+    store
+        .args([
+            "store",
+            "CLI_TEST_MANY_A",
+            "CLI_TEST_MANY_B",
+            "--from-clipboard",
+        ])
+        .env(TEST_SECRET_ENV, "shared-value");
+    store
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "--from-clipboard, --from-file, --stdin and --from-command store a single variable",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn delete_accepts_several_variables() -> Result<(), Box<dyn Error>> {
+    let mut delete = local_secrets_cmd()?;
+    delete.args(["delete", "CLI_TEST_DELETE_A", "CLI_TEST_DELETE_B"]);
+    delete.assert().failure().stderr(predicate::str::contains(
+        "Secret CLI_TEST_DELETE_A not found.\nSecret CLI_TEST_DELETE_B not found.\n",
+    ));
+
+    let mut delete = local_secrets_cmd()?;
+    delete.args([
+        "delete",
+        "CLI_TEST_DELETE_A",
+        "CLI_TEST_DELETE_B",
+        "--ignore-missing",
+    ]);
+    delete.assert().success();
+
+    let mut delete = local_secrets_cmd()?;
+    delete.args(["delete", "CLI_TEST_DELETE_A", "BAD-NAME"]);
+    delete
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found").not());
+
+    Ok(())
+}
+
+#[test]
+fn edit_refuses_to_create_a_secret() -> Result<(), Box<dyn Error>> {
+    let mut edit = local_secrets_cmd()?;
+    edit.args(["edit", "CLI_TEST_EDIT_MISSING"])
+        .env(TEST_SECRET_ENV, "new-value");
+
+    edit.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Secret CLI_TEST_EDIT_MISSING does not exist; use `store` to create it",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn edit_shows_the_current_fingerprint_before_storing() -> Result<(), Box<dyn Error>> {
+    // Read-only backend: the store fails after the orientation line
+    let dir = target_dir().join(format!("test-edit-credentials-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("CLI_TEST_EDIT"), "abc")?;
+
+    let mut edit = local_secrets_cmd()?;
+    edit.env("CREDENTIALS_DIRECTORY", &dir)
+        .env(TEST_SECRET_ENV, "new-value")
+        .args(["--backend", "systemd-creds", "edit", "CLI_TEST_EDIT"]);
+    edit.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "CLI_TEST_EDIT is 3 characters long (fingerprint ba7816bf).",
+        ))
+        .stderr(predicate::str::contains("read-only"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}